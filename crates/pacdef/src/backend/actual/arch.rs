@@ -1,25 +1,292 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ffi::OsStr;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use alpm::Alpm;
 use alpm::PackageReason::Explicit;
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 
-use crate::cmd::run_external_command;
+use crate::backend::backend_trait::parse_versions;
+use crate::backend::root::build_base_command_with_privileges;
+use crate::cmd::{
+    run_command_for_output, run_command_for_stdout, run_external_command,
+    run_external_command_for_output,
+};
+use crate::glob::matches_any;
+use crate::history::parse_timestamp;
+use crate::path::{binary_in_path, get_xdg_cache_home};
 use crate::prelude::*;
 
+/// Whether the package databases were refreshed in this run, either before an
+/// install or by a system upgrade.
+static REFRESHED: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Arch {
     pub binary: String,
     pub aur_rm_args: Vec<String>,
+    pub extra_args: Vec<String>,
+    pub query_with_pacman: bool,
+    pub remove_orphans: bool,
+    pub orphan_recursive: bool,
+    pub prune_build_dir: bool,
+    /// Globs of files that may be overwritten if an install fails due to conflicts.
+    pub overwrite_conflicts: Vec<String>,
+    /// Whether the packages built from the same pkgbase count as one.
+    pub group_split_packages: bool,
+    /// Globs of packages that are never removed, in addition to the built-in ones.
+    pub protected_packages: Vec<String>,
+    /// The package file or URL to install each package from. Packages not listed
+    /// here are installed from the repositories.
+    pub package_files: BTreeMap<String, String>,
+    /// The optional dependencies to install as dependencies after a package, keyed
+    /// by package name.
+    pub optional_deps: BTreeMap<String, Vec<String>>,
+    /// Whether to refresh the package databases once before installing packages
+    /// from the repositories.
+    pub refresh_before_install: bool,
 }
 impl Arch {
     pub fn new(config: &Config) -> Self {
         Self {
             binary: config.aur_helper.clone(),
             aur_rm_args: config.aur_rm_args.clone(),
+            extra_args: config.extra_args_for("arch"),
+            query_with_pacman: config.query_with_pacman,
+            remove_orphans: config.arch_remove_orphans,
+            orphan_recursive: config.arch_orphan_recursive,
+            prune_build_dir: config.arch_prune_build_dir,
+            overwrite_conflicts: config.arch_overwrite_conflicts.clone(),
+            group_split_packages: config.arch_group_split_packages,
+            protected_packages: config.arch_protected_packages.clone(),
+            package_files: config.arch_package_files.clone(),
+            optional_deps: config.arch_optional_deps.clone(),
+            refresh_before_install: config.arch_refresh_before_install,
         }
     }
+
+    /// Get the packages that must never be removed: the built-in ones, the AUR
+    /// helper, the `HoldPkg` of the pacman config and the configured ones.
+    fn get_protected_packages(&self) -> Vec<String> {
+        let mut result: Vec<String> = PROTECTED_PACKAGES.map(String::from).to_vec();
+
+        if let Some(helper) = Path::new(&self.binary).file_name() {
+            result.push(helper.to_string_lossy().into_owned());
+        }
+
+        match read_to_string(PACMAN_CONFIG) {
+            Ok(content) => result.extend(parse_config_list(&content, "HoldPkg")),
+            Err(error) => log::warn!("cannot read {PACMAN_CONFIG}: {error}"),
+        }
+
+        result.extend(self.protected_packages.iter().cloned());
+        result
+    }
+
+    /// The single command that removes `packages`, recursively with
+    /// `orphan_recursive`, or else together with the direct `orphans` they leave
+    /// behind.
+    fn removal_command(&self, packages: &Packages, orphans: &Packages, noconfirm: bool) -> Command {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(&self.binary);
+
+        if self.remove_orphans && !self.orphan_recursive {
+            cmd.arg("--remove");
+        } else {
+            cmd.args(backend_info.switches_remove);
+        }
+        cmd.args(&self.aur_rm_args);
+
+        if noconfirm {
+            cmd.args(backend_info.switches_noconfirm);
+        }
+
+        for p in packages {
+            cmd.arg(format!("{p}"));
+        }
+        cmd.args(orphans.iter().map(|p| p.name.as_str()));
+
+        cmd
+    }
+
+    fn install_command(&self, packages: &Packages, noconfirm: bool, overwrite: bool) -> Command {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(&self.binary);
+
+        cmd.args(backend_info.switches_install);
+
+        if noconfirm {
+            cmd.args(backend_info.switches_noconfirm);
+        }
+
+        if overwrite {
+            for glob in &self.overwrite_conflicts {
+                cmd.arg("--overwrite").arg(glob);
+            }
+        }
+
+        cmd.args(&backend_info.extra_install_args);
+
+        for p in packages {
+            cmd.arg(format!("{p}"));
+        }
+
+        cmd
+    }
+
+    /// Refresh the package databases with `--sync --refresh`, unless they were
+    /// refreshed in this run already. Without upgrading the system, this can
+    /// result in a partial upgrade.
+    fn refresh_databases(&self) -> Result<()> {
+        if REFRESHED.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        log::warn!(
+            backend = "arch";
+            "refreshing the package databases without upgrading, which can result in a partial upgrade, consider `package sync --sysupgrade`"
+        );
+
+        let mut cmd = Command::new(&self.binary);
+        cmd.args(["--sync", "--refresh"]);
+        run_external_command(cmd).context("refreshing the package databases")?;
+
+        REFRESHED.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Install the specified packages from the repositories, after refreshing the
+    /// package databases if `refresh_before_install` is set.
+    /// If the install fails only because of files that exist in the filesystem, and
+    /// all of them match `overwrite_conflicts`, it is retried once with
+    /// `--overwrite` for each of its globs. The files that were overwritten are
+    /// logged. Any other failure is returned as is.
+    fn install_from_repos(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        if self.refresh_before_install {
+            self.refresh_databases()?;
+        }
+
+        if self.overwrite_conflicts.is_empty() {
+            return run_external_command(self.install_command(packages, noconfirm, false));
+        }
+
+        let output =
+            run_external_command_for_output(self.install_command(packages, noconfirm, false))?;
+        let Err(error) = output.ensure_success() else {
+            return Ok(());
+        };
+
+        // pacman reports the conflicts on stdout, but AUR helpers may not
+        let reported = format!("{}\n{}", output.stdout, output.stderr);
+        let conflicts = parse_file_conflicts(&reported);
+        let resolvable = !conflicts.is_empty()
+            && conflicts
+                .iter()
+                .all(|(_, file)| matches_any(&self.overwrite_conflicts, file));
+        if !resolvable {
+            return Err(error);
+        }
+
+        log::warn!(
+            backend = "arch";
+            "install failed due to file conflicts, retrying with --overwrite for {}",
+            self.overwrite_conflicts.join(", ")
+        );
+        run_external_command(self.install_command(packages, noconfirm, true))?;
+        for (package, file) in conflicts {
+            log::warn!(backend = "arch", package; "{package} overwrote {file}");
+        }
+
+        Ok(())
+    }
+
+    /// Install the packages from their package files or URLs with `--upgrade`. All
+    /// files must exist and all URLs must be reachable before anything is installed.
+    fn install_from_files(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let mut sources = vec![];
+        for package in packages {
+            let source = &self.package_files[&package.name];
+            check_package_source(source)
+                .with_context(|| format!("checking the package file of {package}"))?;
+            sources.push(source);
+        }
+
+        let mut cmd = Command::new(&self.binary);
+        cmd.arg("--upgrade");
+        if noconfirm {
+            cmd.args(self.backend_info().switches_noconfirm);
+        }
+        cmd.args(&self.extra_args);
+        cmd.args(sources);
+
+        run_external_command(cmd).context("installing package files")
+    }
+
+    /// Install the optional dependencies of the `packages` with `--asdeps`, so they
+    /// are removed again once nothing needs them. Optional dependencies that are
+    /// installed already are left alone.
+    fn install_optional_deps(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let deps: BTreeSet<&String> = packages
+            .iter()
+            .filter_map(|package| self.optional_deps.get(&package.name))
+            .flatten()
+            .collect();
+        if deps.is_empty() {
+            return Ok(());
+        }
+
+        let mut cmd = Command::new(&self.binary);
+        cmd.args(["--sync", "--asdeps", "--needed"]);
+        if noconfirm {
+            cmd.args(self.backend_info().switches_noconfirm);
+        }
+        cmd.args(&self.extra_args);
+        cmd.args(deps);
+
+        run_external_command(cmd).context("installing optional dependencies")
+    }
+}
+
+/// Make sure that the package file `source` exists, or that it is reachable if it is
+/// a URL, which pacman downloads itself.
+fn check_package_source(source: &str) -> Result<()> {
+    if !source.contains("://") {
+        ensure!(Path::new(source).is_file(), "{source} does not exist");
+        return Ok(());
+    }
+
+    let mut cmd = Command::new("curl");
+    cmd.args([
+        "--silent",
+        "--fail",
+        "--location",
+        "--head",
+        "--max-time",
+        "10",
+    ]);
+    cmd.arg(source);
+    run_command_for_stdout(cmd).with_context(|| format!("{source} is not reachable"))?;
+
+    Ok(())
+}
+
+/// The files that a failed pacman transaction reports as existing in the
+/// filesystem, as `(package, file)`. Only these conflicts can be resolved with
+/// `--overwrite`.
+fn parse_file_conflicts(output: &str) -> Vec<(&str, &str)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (package, rest) = line.split_once(": ")?;
+            let (file, _) = rest.split_once(" exists in filesystem")?;
+            Some((package.trim(), file))
+        })
+        .collect()
 }
 
 impl Backend for Arch {
@@ -30,8 +297,14 @@ impl Backend for Arch {
             switches_info: &["--query", "--info"],
             switches_install: &["--sync"],
             switches_noconfirm: &["--noconfirm"],
-            switches_remove: &["--remove", "--recursive"],
+            // `--recursive` also removes dependencies that are not needed anymore
+            switches_remove: if self.remove_orphans {
+                &["--remove", "--recursive"]
+            } else {
+                &["--remove"]
+            },
             switches_make_dependency: Some(&["--database", "--asdeps"]),
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
@@ -50,43 +323,565 @@ impl Backend for Arch {
         Ok(result)
     }
 
-    /// Install the specified packages.
+    /// AUR helpers let the user review and build packages, pacman itself never
+    /// prompts with `--noconfirm`.
+    fn is_interactive(&self) -> bool {
+        Path::new(&self.binary).file_name() != Some(OsStr::new("pacman"))
+    }
+
+    /// Install the packages from the repositories first, then the packages with a
+    /// package file or URL, and finally their optional dependencies.
     fn install_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let (from_files, from_repos): (Packages, Packages) = packages
+            .iter()
+            .cloned()
+            .partition(|package| self.package_files.contains_key(&package.name));
+
+        if !from_repos.is_empty() {
+            self.install_from_repos(&from_repos, noconfirm)?;
+        }
+        if !from_files.is_empty() {
+            self.install_from_files(&from_files, noconfirm)?;
+        }
+
+        self.install_optional_deps(packages, noconfirm)
+    }
+
+    /// Remove the specified packages together with the orphans they leave behind,
+    /// in a single transaction. Without `orphan_recursive`, the direct
+    /// dependencies that become orphans are named explicitly instead of passing
+    /// `--recursive`, so their own dependencies are kept.
+    ///
+    /// Protected packages are never removed, no matter how they ended up in
+    /// `packages`. With `orphan_recursive`, the removal is refused if pacman would
+    /// also remove a protected package as orphan.
+    fn remove_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let protected = self.get_protected_packages();
+        let packages = &without_protected(packages, &protected);
+        if packages.is_empty() {
+            return Ok(());
+        }
+
+        if self.remove_orphans && self.orphan_recursive {
+            let cascade = self
+                .get_removal_cascade(packages)
+                .context("getting orphans")?;
+            let protected_orphans: Vec<_> = cascade
+                .iter()
+                .filter(|package| matches_any(&protected, &package.name))
+                .map(|package| package.name.as_str())
+                .collect();
+
+            ensure!(
+                protected_orphans.is_empty(),
+                "refusing to remove {}, as this would also remove the protected packages {}",
+                packages
+                    .iter()
+                    .map(|package| package.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                protected_orphans.join(", ")
+            );
+        }
+
+        let orphans = if self.remove_orphans && !self.orphan_recursive {
+            let orphans = self
+                .get_removal_cascade(packages)
+                .context("getting direct orphans")?;
+            without_protected(&orphans, &protected)
+        } else {
+            Packages::new()
+        };
+
+        run_external_command(self.removal_command(packages, &orphans, noconfirm))
+    }
+
+    /// Show information from package manager for package. If `query_with_pacman` is
+    /// set, `pacman` is used instead of the AUR helper, which is faster to start.
+    fn show_package_info(&self, package: &Package) -> Result<()> {
         let backend_info = self.backend_info();
 
+        let binary = if self.query_with_pacman {
+            "pacman"
+        } else {
+            &self.binary
+        };
+
+        let mut cmd = Command::new(binary);
+        cmd.args(backend_info.switches_info);
+        cmd.arg(format!("{package}"));
+
+        run_external_command(cmd)
+    }
+
+    /// Let pacman print what `remove_packages` would remove, without removing it.
+    /// Without `orphan_recursive`, only the direct dependencies of the packages are
+    /// kept from that.
+    fn get_removal_cascade(&self, packages: &Packages) -> Result<Packages> {
+        if !self.remove_orphans {
+            return Ok(Packages::new());
+        }
+
+        let mut cmd = Command::new("pacman");
+        cmd.args(self.backend_info().switches_remove);
+        cmd.args(["--print", "--print-format", "%n"]);
+        cmd.args(packages.iter().map(|p| p.name.as_str()));
+
+        let output = run_command_for_stdout(cmd).context("simulating removal")?;
+
+        let mut cascade: Packages = output
+            .lines()
+            .map(Package::from)
+            .filter(|p| !packages.iter().any(|removed| removed.name == p.name))
+            .collect();
+
+        if !self.orphan_recursive {
+            let direct =
+                get_direct_dependencies_from_alpm(packages).context("getting dependencies")?;
+            cascade.retain(|p| direct.contains(&p.name));
+        }
+
+        Ok(cascade)
+    }
+
+    /// A package that was renamed upstream is replaced by the package with the new
+    /// name on upgrade, which lists the old name in its `replaces` or `provides`.
+    fn get_replacements(&self, packages: &Packages) -> Result<BTreeMap<Package, Package>> {
+        let db = get_db_handle().context("getting DB handle")?;
+        let localdb = db.localdb();
+
+        let not_installed: Packages = packages
+            .iter()
+            .filter(|package| localdb.pkg(package.name.as_str()).is_err())
+            .cloned()
+            .collect();
+        if not_installed.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        let installed = localdb.pkgs().iter().map(|pkg| {
+            let replaced = pkg
+                .replaces()
+                .iter()
+                .chain(pkg.provides().iter())
+                .map(|dep| dep.name().to_string())
+                .collect();
+            (pkg.name().to_string(), replaced)
+        });
+
+        Ok(find_replacements(&not_installed, installed))
+    }
+
+    /// With `group_split_packages`, a managed package covers the installed packages
+    /// with the same pkgbase, like `linux-headers` for `linux`. A managed package
+    /// that is not installed covers the packages whose pkgbase it names, but is still
+    /// missing, as the pkgbase may also be the name of a package.
+    fn get_split_siblings(&self, packages: &Packages) -> Result<BTreeMap<Package, Packages>> {
+        if !self.group_split_packages {
+            return Ok(BTreeMap::new());
+        }
+
+        let db = get_db_handle().context("getting DB handle")?;
+        let installed = db.localdb().pkgs().iter().map(|pkg| {
+            let base = pkg.base().map(str::to_string);
+            (pkg.name().to_string(), base)
+        });
+
+        Ok(find_split_siblings(packages, installed))
+    }
+
+    fn list_orphans(&self) -> Result<Packages> {
+        let mut cmd = Command::new("pacman");
+        cmd.args(["--query", "--deps", "--unrequired", "--quiet"]);
+
+        let output = run_command_for_output(cmd).context("listing orphans")?;
+
+        // pacman exits with 1 without any output if there are no orphans
+        let no_orphans = output.code == Some(1)
+            && output.stdout.trim().is_empty()
+            && output.stderr.trim().is_empty();
+        if no_orphans {
+            return Ok(Packages::new());
+        }
+        output.ensure_success()?;
+
+        Ok(output.stdout.lines().map(Package::from).collect())
+    }
+
+    /// Run a full system upgrade. pacman itself skips the packages in `IgnorePkg` of
+    /// the pacman config, and the `held` packages are passed to `--ignore`.
+    fn upgrade_system(&self, noconfirm: bool, held: &Packages) -> Result<()> {
+        match read_to_string(PACMAN_CONFIG) {
+            Ok(content) => {
+                let ignored = parse_config_list(&content, "IgnorePkg");
+                if !ignored.is_empty() {
+                    log::info!("IgnorePkg holds back {}", ignored.join(", "));
+                }
+            }
+            Err(error) => log::warn!("cannot read {PACMAN_CONFIG}: {error}"),
+        }
+
         let mut cmd = Command::new(&self.binary);
+        cmd.args(["--sync", "--refresh", "--sysupgrade"]);
 
-        cmd.args(backend_info.switches_install);
+        if noconfirm {
+            cmd.args(self.backend_info().switches_noconfirm);
+        }
+
+        if !held.is_empty() {
+            let names: Vec<&str> = held.iter().map(|p| p.name.as_str()).collect();
+            log::info!("holding back locked packages {}", names.join(", "));
+            cmd.args(["--ignore", &names.join(",")]);
+        }
+
+        run_external_command(cmd).context("upgrading system")?;
 
+        REFRESHED.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// The packages matching `IgnorePkg` of the pacman config, or in a group of
+    /// `IgnoreGroup`.
+    fn get_ignored_packages(&self, packages: &Packages) -> Result<Packages> {
+        if packages.is_empty() {
+            return Ok(Packages::new());
+        }
+
+        match read_to_string(PACMAN_CONFIG) {
+            Ok(content) => Ok(ignored_by_config(&content, packages)),
+            Err(error) => {
+                log::warn!("cannot read {PACMAN_CONFIG}: {error}");
+                Ok(Packages::new())
+            }
+        }
+    }
+
+    fn supports_download(&self) -> bool {
+        true
+    }
+
+    /// Download the packages from the repositories with pacman. AUR packages cannot
+    /// be downloaded this way and are skipped with a warning.
+    fn download_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let mut cmd = Command::new("pacman");
+        cmd.args(["--sync", "--list", "--quiet"]);
+        let output = run_command_for_stdout(cmd).context("listing repository packages")?;
+        let in_repos: HashSet<&str> = output.lines().collect();
+
+        let (available, aur): (Vec<_>, Vec<_>) = packages
+            .iter()
+            .partition(|package| in_repos.contains(package.name.as_str()));
+
+        if !aur.is_empty() {
+            let names: Vec<_> = aur.iter().map(ToString::to_string).collect();
+            log::warn!(
+                backend = "arch";
+                "not in the repositories, cannot be prefetched: {}",
+                names.join(", ")
+            );
+        }
+
+        if available.is_empty() {
+            return Ok(());
+        }
+
+        let mut cmd = build_base_command_with_privileges("pacman");
+        cmd.args(["--sync", "--downloadonly", "--needed"]);
         if noconfirm {
-            cmd.args(backend_info.switches_noconfirm);
+            cmd.arg("--noconfirm");
         }
+        cmd.args(available.iter().map(ToString::to_string));
 
-        for p in packages {
-            cmd.arg(format!("{p}"));
+        run_external_command(cmd).context("downloading packages")
+    }
+
+    fn supports_reinstall(&self) -> bool {
+        true
+    }
+
+    /// Packages are installed without `--needed`, so installing them reinstalls
+    /// the ones that are installed already.
+    fn reinstall_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        self.install_packages(packages, noconfirm)
+    }
+
+    /// Show the tree from `pactree`. If it is not installed, the tree is built from
+    /// the local database instead, without resolving virtual dependencies.
+    fn dependency_tree(&self, package: &Package) -> Result<String> {
+        if binary_in_path("pactree").unwrap_or_default() {
+            let mut cmd = Command::new("pactree");
+            cmd.arg(&package.name);
+            return run_command_for_stdout(cmd);
         }
 
-        run_external_command(cmd)
+        log::warn!("pactree is not installed, install pacman-contrib for the full tree");
+
+        let db = get_db_handle().context("getting DB handle")?;
+        let db = db.localdb();
+        db.pkg(package.name.as_str())
+            .with_context(|| format!("{package} is not installed"))?;
+
+        Ok(render_tree(&package.name, |name| {
+            let package = db.pkg(name).ok()?;
+            Some(
+                package
+                    .depends()
+                    .iter()
+                    .map(|dep| dep.name().to_string())
+                    .collect(),
+            )
+        }))
     }
 
-    /// Remove the specified packages.
-    fn remove_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
-        let backend_info = self.backend_info();
+    /// Clean the pacman package cache, and the cache of the AUR helper if it is
+    /// `paru` or `yay`. With `prune_build_dir`, the build directory of the helper is
+    /// deleted as well.
+    fn clean_cache(&self, noconfirm: bool) -> Result<()> {
+        let mut cmd = build_base_command_with_privileges("pacman");
+        cmd.args(["--sync", "--clean"]);
+        if noconfirm {
+            cmd.arg("--noconfirm");
+        }
+        run_external_command(cmd).context("cleaning pacman cache")?;
+
+        let Some(helper) = AurHelper::detect(&self.binary) else {
+            return Ok(());
+        };
 
         let mut cmd = Command::new(&self.binary);
+        cmd.args(helper.clean_switches());
+        if noconfirm {
+            cmd.arg("--noconfirm");
+        }
+        run_external_command(cmd).with_context(|| format!("cleaning {} cache", self.binary))?;
 
-        cmd.args(backend_info.switches_remove);
-        cmd.args(&self.aur_rm_args);
+        if self.prune_build_dir {
+            let build_dir = helper.build_dir().context("getting build dir")?;
+            if build_dir.exists() {
+                let mut cmd = Command::new("rm");
+                cmd.arg("--recursive").arg("--force").arg(&build_dir);
+                run_external_command(cmd).context("pruning build dir")?;
+            }
+        }
 
-        if noconfirm {
-            cmd.args(backend_info.switches_noconfirm);
+        Ok(())
+    }
+
+    /// Let pacman check all files of all packages. pacman reports each discrepancy as
+    /// a warning of the form `<package>: <path> (<problem>)`.
+    fn verify_packages(&self) -> Result<Vec<VerifyIssue>> {
+        let mut cmd = Command::new("pacman");
+        cmd.args(["--query", "--check", "--check"]);
+
+        let output = run_command_for_output(cmd).context("checking package files")?;
+        let issues: Vec<_> = output
+            .stderr
+            .lines()
+            .filter_map(parse_pacman_check_line)
+            .collect();
+
+        // pacman exits with an error if any package has issues, which is only a
+        // failure if it did not report any
+        if issues.is_empty() {
+            output
+                .ensure_success()
+                .with_context(|| output.stderr.trim().to_string())?;
         }
 
-        for p in packages {
-            cmd.arg(format!("{p}"));
+        Ok(issues)
+    }
+
+    /// Read the changes from the pacman log.
+    fn get_history(&self, since: i64) -> Result<Vec<HistoryEntry>> {
+        let log = read_to_string(PACMAN_LOG).context("reading pacman log")?;
+
+        Ok(log
+            .lines()
+            .filter_map(parse_pacman_log_line)
+            .filter(|(time, _)| *time >= since)
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+
+    /// Get the versions from `pacman`. The repositories only carry the latest version
+    /// of a package, so versions can be recorded, but not pinned.
+    fn get_installed_versions(&self) -> Result<Versions> {
+        let mut cmd = Command::new("pacman");
+        cmd.arg("--query");
+
+        let output = run_command_for_stdout(cmd).context("querying installed versions")?;
+        Ok(parse_versions(&output))
+    }
+}
+
+const PACMAN_LOG: &str = "/var/log/pacman.log";
+
+/// Parse a line of the pacman log like
+/// `[2024-04-13T10:15:32+0200] [ALPM] installed ripgrep (14.1.0-1)`. Returns `None`
+/// for all lines that do not describe an installed or removed package.
+fn parse_pacman_log_line(line: &str) -> Option<(i64, HistoryEntry)> {
+    let (timestamp, rest) = line.strip_prefix('[')?.split_once("] [ALPM] ")?;
+    let (action, rest) = rest.split_once(' ')?;
+    let (name, _) = rest.split_once(' ')?;
+
+    let action = match action {
+        "installed" => HistoryAction::Installed,
+        "removed" => HistoryAction::Removed,
+        _ => return None,
+    };
+
+    let entry = HistoryEntry {
+        package: Package::from(name),
+        action,
+        timestamp: timestamp.to_string(),
+    };
+
+    Some((parse_timestamp(timestamp)?, entry))
+}
+
+fn parse_pacman_check_line(line: &str) -> Option<VerifyIssue> {
+    let line = line.strip_prefix("warning: ").unwrap_or(line);
+    let (package, description) = line.split_once(": ")?;
+
+    if !description.starts_with('/') {
+        return None;
+    }
+
+    Some(VerifyIssue {
+        package: Package::from(package),
+        description: description.to_string(),
+    })
+}
+
+const PACMAN_CONFIG: &str = "/etc/pacman.conf";
+
+/// Draw the dependency tree of `root` like `pactree`, with the dependencies of each
+/// package from `depends`. Packages that appeared before are not expanded again, and
+/// packages without an entry in `depends` are shown without dependencies.
+fn render_tree(root: &str, depends: impl Fn(&str) -> Option<Vec<String>>) -> String {
+    fn render(
+        name: &str,
+        prefix: &str,
+        depends: &dyn Fn(&str) -> Option<Vec<String>>,
+        seen: &mut HashSet<String>,
+        output: &mut String,
+    ) {
+        if !seen.insert(name.to_string()) {
+            return;
         }
 
-        run_external_command(cmd)
+        let children = depends(name).unwrap_or_default();
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, indent) = if last {
+                ("└─", "  ")
+            } else {
+                ("├─", "│ ")
+            };
+            output.push_str(&format!("{prefix}{branch}{child}\n"));
+            render(child, &format!("{prefix}{indent}"), depends, seen, output);
+        }
+    }
+
+    let mut output = format!("{root}\n");
+    render(root, "", &depends, &mut HashSet::new(), &mut output);
+    output
+}
+
+/// Packages without which the system cannot be repaired with pacman, so they are
+/// never removed.
+const PROTECTED_PACKAGES: [&str; 4] = ["pacman", "glibc", "systemd", "linux"];
+
+/// Get `packages` without the ones that match a glob in `protected`, and log an
+/// error for each of those.
+fn without_protected(packages: &Packages, protected: &[String]) -> Packages {
+    let (skipped, result): (Packages, Packages) = packages
+        .iter()
+        .cloned()
+        .partition(|package| matches_any(protected, &package.name));
+
+    for package in skipped {
+        log::error!(
+            backend = "arch", package:% = package;
+            "refusing to remove protected package {package}, skipping it"
+        );
+    }
+
+    result
+}
+
+/// Get the packages of all `<key> = <package>...` lines in the pacman config, e.g.
+/// for `IgnorePkg`. Included files are not followed.
+fn parse_config_list(config: &str, key: &str) -> Vec<String> {
+    config
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            (name.trim() == key).then_some(value)
+        })
+        .flat_map(str::split_whitespace)
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Get the packages among `packages` that `IgnorePkg` of the pacman `config`
+/// matches, which may be globs, or that belong to a group of `IgnoreGroup`.
+fn ignored_by_config(config: &str, packages: &Packages) -> Packages {
+    let ignored = parse_config_list(config, "IgnorePkg");
+    let groups = parse_config_list(config, "IgnoreGroup");
+
+    let mut members = HashSet::new();
+    if !groups.is_empty() {
+        let mut cmd = Command::new("pacman");
+        cmd.args(["--sync", "--groups", "--quiet"]).args(&groups);
+        match run_command_for_stdout(cmd) {
+            Ok(output) => members.extend(output.lines().map(ToString::to_string)),
+            Err(error) => log::warn!(
+                backend = "arch";
+                "could not get the packages of IgnoreGroup {}: {error:#}",
+                groups.join(", ")
+            ),
+        }
+    }
+
+    packages
+        .iter()
+        .filter(|package| matches_any(&ignored, &package.name) || members.contains(&package.name))
+        .cloned()
+        .collect()
+}
+
+/// AUR helpers with a cache of their own.
+enum AurHelper {
+    Paru,
+    Yay,
+}
+
+impl AurHelper {
+    /// Detect the helper from the configured `aur_helper`, which may be a path.
+    fn detect(binary: &str) -> Option<Self> {
+        match Path::new(binary).file_name()?.to_str()? {
+            "paru" => Some(Self::Paru),
+            "yay" => Some(Self::Yay),
+            _ => None,
+        }
+    }
+
+    fn clean_switches(&self) -> &'static [&'static str] {
+        match self {
+            Self::Paru => &["-Sccd"],
+            Self::Yay => &["-Sc"],
+        }
+    }
+
+    /// The directory the helper clones and builds AUR packages in.
+    fn build_dir(&self) -> Result<PathBuf> {
+        let mut dir = get_xdg_cache_home()?;
+        match self {
+            Self::Paru => dir.push("paru/clone"),
+            Self::Yay => dir.push("yay"),
+        }
+        Ok(dir)
     }
 }
 
@@ -113,6 +908,79 @@ fn get_explicitly_installed_packages_from_alpm() -> Result<HashSet<String>> {
     Ok(result)
 }
 
+/// Get the names of the packages that the installed `packages` depend on directly.
+/// Dependencies on virtual packages are not resolved.
+fn get_direct_dependencies_from_alpm(packages: &Packages) -> Result<HashSet<String>> {
+    let db = get_db_handle().context("getting DB handle")?;
+    let localdb = db.localdb();
+
+    let mut result = HashSet::new();
+    for package in packages {
+        let Ok(installed) = localdb.pkg(package.name.as_str()) else {
+            continue;
+        };
+        result.extend(installed.depends().iter().map(|dep| dep.name().to_string()));
+    }
+
+    Ok(result)
+}
+
+/// Find the replacement of each of `packages` among the `installed` packages, which
+/// are given by name with the names they replace or provide.
+fn find_replacements(
+    packages: &Packages,
+    installed: impl IntoIterator<Item = (String, Vec<String>)>,
+) -> BTreeMap<Package, Package> {
+    let mut result = BTreeMap::new();
+
+    for (name, replaced) in installed {
+        for package in packages {
+            if replaced.contains(&package.name) {
+                result.insert(package.clone(), Package::from(name.as_str()));
+            }
+        }
+    }
+
+    result
+}
+
+/// Find the siblings of each of `packages` among the `installed` packages, which are
+/// given by name with their pkgbase. Packages without a pkgbase are their own base.
+fn find_split_siblings(
+    packages: &Packages,
+    installed: impl IntoIterator<Item = (String, Option<String>)>,
+) -> BTreeMap<Package, Packages> {
+    let mut bases: BTreeMap<String, String> = BTreeMap::new();
+    let mut members: BTreeMap<String, Packages> = BTreeMap::new();
+    for (name, base) in installed {
+        let base = base.unwrap_or_else(|| name.clone());
+        members
+            .entry(base.clone())
+            .or_default()
+            .insert(Package::from(name.as_str()));
+        bases.insert(name, base);
+    }
+
+    let mut result = BTreeMap::new();
+    for package in packages {
+        let base = bases.get(&package.name).unwrap_or(&package.name);
+        let Some(siblings) = members.get(base) else {
+            continue;
+        };
+
+        let siblings: Packages = siblings
+            .iter()
+            .filter(|sibling| *sibling != package)
+            .cloned()
+            .collect();
+        if !siblings.is_empty() {
+            result.insert(package.clone(), siblings);
+        }
+    }
+
+    result
+}
+
 fn convert_to_pacdef_packages(packages: HashSet<String>) -> Packages {
     packages.into_iter().map(Package::from).collect()
 }
@@ -120,3 +988,434 @@ fn convert_to_pacdef_packages(packages: HashSet<String>) -> Packages {
 fn get_db_handle() -> Result<Alpm> {
     Alpm::new("/", "/var/lib/pacman").context("connecting to DB using expected default values")
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, CommandOutput, FakeRunner};
+
+    #[test]
+    fn pacman_log() {
+        let (time, entry) =
+            parse_pacman_log_line("[2024-04-13T10:15:32+0200] [ALPM] installed ripgrep (14.1.0-1)")
+                .expect("line describes an installed package");
+        assert_eq!(time, 1_712_996_132);
+        assert_eq!(entry.package, Package::from("ripgrep"));
+        assert_eq!(entry.action, HistoryAction::Installed);
+
+        assert!(parse_pacman_log_line(
+            "[2024-04-13T10:15:32+0200] [ALPM] upgraded bash (5.2.026-1 -> 5.2.026-2)"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn pacman_check_output() {
+        let issue =
+            parse_pacman_check_line("warning: bash: /etc/bash.bashrc (Modification time mismatch)")
+                .expect("line describes an issue");
+        assert_eq!(issue.package, Package::from("bash"));
+        assert_eq!(
+            issue.description,
+            "/etc/bash.bashrc (Modification time mismatch)"
+        );
+
+        assert!(parse_pacman_check_line("bash: 45 total files, 1 altered file").is_none());
+    }
+
+    #[test]
+    fn file_conflicts() {
+        let output = "error: failed to commit transaction (conflicting files)\nhicolor-icon-theme: /usr/share/icons/hicolor/index.theme exists in filesystem (owned by foo)\nbar: /usr/bin/bar exists in filesystem\nErrors occurred, no packages were upgraded.\n";
+        assert_eq!(
+            parse_file_conflicts(output),
+            [
+                ("hicolor-icon-theme", "/usr/share/icons/hicolor/index.theme"),
+                ("bar", "/usr/bin/bar")
+            ]
+        );
+    }
+
+    #[test]
+    fn overwrite_only_on_file_conflicts() {
+        let arch = Arch {
+            overwrite_conflicts: vec!["/usr/share/icons/*".into()],
+            ..Arch::new(&Config::default())
+        };
+        let packages: Packages = [Package::from("hicolor-icon-theme")].into();
+        let conflict = CommandOutput {
+            code: Some(1),
+            stdout:
+                "hicolor-icon-theme: /usr/share/icons/hicolor/index.theme exists in filesystem\n"
+                    .into(),
+            ..CommandOutput::default()
+        };
+
+        let runner = Rc::new(FakeRunner {
+            outputs: RefCell::new([conflict.clone()].into()),
+            ..FakeRunner::default()
+        });
+        with_runner(runner.clone(), || arch.install_from_repos(&packages, true))
+            .expect("conflict is resolved");
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "paru --sync --noconfirm hicolor-icon-theme",
+                "paru --sync --noconfirm --overwrite '/usr/share/icons/*' hicolor-icon-theme"
+            ]
+        );
+
+        let unmatched = CommandOutput {
+            stdout: format!(
+                "{}bar: /usr/bin/bar exists in filesystem\n",
+                conflict.stdout
+            ),
+            ..conflict
+        };
+        let other = CommandOutput {
+            code: Some(1),
+            stdout: "error: target not found: hicolor-icon-theme\n".into(),
+            ..CommandOutput::default()
+        };
+        for output in [unmatched, other] {
+            let runner = Rc::new(FakeRunner {
+                outputs: RefCell::new([output].into()),
+                ..FakeRunner::default()
+            });
+            with_runner(runner.clone(), || arch.install_from_repos(&packages, true))
+                .expect_err("install failed");
+            assert_eq!(runner.commands.borrow().len(), 1);
+        }
+    }
+
+    #[test]
+    fn renamed_packages() {
+        let packages: Packages = [Package::from("exa"), Package::from("ripgrep")].into();
+        let installed = [
+            ("eza".to_string(), vec!["exa".to_string()]),
+            ("bash".to_string(), vec!["sh".to_string()]),
+        ];
+
+        assert_eq!(
+            find_replacements(&packages, installed),
+            [(Package::from("exa"), Package::from("eza"))].into()
+        );
+    }
+
+    #[test]
+    fn split_siblings() {
+        let installed = [
+            ("linux".to_string(), Some("linux".to_string())),
+            ("linux-headers".to_string(), Some("linux".to_string())),
+            ("gcc-libs".to_string(), Some("gcc".to_string())),
+            ("ripgrep".to_string(), None),
+        ];
+        let packages: Packages = [
+            Package::from("linux"),
+            Package::from("gcc"),
+            Package::from("ripgrep"),
+        ]
+        .into();
+
+        assert_eq!(
+            find_split_siblings(&packages, installed),
+            [
+                (Package::from("gcc"), [Package::from("gcc-libs")].into()),
+                (
+                    Package::from("linux"),
+                    [Package::from("linux-headers")].into()
+                ),
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn ignored_packages() {
+        let config = "[options]\nIgnorePkg   = linux linux-headers\n#IgnorePkg = firefox\nIgnorePkg=nvidia\n";
+        assert_eq!(
+            parse_config_list(config, "IgnorePkg"),
+            ["linux", "linux-headers", "nvidia"]
+        );
+    }
+
+    #[test]
+    fn ignored_by_pacman_config() {
+        let config = "[options]\nIgnorePkg = linux*\nIgnoreGroup = gnome\n";
+        let packages: Packages = [
+            Package::from("linux-lts"),
+            Package::from("nautilus"),
+            Package::from("vim"),
+        ]
+        .into();
+
+        let runner = Rc::new(FakeRunner {
+            stdout: "gnome-shell\nnautilus\n".into(),
+            ..FakeRunner::default()
+        });
+        let ignored = with_runner(runner.clone(), || ignored_by_config(config, &packages));
+
+        assert_eq!(
+            ignored,
+            [Package::from("linux-lts"), Package::from("nautilus")].into()
+        );
+        assert_eq!(
+            *runner.commands.borrow(),
+            ["pacman --sync --groups --quiet gnome"]
+        );
+    }
+
+    #[test]
+    fn dependency_tree() {
+        let depends = |name: &str| {
+            let deps: &[&str] = match name {
+                "git" => &["curl", "perl"],
+                "curl" => &["openssl", "zlib"],
+                "perl" => &["zlib"],
+                "zlib" => &[],
+                _ => return None,
+            };
+            Some(deps.iter().map(ToString::to_string).collect())
+        };
+
+        assert_eq!(
+            render_tree("git", depends),
+            "git\n├─curl\n│ ├─openssl\n│ └─zlib\n└─perl\n  └─zlib\n"
+        );
+    }
+
+    #[test]
+    fn install_from_package_files() {
+        let url = "https://example.org/foo-1.0-1-x86_64.pkg.tar.zst";
+        let mut arch = Arch {
+            binary: "paru".into(),
+            aur_rm_args: vec![],
+            extra_args: vec![],
+            query_with_pacman: false,
+            remove_orphans: true,
+            orphan_recursive: true,
+            prune_build_dir: false,
+            overwrite_conflicts: vec![],
+            group_split_packages: false,
+            protected_packages: vec![],
+            package_files: [("foo".into(), url.into())].into(),
+            optional_deps: BTreeMap::new(),
+            refresh_before_install: false,
+        };
+        let packages: Packages = [Package::from("foo"), Package::from("vim")].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || arch.install_packages(&packages, true))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "paru --sync --noconfirm vim".to_string(),
+                format!("curl --silent --fail --location --head --max-time 10 {url}"),
+                format!("paru --upgrade --noconfirm {url}"),
+            ]
+        );
+
+        arch.package_files = [("foo".into(), "/nonexistent/foo.pkg.tar.zst".into())].into();
+        let error = arch
+            .install_packages(&[Package::from("foo")].into(), true)
+            .expect_err("package file does not exist");
+        assert_eq!(
+            format!("{error:#}"),
+            "checking the package file of foo: /nonexistent/foo.pkg.tar.zst does not exist"
+        );
+    }
+
+    #[test]
+    fn refresh_once_before_install() {
+        let arch = Arch {
+            refresh_before_install: true,
+            ..Arch::new(&Config::default())
+        };
+        REFRESHED.store(false, Ordering::Relaxed);
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || {
+            arch.install_packages(&[Package::from("vim")].into(), true)?;
+            arch.install_packages(&[Package::from("git")].into(), true)
+        })
+        .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "paru --sync --refresh",
+                "paru --sync --noconfirm vim",
+                "paru --sync --noconfirm git"
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_deps_installed_as_dependencies() {
+        let arch = Arch {
+            optional_deps: [
+                ("mpv".into(), vec!["yt-dlp".into()]),
+                ("vlc".into(), vec!["yt-dlp".into(), "libdvdcss".into()]),
+            ]
+            .into(),
+            ..Arch::new(&Config::default())
+        };
+        let packages: Packages = [Package::from("mpv"), Package::from("vlc")].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || {
+            arch.install_packages(&packages, true)?;
+            arch.install_packages(&[Package::from("git")].into(), true)
+        })
+        .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "paru --sync --noconfirm mpv vlc",
+                "paru --sync --asdeps --needed --noconfirm libdvdcss yt-dlp",
+                "paru --sync --noconfirm git"
+            ]
+        );
+    }
+
+    #[test]
+    fn orphans_removed_in_one_transaction() {
+        let packages: Packages = [Package::from("vim"), Package::from("gvim")].into();
+        let orphans: Packages = [Package::from("vim-runtime")].into();
+
+        let recursive = Arch::new(&Config::default());
+        let direct = Arch {
+            orphan_recursive: false,
+            ..Arch::new(&Config::default())
+        };
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || {
+            run_external_command(recursive.removal_command(&packages, &Packages::new(), true))?;
+            run_external_command(direct.removal_command(&packages, &orphans, true))
+        })
+        .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "paru --remove --recursive --noconfirm gvim vim",
+                "paru --remove --noconfirm gvim vim vim-runtime"
+            ]
+        );
+    }
+
+    #[test]
+    fn orphans_through_the_runner() {
+        let arch = Arch::new(&Config::default());
+        let output = |code, stdout: &str, stderr: &str| CommandOutput {
+            success: code == 0,
+            code: Some(code),
+            stdout: stdout.into(),
+            stderr: stderr.into(),
+            ..CommandOutput::default()
+        };
+        let runner = Rc::new(FakeRunner {
+            outputs: RefCell::new(
+                [
+                    output(0, "vim-runtime\n", ""),
+                    output(1, "", ""),
+                    output(1, "", "error: failed to initialize alpm library\n"),
+                ]
+                .into(),
+            ),
+            ..FakeRunner::default()
+        });
+
+        with_runner(runner.clone(), || {
+            assert_eq!(
+                arch.list_orphans().expect("orphans found"),
+                [Package::from("vim-runtime")].into()
+            );
+            assert!(arch.list_orphans().expect("no orphans").is_empty());
+            assert!(arch.list_orphans().is_err());
+        });
+
+        assert_eq!(
+            runner.commands.borrow()[0],
+            "pacman --query --deps --unrequired --quiet"
+        );
+    }
+
+    #[test]
+    fn verify_through_the_runner() {
+        let arch = Arch::new(&Config::default());
+        let output = |stderr: &str| CommandOutput {
+            code: Some(1),
+            stderr: stderr.into(),
+            ..CommandOutput::default()
+        };
+        let runner = Rc::new(FakeRunner {
+            outputs: RefCell::new(
+                [
+                    output("warning: bash: /etc/bash.bashrc (Modification time mismatch)\n"),
+                    output("error: failed to initialize alpm library\n"),
+                ]
+                .into(),
+            ),
+            ..FakeRunner::default()
+        });
+
+        with_runner(runner, || {
+            let issues = arch.verify_packages().expect("issues found");
+            assert_eq!(issues.len(), 1);
+            assert!(arch.verify_packages().is_err());
+        });
+    }
+
+    #[test]
+    fn protected_packages_are_kept() {
+        let packages: Packages = [
+            Package::from("glibc"),
+            Package::from("linux-lts"),
+            Package::from("paru"),
+            Package::from("vim"),
+        ]
+        .into();
+        let protected = ["glibc", "linux*", "paru"].map(String::from);
+
+        assert_eq!(
+            without_protected(&packages, &protected),
+            [Package::from("vim")].into()
+        );
+    }
+
+    #[test]
+    fn protected_orphans_are_not_removed_recursively() {
+        let arch = Arch::new(&Config::default());
+        let packages: Packages = [Package::from("vim")].into();
+
+        let runner = Rc::new(FakeRunner {
+            stdout: "vim\nvim-runtime\nglibc\n".into(),
+            ..FakeRunner::default()
+        });
+        let error = with_runner(runner.clone(), || arch.remove_packages(&packages, true))
+            .expect_err("glibc is protected");
+        assert!(error.to_string().contains("protected packages glibc"));
+        assert_eq!(
+            *runner.commands.borrow(),
+            ["pacman --remove --recursive --print --print-format %n vim"]
+        );
+
+        let runner = Rc::new(FakeRunner {
+            stdout: "vim\nvim-runtime\n".into(),
+            ..FakeRunner::default()
+        });
+        with_runner(runner.clone(), || arch.remove_packages(&packages, true))
+            .expect("fake runner does not fail");
+        assert_eq!(
+            runner.commands.borrow()[1],
+            "paru --remove --recursive --noconfirm vim"
+        );
+    }
+}
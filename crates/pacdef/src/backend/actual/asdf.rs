@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::prelude::*;
+
+/// Runtime versions managed by asdf. Packages are written as `<plugin>/<version>`,
+/// e.g. `nodejs/20.5.0`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Asdf {
+    /// Whether to set a version as global version after installing it, keyed by
+    /// package.
+    pub global: BTreeMap<String, bool>,
+    pub extra_args: Vec<String>,
+}
+impl Asdf {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            global: config.asdf_global.clone(),
+            extra_args: config.extra_args_for("asdf"),
+        }
+    }
+
+    fn run_asdf(&self, args: &[&str]) -> Result<()> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.args(args);
+        run_external_command(cmd)
+    }
+
+    /// Whether `package` is set as global version after installing it. Sidecar files
+    /// and the config name it as `<plugin>/<version>`, inline options by its version.
+    fn sets_global(&self, package: &Package) -> bool {
+        [package.to_string(), package.name.clone()]
+            .iter()
+            .find_map(|key| self.global.get(key))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn installed_plugins(&self) -> Result<Vec<String>> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.args(["plugin", "list"]);
+
+        let output = run_command_for_stdout(cmd).context("listing asdf plugins")?;
+        Ok(output.lines().map(|line| line.trim().to_string()).collect())
+    }
+}
+
+impl Backend for Asdf {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "asdf".to_string(),
+            section: "asdf",
+            switches_info: &["list", "all"],
+            switches_install: &["install"],
+            switches_noconfirm: &[],
+            switches_remove: &["uninstall"],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.arg("list");
+
+        let output = run_command_for_stdout(cmd).context("listing asdf versions")?;
+        Ok(parse_asdf_list(&output))
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        self.get_all_installed_packages()
+            .context("getting all installed packages")
+    }
+
+    /// Add missing plugins, then install every version on its own, since asdf only
+    /// takes a single version per invocation.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+        let plugins = self.installed_plugins()?;
+
+        for package in packages {
+            let plugin = package
+                .repo
+                .as_deref()
+                .with_context(|| format!("{package} must be written as <plugin>/<version>"))?;
+
+            if !plugins.iter().any(|p| p == plugin) {
+                self.run_asdf(&["plugin", "add", plugin])
+                    .with_context(|| format!("adding asdf plugin {plugin}"))?;
+            }
+
+            let mut args = backend_info.switches_install.to_vec();
+            args.extend([plugin, package.name.as_str()]);
+            args.extend(self.extra_args.iter().map(String::as_str));
+            self.run_asdf(&args)
+                .with_context(|| format!("installing {package}"))?;
+
+            if self.sets_global(package) {
+                self.run_asdf(&["global", plugin, &package.name])
+                    .with_context(|| format!("setting {package} as global version"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        for package in packages {
+            let plugin = package
+                .repo
+                .as_deref()
+                .with_context(|| format!("{package} must be written as <plugin>/<version>"))?;
+
+            let mut args = backend_info.switches_remove.to_vec();
+            args.extend([plugin, package.name.as_str()]);
+            self.run_asdf(&args)
+                .with_context(|| format!("removing {package}"))?;
+        }
+
+        Ok(())
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(backend_info.switches_info);
+        cmd.arg(package.repo.as_deref().unwrap_or(&package.name));
+
+        run_external_command(cmd)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+}
+
+/// Parse the output of `asdf list`, which lists every plugin on its own line,
+/// followed by its installed versions, indented and with the current one marked
+/// by `*`.
+fn parse_asdf_list(output: &str) -> Packages {
+    let mut result = Packages::new();
+    let mut plugin = None;
+
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            plugin = Some(line.trim());
+            continue;
+        }
+
+        let version = line.trim().trim_start_matches('*');
+
+        // plugins without versions print a message like `No versions installed`
+        if version.is_empty() || version.contains(' ') {
+            continue;
+        }
+
+        if let Some(plugin) = plugin {
+            result.insert(Package::from(format!("{plugin}/{version}")));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn asdf_list() {
+        let output = "nodejs\n  18.17.0\n *20.5.0\nruby\n  No versions installed\n";
+        let expected: Packages = [
+            Package::from("nodejs/18.17.0"),
+            Package::from("nodejs/20.5.0"),
+        ]
+        .into();
+
+        assert_eq!(parse_asdf_list(output), expected);
+    }
+
+    #[test]
+    fn install_and_set_global() {
+        let asdf = Asdf {
+            global: [("nodejs/20.5.0".into(), true)].into(),
+            extra_args: vec![],
+        };
+        let packages: Packages = [
+            Package::from("nodejs/18.17.0"),
+            Package::from("nodejs/20.5.0"),
+        ]
+        .into();
+
+        let runner = Rc::new(FakeRunner {
+            stdout: "nodejs\n".into(),
+            ..FakeRunner::default()
+        });
+        with_runner(runner.clone(), || asdf.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "asdf plugin list",
+                "asdf install nodejs 18.17.0",
+                "asdf install nodejs 20.5.0",
+                "asdf global nodejs 20.5.0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::fs::{read_dir, read_link};
+use std::io::ErrorKind::NotFound;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cmd::run_external_command;
+use crate::path::get_home_dir;
+use crate::prelude::*;
+
+/// Executables of Haskell packages, installed with `cabal install`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cabal {
+    /// The cabal flags to build a package with, e.g. `+threaded -pedantic`, keyed by
+    /// package name.
+    pub flags: BTreeMap<String, String>,
+    /// The version constraint of a package, e.g. `==3.8`, keyed by package name.
+    pub constraints: BTreeMap<String, String>,
+    pub extra_args: Vec<String>,
+}
+impl Cabal {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            flags: config.cabal_flags.clone(),
+            constraints: config.cabal_constraints.clone(),
+            extra_args: config.extra_args_for("cabal"),
+        }
+    }
+
+    /// The `--constraint` arguments for the flags and constraints of `packages`.
+    fn constraint_args(&self, packages: &Packages) -> Vec<String> {
+        let mut args = vec![];
+
+        for package in packages {
+            for map in [&self.constraints, &self.flags] {
+                if let Some(value) = map.get(&package.name) {
+                    args.push(format!("--constraint={} {value}", package.name));
+                }
+            }
+        }
+
+        args
+    }
+}
+
+/// An installed executable and the package it belongs to.
+struct Executable {
+    path: PathBuf,
+    package: String,
+}
+
+/// Get the executables in the dirs cabal installs into. Executables in
+/// `~/.cabal/bin` whose package cannot be told from the cabal store are taken as
+/// packages of their own name, as cabal v1 copied them there. `~/.local/bin` is
+/// shared with other tools, so only executables that link into the store count.
+fn get_installed_executables() -> Result<Vec<Executable>> {
+    let home = get_home_dir().context("getting home dir")?;
+
+    let mut result = vec![];
+    for (dir, store_only) in [(".cabal/bin", false), (".local/bin", true)] {
+        result.extend(
+            read_executables(&home.join(dir), store_only)
+                .with_context(|| format!("reading executables in ~/{dir}"))?,
+        );
+    }
+
+    Ok(result)
+}
+
+fn read_executables(dir: &Path, store_only: bool) -> Result<Vec<Executable>> {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == NotFound => return Ok(vec![]),
+        Err(err) => bail!(err),
+    };
+
+    let mut result = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let path = entry.path();
+        let package = read_link(&path)
+            .ok()
+            .and_then(|target| package_from_store_path(&target));
+
+        let package = match package {
+            Some(package) => package,
+            None if store_only => continue,
+            None => entry.file_name().to_string_lossy().to_string(),
+        };
+
+        result.push(Executable { path, package });
+    }
+
+    Ok(result)
+}
+
+/// Get the name of the package an executable in the cabal store belongs to. The
+/// path has the form `<store>/ghc-<version>/<package id>/bin/<executable>`.
+fn package_from_store_path(path: &Path) -> Option<String> {
+    let package_dir = path.parent()?.parent()?;
+    let ghc_dir = package_dir.parent()?.file_name()?.to_str()?;
+    if !ghc_dir.starts_with("ghc-") {
+        return None;
+    }
+
+    parse_package_id(package_dir.file_name()?.to_str()?)
+}
+
+/// Get the package name from a package id of the form `<name>-<version>-<hash>`,
+/// where the hash is optional. The name may contain dashes itself, the version only
+/// digits and dots.
+fn parse_package_id(id: &str) -> Option<String> {
+    let is_version = |segment: &str| {
+        segment.starts_with(|c: char| c.is_ascii_digit())
+            && segment.chars().all(|c| c.is_ascii_digit() || c == '.')
+    };
+
+    let segments: Vec<&str> = id.split('-').collect();
+    let mut version = segments.iter().rposition(|segment| is_version(segment))?;
+
+    // a hash of only digits looks like a version
+    if version + 1 == segments.len() && version > 1 && is_version(segments[version - 1]) {
+        version -= 1;
+    }
+
+    (version > 0).then(|| segments[..version].join("-"))
+}
+
+impl Backend for Cabal {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "cabal".to_string(),
+            section: "cabal",
+            switches_info: &["info"],
+            switches_install: &["install"],
+            switches_noconfirm: &[],
+            switches_remove: &[],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        self.get_explicitly_installed_packages()
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        Ok(get_installed_executables()?
+            .into_iter()
+            .map(|executable| Package::from(executable.package))
+            .collect())
+    }
+
+    /// Flags and constraints are passed as `--constraint`, which only affects the
+    /// package it names.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(backend_info.switches_install);
+        cmd.args(self.constraint_args(packages));
+        cmd.args(&backend_info.extra_install_args);
+        cmd.args(packages.iter().map(|package| &package.name));
+
+        run_external_command(cmd).context("installing haskell packages")
+    }
+
+    /// cabal cannot uninstall packages, so the executables of the packages are
+    /// removed. The builds stay in the cabal store.
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let paths: Vec<PathBuf> = get_installed_executables()?
+            .into_iter()
+            .filter(|executable| packages.contains(&Package::from(executable.package.as_str())))
+            .map(|executable| executable.path)
+            .collect();
+
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let mut cmd = Command::new("rm");
+        cmd.arg("--force");
+        cmd.args(paths);
+        run_external_command(cmd).context("removing haskell executables")
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn packages_from_store_paths() {
+        assert_eq!(
+            parse_package_id("hlint-3.8-e1a2b3c4").as_deref(),
+            Some("hlint")
+        );
+        assert_eq!(
+            parse_package_id("haskell-language-server-2.7.0.0-0123abcd").as_deref(),
+            Some("haskell-language-server")
+        );
+        assert_eq!(
+            parse_package_id("pandoc-cli-3.1.12").as_deref(),
+            Some("pandoc-cli")
+        );
+        assert_eq!(
+            parse_package_id("hlint-3.8-01234567").as_deref(),
+            Some("hlint")
+        );
+        assert_eq!(parse_package_id("hlint"), None);
+
+        let path = Path::new("/home/me/.cabal/store/ghc-9.4.8/hlint-3.8-e1a2b3c4/bin/hlint");
+        assert_eq!(package_from_store_path(path).as_deref(), Some("hlint"));
+        assert_eq!(package_from_store_path(Path::new("/usr/bin/hlint")), None);
+    }
+
+    #[test]
+    fn install_with_constraints() {
+        let cabal = Cabal {
+            flags: [("pandoc-cli".into(), "-lua".into())].into(),
+            constraints: [("hlint".into(), "==3.8".into())].into(),
+            extra_args: vec![],
+        };
+        let packages: Packages = [Package::from("hlint"), Package::from("pandoc-cli")].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || cabal.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            ["cabal install '--constraint=hlint ==3.8' '--constraint=pandoc-cli -lua' hlint pandoc-cli"]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::path::binary_in_path;
+use crate::prelude::*;
+
+/// Container images of podman or docker. Packages are the images as the runtime
+/// lists them, `<repository>:<tag>`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Container {
+    /// The binary of the container runtime.
+    pub runtime: String,
+    pub extra_args: Vec<String>,
+}
+impl Container {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            runtime: config
+                .container_runtime
+                .clone()
+                .unwrap_or_else(detect_runtime),
+            extra_args: config.extra_args_for("container"),
+        }
+    }
+}
+
+/// Use podman if it is installed, and docker otherwise.
+fn detect_runtime() -> String {
+    let podman = binary_in_path("podman").unwrap_or_default();
+    if podman { "podman" } else { "docker" }.to_string()
+}
+
+impl Backend for Container {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: self.runtime.clone(),
+            section: "container",
+            switches_info: &["image", "inspect"],
+            switches_install: &["pull"],
+            switches_noconfirm: &["--force"],
+            switches_remove: &["rmi"],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    /// Images without a repository or tag, like intermediate build layers, are not
+    /// listed.
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        let mut cmd = Command::new(&self.runtime);
+        cmd.args(["images", "--format", "{{.Repository}}:{{.Tag}}"]);
+
+        let output = run_command_for_stdout(cmd).context("listing images")?;
+        Ok(parse_images(&output))
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        self.get_all_installed_packages()
+            .context("getting all installed packages")
+    }
+
+    /// Pull every image on its own, as `pull` only takes a single image.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        for package in packages {
+            let mut cmd = Command::new(&backend_info.binary);
+            cmd.args(backend_info.switches_install);
+            cmd.args(&backend_info.extra_install_args);
+            cmd.arg(package.to_string());
+
+            run_external_command(cmd).with_context(|| format!("pulling image {package}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// `rmi` never prompts, so `noconfirm` is ignored.
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(&backend_info.binary);
+        cmd.args(backend_info.switches_remove);
+        cmd.args(packages.iter().map(ToString::to_string));
+
+        run_external_command(cmd)
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(&backend_info.binary);
+        cmd.args(backend_info.switches_info);
+        cmd.arg(package.to_string());
+
+        run_external_command(cmd)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+
+    /// Remove dangling images, i.e. layers that no tagged image uses anymore.
+    fn clean_cache(&self, noconfirm: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(&backend_info.binary);
+        cmd.args(["image", "prune"]);
+        if noconfirm {
+            cmd.args(backend_info.switches_noconfirm);
+        }
+
+        run_external_command(cmd).context("pruning images")
+    }
+}
+
+fn parse_images(output: &str) -> Packages {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.contains("<none>"))
+        .map(Package::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dangling_images_are_skipped() {
+        let output =
+            "docker.io/library/alpine:3.19\n<none>:<none>\nlocalhost/build:<none>\npostgres:16\n";
+        let images = parse_images(output);
+
+        let expected: Packages = [
+            Package::from("docker.io/library/alpine:3.19"),
+            Package::from("postgres:16"),
+        ]
+        .into();
+        assert_eq!(images, expected);
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::env::{split_paths, var_os};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use walkdir::WalkDir;
+
+use crate::cmd::run_external_command;
+use crate::path::get_home_dir;
+use crate::prelude::*;
+
+/// Perl modules, installed with `cpanm` into a local::lib.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cpanm {
+    /// The version to install a module in, e.g. `1.0048` or `>= 2.0`, keyed by
+    /// module name.
+    pub versions: BTreeMap<String, String>,
+    /// Whether to install a module without running its tests, keyed by module name.
+    pub notest: BTreeMap<String, bool>,
+    pub extra_args: Vec<String>,
+}
+impl Cpanm {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            versions: config.cpanm_versions.clone(),
+            notest: config.cpanm_notest.clone(),
+            extra_args: config.extra_args_for("cpanm"),
+        }
+    }
+
+    /// Get the argument for `module` that makes cpanm install its configured version.
+    /// An exact version becomes `Module@version`, a range `Module~range`.
+    fn module_arg(&self, module: &Package) -> String {
+        match self.versions.get(&module.name) {
+            Some(version) if version.starts_with(|c: char| c.is_ascii_digit()) => {
+                format!("{}@{version}", module.name)
+            }
+            Some(range) => format!("{}~{range}", module.name),
+            None => module.name.clone(),
+        }
+    }
+}
+
+/// Get the roots of the local::libs, from `PERL_LOCAL_LIB_ROOT` or else the default
+/// `~/perl5`.
+fn get_local_lib_roots() -> Result<Vec<PathBuf>> {
+    if let Some(roots) = var_os("PERL_LOCAL_LIB_ROOT").filter(|roots| !roots.is_empty()) {
+        return Ok(split_paths(&roots).collect());
+    }
+
+    Ok(vec![get_home_dir()
+        .context("getting home dir")?
+        .join("perl5")])
+}
+
+/// Get the modules installed into the local::lib at `root`. Every installed
+/// distribution leaves a `.packlist` under `lib/perl5/<archname>/auto/`, in the
+/// directory of its main module, which is also what `cpanm --uninstall` removes.
+fn read_packlists(root: &Path) -> Result<Packages> {
+    let lib = root.join("lib").join("perl5");
+    if !lib.is_dir() {
+        return Ok(Packages::new());
+    }
+
+    let mut result = Packages::new();
+    for entry in WalkDir::new(&lib) {
+        let entry = entry.with_context(|| format!("reading {}", lib.to_string_lossy()))?;
+        if entry.file_name() != ".packlist" {
+            continue;
+        }
+
+        if let Some(module) = module_from_packlist_path(entry.path()) {
+            result.insert(Package::from(module));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Get the name of the module that the `.packlist` at `path` belongs to, e.g.
+/// `JSON::PP` for `.../auto/JSON/PP/.packlist`.
+fn module_from_packlist_path(path: &Path) -> Option<String> {
+    let components: Vec<&str> = path
+        .parent()?
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+
+    let auto = components.iter().rposition(|name| *name == "auto")?;
+    let module = &components[auto + 1..];
+
+    (!module.is_empty()).then(|| module.join("::"))
+}
+
+impl Backend for Cpanm {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "cpanm".to_string(),
+            section: "cpanm",
+            switches_info: &["--info"],
+            switches_install: &[],
+            switches_noconfirm: &["--force"],
+            switches_remove: &["--uninstall"],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        self.get_explicitly_installed_packages()
+    }
+
+    /// Read the `.packlist` files of the local::libs. cpanm does not record which
+    /// modules it was asked to install, so the dependencies it installed count as
+    /// explicitly installed, too. Modules of the system Perl are not found.
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        let mut result = Packages::new();
+        for root in get_local_lib_roots()? {
+            result.extend(
+                read_packlists(&root)
+                    .with_context(|| format!("reading modules in {}", root.to_string_lossy()))?,
+            );
+        }
+
+        Ok(result)
+    }
+
+    /// Modules with `notest` are installed with `--notest` in an invocation of their
+    /// own, the others with their tests.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let (notest, tested): (Vec<&Package>, Vec<&Package>) = packages
+            .iter()
+            .partition(|module| self.notest.get(&module.name).copied().unwrap_or_default());
+
+        for (modules, notest) in [(tested, false), (notest, true)] {
+            if modules.is_empty() {
+                continue;
+            }
+
+            let mut cmd = Command::new(self.backend_info().binary);
+            if notest {
+                cmd.arg("--notest");
+            }
+            cmd.args(&self.extra_args);
+            cmd.args(modules.into_iter().map(|module| self.module_arg(module)));
+
+            run_external_command(cmd).context("installing perl modules")?;
+        }
+
+        Ok(())
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+
+    /// `cpanm --uninstall` asks for confirmation unless `--force` is given.
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn modules_from_packlists() {
+        let path = Path::new("/home/me/perl5/lib/perl5/x86_64-linux/auto/JSON/PP/.packlist");
+        assert_eq!(module_from_packlist_path(path).as_deref(), Some("JSON::PP"));
+
+        let path = Path::new("/home/me/perl5/lib/perl5/x86_64-linux/auto/Moo/.packlist");
+        assert_eq!(module_from_packlist_path(path).as_deref(), Some("Moo"));
+
+        let path = Path::new("/home/me/perl5/lib/perl5/x86_64-linux/auto/.packlist");
+        assert_eq!(module_from_packlist_path(path), None);
+    }
+
+    #[test]
+    fn install_with_versions_and_notest() {
+        let cpanm = Cpanm {
+            versions: [
+                ("Moo".into(), "2.005005".into()),
+                ("Plack".into(), ">= 1.0, < 2.0".into()),
+            ]
+            .into(),
+            notest: [("Plack".into(), true)].into(),
+            extra_args: vec![],
+        };
+        let packages: Packages = [
+            Package::from("JSON::PP"),
+            Package::from("Moo"),
+            Package::from("Plack"),
+        ]
+        .into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || cpanm.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "cpanm JSON::PP Moo@2.005005",
+                "cpanm --notest 'Plack~>= 1.0, < 2.0'"
+            ]
+        );
+    }
+}
@@ -1,21 +1,23 @@
-use anyhow::Result;
+use std::process::Command;
+
+use anyhow::{Context, Result};
 use rust_apt::cache::PackageSort;
 use rust_apt::new_cache;
 
+use crate::backend::backend_trait::parse_versions;
 use crate::backend::root::build_base_command_with_privileges;
-use crate::cmd::run_external_command;
+use crate::cmd::{run_command_for_stdout, run_external_command};
 use crate::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Debian {}
-impl Debian {
-    pub fn new() -> Self {
-        Self {}
-    }
+pub struct Debian {
+    pub extra_args: Vec<String>,
 }
-impl Default for Debian {
-    fn default() -> Self {
-        Self::new()
+impl Debian {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            extra_args: config.extra_args_for("debian"),
+        }
     }
 }
 
@@ -29,6 +31,7 @@ impl Backend for Debian {
             switches_noconfirm: &["--yes"],
             switches_remove: &["remove"],
             switches_make_dependency: Some(&[]),
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
@@ -76,6 +79,8 @@ impl Backend for Debian {
             cmd.args(backend_info.switches_noconfirm);
         }
 
+        cmd.args(&backend_info.extra_install_args);
+
         for p in packages {
             cmd.arg(format!("{p}"));
         }
@@ -100,4 +105,34 @@ impl Backend for Debian {
 
         run_external_command(cmd)
     }
+
+    fn list_orphans(&self) -> Result<Packages> {
+        let mut cmd = Command::new("apt-get");
+        cmd.args(["--dry-run", "autoremove"]);
+
+        let output = run_command_for_stdout(cmd).context("simulating autoremove")?;
+
+        // packages that would be removed are listed as `Remv <name> [<version>]`
+        let orphans = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("Remv "))
+            .filter_map(|rest| rest.split_whitespace().next())
+            .map(Package::from)
+            .collect();
+
+        Ok(orphans)
+    }
+
+    fn get_installed_versions(&self) -> Result<Versions> {
+        let mut cmd = Command::new("dpkg-query");
+        cmd.args(["--show", "--showformat", "${Package} ${Version}\\n"]);
+
+        let output = run_command_for_stdout(cmd).context("querying installed versions")?;
+        Ok(parse_versions(&output))
+    }
+
+    /// apt installs a specific version with `<name>=<version>`.
+    fn pin_version(&self, package: &Package, version: &str) -> Option<Package> {
+        Some(Package::from(format!("{}={version}", package.name)))
+    }
 }
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::prelude::*;
+
+/// Emacs packages managed by `package.el`, which is driven by `emacs --batch`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Emacs {
+    /// The elisp that prints one line per installed package: its name, followed by
+    /// the names of the packages it requires.
+    pub list_snippet: String,
+    /// The archive to install each package from, keyed by package name.
+    pub archives: BTreeMap<String, String>,
+    pub extra_args: Vec<String>,
+}
+impl Emacs {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            list_snippet: config.emacs_list_snippet.clone(),
+            archives: config.emacs_archives.clone(),
+            extra_args: config.extra_args_for("emacs"),
+        }
+    }
+
+    /// Run `snippet` with `emacs --batch`. The extra arguments come first, so that
+    /// they can e.g. point emacs to a different init directory.
+    fn batch_command(&self, snippet: &str) -> Command {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.arg("--batch");
+        cmd.args(&self.extra_args);
+        cmd.args(["--eval", snippet]);
+        cmd
+    }
+
+    fn query(&self) -> Result<(Packages, Packages)> {
+        let cmd = self.batch_command(&self.list_snippet);
+        let output = run_command_for_stdout(cmd).context("listing emacs packages")?;
+        Ok(parse_package_lines(&output))
+    }
+}
+
+/// The default for `emacs_list_snippet` in the config.
+pub const DEFAULT_LIST_SNIPPET: &str = "(progn
+  (require 'package)
+  (package-initialize)
+  (dolist (entry package-alist)
+    (princ (mapconcat #'symbol-name
+                      (cons (car entry) (mapcar #'car (package-desc-reqs (cadr entry))))
+                      \" \"))
+    (terpri)))";
+
+/// The URLs of the archives that `package.el` does not know by default.
+const ARCHIVE_URLS: [(&str, &str); 2] = [
+    ("melpa", "https://melpa.org/packages/"),
+    ("melpa-stable", "https://stable.melpa.org/packages/"),
+];
+
+impl Backend for Emacs {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "emacs".to_string(),
+            section: "emacs",
+            switches_info: &[],
+            switches_install: &[],
+            switches_noconfirm: &[],
+            switches_remove: &[],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        let (all, _) = self.query()?;
+        Ok(all)
+    }
+
+    /// `package.el` only records which packages were selected in the init file, which
+    /// is not written in batch mode. Instead, every package that no other installed
+    /// package requires counts as explicitly installed.
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        let (_, explicit) = self.query()?;
+        Ok(explicit)
+    }
+
+    /// Install the packages from the archives in the config. The packages are not
+    /// marked as selected, as saving that to the init file fails in batch mode.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let mut snippet = String::from("(progn (require 'package)");
+
+        let archives: BTreeSet<&str> = packages
+            .iter()
+            .filter_map(|package| self.archives.get(&package.name))
+            .map(String::as_str)
+            .collect();
+        for archive in archives {
+            if let Some((_, url)) = ARCHIVE_URLS.iter().find(|(name, _)| *name == archive) {
+                snippet.push_str(&format!(
+                    " (add-to-list 'package-archives '({} . {}) t)",
+                    elisp_string(archive),
+                    elisp_string(url)
+                ));
+            }
+        }
+
+        snippet.push_str(" (setq package-pinned-packages (list");
+        for package in packages {
+            if let Some(archive) = self.archives.get(&package.name) {
+                snippet.push_str(&format!(
+                    " (cons (intern {}) {})",
+                    elisp_string(&package.name),
+                    elisp_string(archive)
+                ));
+            }
+        }
+        snippet.push_str("))");
+
+        snippet.push_str(" (package-initialize) (package-refresh-contents)");
+        snippet.push_str(&format!(
+            " (dolist (name '({})) (package-install (intern name) t)))",
+            elisp_strings(packages)
+        ));
+
+        run_external_command(self.batch_command(&snippet))
+    }
+
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let snippet = format!(
+            "(progn (require 'package) (package-initialize) \
+             (dolist (name '({})) \
+             (let ((desc (cadr (assq (intern name) package-alist)))) \
+             (when desc (package-delete desc nil t)))))",
+            elisp_strings(packages)
+        );
+
+        run_external_command(self.batch_command(&snippet))
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let snippet = format!(
+            "(progn (require 'package) (package-initialize) \
+             (let ((desc (cadr (assq (intern {}) package-alist)))) \
+             (princ (if desc (package-desc-summary desc) \"not installed\")) (terpri)))",
+            elisp_string(&package.name)
+        );
+
+        run_external_command(self.batch_command(&snippet))
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+}
+
+/// Parse the output of the list snippet into all packages, and the packages that no
+/// other package requires.
+fn parse_package_lines(output: &str) -> (Packages, Packages) {
+    let mut all = Packages::new();
+    let mut required = BTreeSet::new();
+
+    for line in output.lines() {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+        all.insert(Package::from(name));
+        required.extend(words);
+    }
+
+    let explicit = all
+        .iter()
+        .filter(|package| !required.contains(package.name.as_str()))
+        .cloned()
+        .collect();
+
+    (all, explicit)
+}
+
+fn elisp_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn elisp_strings(packages: &Packages) -> String {
+    packages
+        .iter()
+        .map(|package| elisp_string(&package.name))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_packages_are_not_required() {
+        let output = "magit compat dash git-commit emacs\ncompat emacs seq\ndash emacs\nvertico\n";
+        let (all, explicit) = parse_package_lines(output);
+
+        assert_eq!(all.len(), 4);
+        let expected: Packages = [Package::from("magit"), Package::from("vertico")].into();
+        assert_eq!(explicit, expected);
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs::read_dir;
+use std::process::Command;
+use std::sync::Mutex;
+
+use anyhow::{ensure, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::path::binary_in_path;
+use crate::prelude::*;
+
+/// The prefix of the executables that implement an external backend.
+pub const EXECUTABLE_PREFIX: &str = "pacdef-backend-";
+
+/// The version of the protocol between pacdef and external backends.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// The names of all external backends of this run. The section of a backend must be
+/// `'static`, so each name is leaked once.
+static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// The external backends whose protocol version was already checked in this run.
+static CHECKED: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// A backend for the section `[<name>]`, implemented by the executable
+/// `pacdef-backend-<name>` in `PATH`. pacdef runs it as
+/// `pacdef-backend-<name> <subcommand> <payload>`, where the payload is a JSON object
+/// that always contains `protocol`:
+///
+/// - `version`: print `{"protocol": 1, "version": "<version>"}`, asked once per run
+///   before any other subcommand.
+/// - `query`: print `{"installed": [...], "explicit": [...]}`, the names of all
+///   installed packages and of those installed explicitly. Without `explicit`, all
+///   installed packages count as explicitly installed.
+/// - `install` and `remove`: install or remove the packages in the `packages` field
+///   of the payload, without prompting if `noconfirm` is true. The output is shown to
+///   the user, only the exit status counts.
+///
+/// A non-zero exit status is a failure of the subcommand.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct External {
+    pub name: &'static str,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    protocol: u64,
+    #[serde(default)]
+    version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct QueryResponse {
+    installed: BTreeSet<String>,
+    #[serde(default)]
+    explicit: Option<BTreeSet<String>>,
+}
+
+impl External {
+    pub fn new(name: &str) -> Self {
+        let mut names = NAMES
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        if let Some(name) = names.get(name) {
+            return Self { name };
+        }
+
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        names.insert(name);
+        Self { name }
+    }
+
+    /// Get the external backend for the section `name`, if its executable is in
+    /// `PATH`.
+    pub fn find(name: &str) -> Option<Self> {
+        let found = is_valid_name(name)
+            && binary_in_path(&format!("{EXECUTABLE_PREFIX}{name}")).unwrap_or_default();
+        found.then(|| Self::new(name))
+    }
+
+    /// Get all external backends whose executable is in `PATH`, except those named
+    /// like one of the `builtin` sections, which take precedence.
+    pub fn discover(builtin: &[&str]) -> Vec<Self> {
+        let Some(paths) = env::var_os("PATH") else {
+            return vec![];
+        };
+
+        let mut names = BTreeSet::new();
+        for dir in env::split_paths(&paths) {
+            let Ok(entries) = read_dir(dir) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let file_name = entry.file_name();
+                let Some(name) = file_name
+                    .to_str()
+                    .and_then(|f| f.strip_prefix(EXECUTABLE_PREFIX))
+                else {
+                    continue;
+                };
+                if is_valid_name(name) && !builtin.contains(&name) && entry.path().is_file() {
+                    names.insert(name.to_string());
+                }
+            }
+        }
+
+        names.iter().map(|name| Self::new(name)).collect()
+    }
+
+    fn command(&self, subcommand: &str, payload: &Value) -> Command {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.arg(subcommand);
+        cmd.arg(payload.to_string());
+        cmd
+    }
+
+    /// Run `subcommand` with the fields of `payload` and parse the response.
+    fn request<T: DeserializeOwned>(&self, subcommand: &str, mut payload: Value) -> Result<T> {
+        payload["protocol"] = json!(PROTOCOL_VERSION);
+
+        let output = run_command_for_stdout(self.command(subcommand, &payload))
+            .with_context(|| format!("running {subcommand} of external backend {}", self.name))?;
+
+        serde_json::from_str(&output).with_context(|| {
+            format!(
+                "parsing the response to {subcommand} of external backend {}",
+                self.name
+            )
+        })
+    }
+
+    /// Make sure the backend speaks the protocol of this pacdef, once per run.
+    fn check_protocol(&self) -> Result<()> {
+        if CHECKED
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .contains(self.name)
+        {
+            return Ok(());
+        }
+
+        let response: VersionResponse = self.request("version", json!({}))?;
+        ensure!(
+            response.protocol == PROTOCOL_VERSION,
+            "external backend {} speaks protocol {}, but pacdef speaks protocol {PROTOCOL_VERSION}",
+            self.name,
+            response.protocol
+        );
+        log::debug!(
+            "using external backend {} {}",
+            self.name,
+            response.version.as_deref().unwrap_or("(unknown version)")
+        );
+
+        CHECKED
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(self.name);
+        Ok(())
+    }
+
+    fn query(&self) -> Result<QueryResponse> {
+        self.check_protocol()?;
+        self.request("query", json!({}))
+    }
+
+    fn run_with_packages(
+        &self,
+        subcommand: &str,
+        packages: &Packages,
+        noconfirm: bool,
+    ) -> Result<()> {
+        self.check_protocol()?;
+
+        let payload = json!({
+            "protocol": PROTOCOL_VERSION,
+            "packages": packages.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            "noconfirm": noconfirm,
+        });
+        run_external_command(self.command(subcommand, &payload))
+    }
+}
+
+/// Names of external backends are restricted, so that they make valid section
+/// headers and file names.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl Backend for External {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: format!("{EXECUTABLE_PREFIX}{}", self.name),
+            section: self.name,
+            switches_info: &[],
+            switches_install: &[],
+            switches_noconfirm: &[],
+            switches_remove: &[],
+            switches_make_dependency: None,
+            extra_install_args: vec![],
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        let response = self.query()?;
+        Ok(response
+            .installed
+            .iter()
+            .map(String::as_str)
+            .map(Package::from)
+            .collect())
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        let response = self.query()?;
+        let explicit = response.explicit.unwrap_or(response.installed);
+        Ok(explicit
+            .iter()
+            .map(String::as_str)
+            .map(Package::from)
+            .collect())
+    }
+
+    fn install_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        self.run_with_packages("install", packages, noconfirm)
+    }
+
+    fn remove_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        self.run_with_packages("remove", packages, noconfirm)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        println!("{package} is managed by the external backend {}", self.name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn query_and_install_with_json() {
+        let backend = External::new("test-tool");
+        assert_eq!(backend, External::new("test-tool"));
+        CHECKED
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(backend.name);
+
+        let runner = Rc::new(FakeRunner {
+            stdout: r#"{"installed": ["a", "b"], "explicit": ["a"]}"#.into(),
+            ..FakeRunner::default()
+        });
+        let (all, explicit) = with_runner(runner.clone(), || {
+            let all = backend.get_all_installed_packages()?;
+            let explicit = backend.get_explicitly_installed_packages()?;
+            backend.install_packages(&[Package::from("c")].into(), true)?;
+            anyhow::Ok((all, explicit))
+        })
+        .expect("response is valid");
+
+        assert_eq!(all, [Package::from("a"), Package::from("b")].into());
+        assert_eq!(explicit, [Package::from("a")].into());
+        assert_eq!(
+            runner.commands.borrow().last().map(String::as_str),
+            Some(
+                r#"pacdef-backend-test-tool install '{"noconfirm":true,"packages":["c"],"protocol":1}'"#
+            )
+        );
+
+        assert!(!is_valid_name("../evil"));
+    }
+}
@@ -1,20 +1,20 @@
 use std::process::Command;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use crate::cmd::run_external_command;
+use crate::backend::backend_trait::parse_versions;
+use crate::cmd::{run_command_for_stdout, run_external_command};
 use crate::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Fedora {}
-impl Fedora {
-    pub fn new() -> Self {
-        Self {}
-    }
+pub struct Fedora {
+    pub extra_args: Vec<String>,
 }
-impl Default for Fedora {
-    fn default() -> Self {
-        Self::new()
+impl Fedora {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            extra_args: config.extra_args_for("fedora"),
+        }
     }
 }
 
@@ -40,6 +40,19 @@ const SWITCHES_FETCH_GLOBAL: Switches = &[
     "%{from_repo}/%{name}",
 ];
 
+/// These switches are responsible for
+/// getting the version of every installed package
+const SWITCHES_FETCH_VERSIONS: Switches = &[
+    "--query",
+    "--all",
+    "--queryformat",
+    "%{name} %{version}-%{release}\\n",
+];
+
+/// These switches are responsible for
+/// checking the files of every installed package
+const SWITCHES_VERIFY: Switches = &["--verify", "--all"];
+
 impl Backend for Fedora {
     fn backend_info(&self) -> BackendInfo {
         BackendInfo {
@@ -50,6 +63,7 @@ impl Backend for Fedora {
             switches_noconfirm: &["--assumeyes"],
             switches_remove: &["remove"],
             switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
@@ -85,6 +99,8 @@ impl Backend for Fedora {
             cmd.args(backend_info.switches_noconfirm);
         }
 
+        cmd.args(&backend_info.extra_install_args);
+
         for p in packages {
             cmd.arg(&p.name);
             if let Some(repo) = p.repo.as_ref() {
@@ -131,6 +147,52 @@ impl Backend for Fedora {
     fn make_dependency(&self, _: &Packages) -> Result<()> {
         panic!("Not supported by the package manager!")
     }
+
+    fn get_installed_versions(&self) -> Result<Versions> {
+        let mut cmd = Command::new("rpm");
+        cmd.args(SWITCHES_FETCH_VERSIONS);
+
+        let output = run_command_for_stdout(cmd).context("querying installed versions")?;
+        Ok(parse_versions(&output))
+    }
+
+    /// `rpm` reports modified files without their package, so the owner of every
+    /// reported file is queried separately.
+    fn verify_packages(&self) -> Result<Vec<VerifyIssue>> {
+        let mut cmd = Command::new("rpm");
+        cmd.args(SWITCHES_VERIFY);
+
+        // rpm exits with an error if any file differs, so the status is ignored
+        let output = cmd.output().context("verifying package files")?;
+        let stdout = String::from_utf8(output.stdout)?;
+
+        let mut result = vec![];
+
+        for (flags, path) in stdout.lines().filter_map(parse_rpm_verify_line) {
+            let mut cmd = Command::new("rpm");
+            cmd.args(["--query", "--file", "--queryformat", "%{name}\\n", path]);
+
+            let owners =
+                run_command_for_stdout(cmd).with_context(|| format!("getting owner of {path}"))?;
+
+            for owner in owners.lines() {
+                result.push(VerifyIssue {
+                    package: Package::from(owner),
+                    description: format!("{path} ({flags})"),
+                });
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// dnf installs a specific version when it is appended to the name.
+    fn pin_version(&self, package: &Package, version: &str) -> Option<Package> {
+        Some(Package {
+            name: format!("{}-{version}", package.name),
+            repo: package.repo.clone(),
+        })
+    }
 }
 
 fn create_package(package: &str) -> Package {
@@ -141,3 +203,55 @@ fn create_package(package: &str) -> Package {
         package.into()
     }
 }
+
+/// Split a line of `rpm --verify` into the flags describing what differs and the
+/// path of the file. Files that are missing are reported as `missing` instead of
+/// flags.
+fn parse_rpm_verify_line(line: &str) -> Option<(&str, &str)> {
+    let flags = line.split_whitespace().next()?;
+    let path = &line[line.find('/')?..];
+    Some((flags, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn versions_from_rpm() {
+        let fedora = Fedora { extra_args: vec![] };
+        let runner = Rc::new(FakeRunner {
+            stdout: "bash 5.2.26-3.fc40\nzsh 5.9-14.fc40\n".into(),
+            ..Default::default()
+        });
+
+        let versions = with_runner(runner.clone(), || fedora.get_installed_versions())
+            .expect("fake runner does not fail");
+
+        assert_eq!(versions["bash"], "5.2.26-3.fc40");
+        assert_eq!(versions["zsh"], "5.9-14.fc40");
+        assert_eq!(
+            *runner.commands.borrow(),
+            [r"rpm --query --all --queryformat '%{name} %{version}-%{release}\n'"]
+        );
+    }
+
+    #[test]
+    fn rpm_verify_output() {
+        assert_eq!(
+            parse_rpm_verify_line("S.5....T.  c /etc/dnf/dnf.conf"),
+            Some(("S.5....T.", "/etc/dnf/dnf.conf"))
+        );
+        assert_eq!(
+            parse_rpm_verify_line("missing     /usr/share/doc/bash/README"),
+            Some(("missing", "/usr/share/doc/bash/README"))
+        );
+        assert_eq!(
+            parse_rpm_verify_line("Unsatisfied dependencies for x"),
+            None
+        );
+    }
+}
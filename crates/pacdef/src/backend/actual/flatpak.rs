@@ -8,11 +8,13 @@ use crate::prelude::*;
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Flatpak {
     pub systemwide: bool,
+    pub extra_args: Vec<String>,
 }
 impl Flatpak {
     pub fn new(config: &Config) -> Self {
         Self {
             systemwide: config.flatpak_systemwide,
+            extra_args: config.extra_args_for("flatpak"),
         }
     }
 
@@ -37,6 +39,32 @@ impl Flatpak {
         let output = String::from_utf8(cmd.output()?.stdout)?;
         Ok(output.lines().map(Package::from).collect::<Packages>())
     }
+
+    /// Install the packages, with `reinstall` also the ones that are installed
+    /// already.
+    fn install(&self, packages: &Packages, noconfirm: bool, reinstall: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(backend_info.switches_install);
+        cmd.args(self.get_switches_runtime());
+
+        if reinstall {
+            cmd.arg("--reinstall");
+        }
+
+        if noconfirm {
+            cmd.args(backend_info.switches_noconfirm);
+        }
+
+        cmd.args(&backend_info.extra_install_args);
+
+        for p in packages {
+            cmd.arg(format!("{p}"));
+        }
+
+        run_external_command(cmd)
+    }
 }
 
 impl Backend for Flatpak {
@@ -49,9 +77,15 @@ impl Backend for Flatpak {
             switches_noconfirm: &["--assumeyes"],
             switches_remove: &["uninstall"],
             switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
+    /// Flatpak asks to confirm permissions and runtimes it pulls in.
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
     fn get_all_installed_packages(&self) -> Result<Packages> {
         self.get_installed_packages(true)
     }
@@ -62,21 +96,16 @@ impl Backend for Flatpak {
 
     /// Install the specified packages.
     fn install_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
-        let backend_info = self.backend_info();
-
-        let mut cmd = Command::new(backend_info.binary);
-        cmd.args(backend_info.switches_install);
-        cmd.args(self.get_switches_runtime());
-
-        if noconfirm {
-            cmd.args(backend_info.switches_noconfirm);
-        }
+        self.install(packages, noconfirm, false)
+    }
 
-        for p in packages {
-            cmd.arg(format!("{p}"));
-        }
+    fn supports_reinstall(&self) -> bool {
+        true
+    }
 
-        run_external_command(cmd)
+    /// Install the packages with `--reinstall`, which uninstalls them first.
+    fn reinstall_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        self.install(packages, noconfirm, true)
     }
 
     fn make_dependency(&self, _: &Packages) -> Result<()> {
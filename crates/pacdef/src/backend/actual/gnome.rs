@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::grouping::options::PackageOptions;
+use crate::path::TempDir;
+use crate::prelude::*;
+
+/// The extensions.gnome.org endpoint that describes an extension by its UUID.
+const EXTENSION_INFO_URL: &str = "https://extensions.gnome.org/extension-info/";
+
+/// GNOME Shell extensions, managed with `gnome-extensions`. Packages are the UUIDs of
+/// the extensions, e.g. `dash-to-dock@micxgx.gmail.com`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GnomeExtensions {
+    /// The path or URL of the zip file to install an extension from, keyed by UUID.
+    pub urls: BTreeMap<String, String>,
+    /// Whether to enable an extension after installing it, keyed by UUID.
+    pub enable: BTreeMap<String, bool>,
+    pub extra_args: Vec<String>,
+}
+impl GnomeExtensions {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            urls: config.gnome_extension_urls.clone(),
+            enable: config.gnome_extension_enable.clone(),
+            extra_args: config.extra_args_for("gnome"),
+        }
+    }
+
+    fn list(&self, args: &[&str]) -> Result<Packages> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.arg("list").args(args);
+
+        let output = run_command_for_stdout(cmd).context("listing extensions")?;
+        Ok(output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(Package::from)
+            .collect())
+    }
+
+    /// The zip file of an extension, unless it is set to a local path in the config
+    /// downloaded into a new private directory, which is removed once it is dropped.
+    fn get_zip(&self, package: &Package) -> Result<(String, Option<TempDir>)> {
+        let url = match self.urls.get(&package.name) {
+            Some(url) if !url.contains("://") => return Ok((url.clone(), None)),
+            Some(url) => url.clone(),
+            None => get_download_url(package)?,
+        };
+
+        let dir = TempDir::new("pacdef-gnome")?;
+        let file = dir
+            .path()
+            .join(format!("{}.shell-extension.zip", package.name));
+
+        let mut cmd = Command::new("curl");
+        cmd.args(["--silent", "--fail", "--location", "--output"]);
+        cmd.arg(&file).arg(url);
+        run_external_command(cmd).with_context(|| format!("downloading extension {package}"))?;
+
+        Ok((file.to_string_lossy().into(), Some(dir)))
+    }
+}
+
+/// Look up the download URL of an extension for the running GNOME Shell version on
+/// extensions.gnome.org.
+fn get_download_url(package: &Package) -> Result<String> {
+    let mut cmd = Command::new("gnome-shell");
+    cmd.arg("--version");
+    let version = run_command_for_stdout(cmd).context("getting GNOME Shell version")?;
+    let version = parse_shell_version(&version).context("reading GNOME Shell version")?;
+
+    let mut cmd = Command::new("curl");
+    cmd.args(["--silent", "--fail", "--location", "--get"]);
+    cmd.arg("--data-urlencode")
+        .arg(format!("uuid={}", package.name));
+    cmd.arg("--data-urlencode")
+        .arg(format!("shell_version={version}"));
+    cmd.arg(EXTENSION_INFO_URL);
+
+    let response = run_command_for_stdout(cmd)
+        .with_context(|| format!("looking up extension {package} on extensions.gnome.org"))?;
+    parse_download_url(&response).with_context(|| {
+        format!("no version of extension {package} supports GNOME Shell {version}")
+    })
+}
+
+/// The major version from the output of `gnome-shell --version`, e.g. `45` from
+/// `GNOME Shell 45.2`.
+fn parse_shell_version(output: &str) -> Option<&str> {
+    let version = output.split_whitespace().last()?;
+    version.split('.').next()
+}
+
+/// The absolute download URL from the extension info of extensions.gnome.org.
+fn parse_download_url(response: &str) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_str(response).ok()?;
+    let path = json["download_url"].as_str()?;
+    Some(format!("https://extensions.gnome.org{path}"))
+}
+
+impl Backend for GnomeExtensions {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "gnome-extensions".to_string(),
+            section: "gnome",
+            switches_info: &["info"],
+            switches_install: &["install", "--force"],
+            switches_noconfirm: &[],
+            switches_remove: &["uninstall"],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        self.list(&[])
+    }
+
+    /// Extensions the user installed, as opposed to those of the system.
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        self.list(&["--user"])
+    }
+
+    /// Install every extension on its own, as `install` only takes a single zip file.
+    /// Extensions with `enable` set are enabled afterwards, which takes effect once
+    /// GNOME Shell has loaded them, i.e. after logging in again on Wayland.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        for package in packages {
+            let (zip, download_dir) = self.get_zip(package)?;
+
+            let mut cmd = Command::new(&backend_info.binary);
+            cmd.args(backend_info.switches_install);
+            cmd.args(&backend_info.extra_install_args);
+            cmd.arg(zip);
+            run_external_command(cmd).with_context(|| format!("installing extension {package}"))?;
+            drop(download_dir);
+
+            if self.enable.get(&package.name).copied().unwrap_or_default() {
+                let mut cmd = Command::new(&backend_info.binary);
+                cmd.arg("enable").arg(&package.name);
+                run_external_command(cmd)
+                    .with_context(|| format!("enabling extension {package}"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove every extension on its own, as `uninstall` only takes a single UUID.
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        for package in packages {
+            let mut cmd = Command::new(&backend_info.binary);
+            cmd.args(backend_info.switches_remove);
+            cmd.arg(&package.name);
+            run_external_command(cmd).with_context(|| format!("removing extension {package}"))?;
+        }
+
+        Ok(())
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(backend_info.switches_info);
+        cmd.arg(&package.name);
+
+        run_external_command(cmd)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+
+    /// Whether each extension is enabled.
+    fn get_install_options(&self, packages: &Packages) -> Result<InstallOptions> {
+        let enabled = self.list(&["--user", "--enabled"])?;
+
+        Ok(packages
+            .iter()
+            .filter(|package| enabled.contains(package))
+            .map(|package| {
+                let options = PackageOptions {
+                    enable: Some(true),
+                    ..PackageOptions::default()
+                };
+                (package.name.clone(), options)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn install_from_zip_and_enable() {
+        let uuid = "dash-to-dock@micxgx.gmail.com";
+        let gnome = GnomeExtensions {
+            urls: [(uuid.into(), "/tmp/dash-to-dock.zip".into())].into(),
+            enable: [(uuid.into(), true)].into(),
+            extra_args: vec![],
+        };
+        let packages: Packages = [Package::from(uuid)].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || gnome.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "gnome-extensions install --force /tmp/dash-to-dock.zip",
+                "gnome-extensions enable dash-to-dock@micxgx.gmail.com",
+            ]
+        );
+    }
+
+    #[test]
+    fn download_is_removed_after_install() {
+        let uuid = "appindicatorsupport@rgcjonas.gmail.com";
+        let gnome = GnomeExtensions {
+            urls: [(uuid.into(), "https://example.org/appindicator.zip".into())].into(),
+            enable: BTreeMap::new(),
+            extra_args: vec![],
+        };
+        let packages: Packages = [Package::from(uuid)].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || gnome.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        let commands = runner.commands.borrow();
+        let zip = commands[0]
+            .strip_prefix("curl --silent --fail --location --output ")
+            .and_then(|rest| rest.strip_suffix(" https://example.org/appindicator.zip"))
+            .expect("zip is downloaded with curl");
+        assert!(zip.contains("/pacdef-gnome-"));
+        assert_eq!(
+            commands[1],
+            format!("gnome-extensions install --force {zip}")
+        );
+
+        let dir = std::path::Path::new(zip).parent().expect("zip is in a dir");
+        assert!(!dir.exists(), "download dir is removed");
+    }
+
+    #[test]
+    fn extension_info_is_parsed() {
+        assert_eq!(parse_shell_version("GNOME Shell 45.2\n"), Some("45"));
+
+        let response = r#"{"uuid":"dash-to-dock@micxgx.gmail.com","download_url":"/download-extension/dash-to-dock@micxgx.gmail.com.shell-extension.zip?version_tag=40000"}"#;
+        assert_eq!(
+            parse_download_url(response).as_deref(),
+            Some("https://extensions.gnome.org/download-extension/dash-to-dock@micxgx.gmail.com.shell-extension.zip?version_tag=40000")
+        );
+        assert_eq!(parse_download_url("{}"), None);
+    }
+}
@@ -0,0 +1,91 @@
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cmd::run_command_for_stdout;
+use crate::prelude::*;
+
+/// Packages of the current home-manager generation. These are declared in the
+/// home-manager configuration, so this backend only reports them, which prevents
+/// pacdef from managing them a second time.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HomeManager;
+
+const READ_ONLY_MESSAGE: &str =
+    "home-manager packages are declared in your home.nix, edit it and run `home-manager switch`";
+
+impl Backend for HomeManager {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "home-manager".to_string(),
+            section: "home-manager",
+            switches_info: &[],
+            switches_install: &[],
+            switches_noconfirm: &[],
+            switches_remove: &[],
+            switches_make_dependency: None,
+            extra_install_args: vec![],
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.arg("packages");
+
+        let output = run_command_for_stdout(cmd).context("listing home-manager packages")?;
+
+        Ok(output
+            .lines()
+            .filter_map(|line| Package::try_from(strip_version(line.trim())))
+            .collect())
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        self.get_all_installed_packages()
+            .context("getting all installed packages")
+    }
+
+    fn install_packages(&self, _: &Packages, _: bool) -> Result<()> {
+        bail!(READ_ONLY_MESSAGE)
+    }
+
+    fn remove_packages(&self, _: &Packages, _: bool) -> Result<()> {
+        bail!(READ_ONLY_MESSAGE)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        bail!(READ_ONLY_MESSAGE)
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        println!("{package} is provided by the current home-manager generation");
+        Ok(())
+    }
+}
+
+/// Remove the version from a nix store name like `gnome-shell-45.2`. As in nix, the
+/// version starts at the first dash that is followed by a digit.
+fn strip_version(name: &str) -> &str {
+    name.char_indices()
+        .find(|&(i, c)| {
+            c == '-'
+                && name[i + 1..]
+                    .chars()
+                    .next()
+                    .is_some_and(|next| next.is_ascii_digit())
+        })
+        .map_or(name, |(i, _)| &name[..i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip_version;
+
+    #[test]
+    fn version_is_stripped() {
+        assert_eq!(strip_version("git-2.43.0"), "git");
+        assert_eq!(strip_version("gnome-shell-45.2"), "gnome-shell");
+        assert_eq!(strip_version("python3-3.11.6"), "python3");
+        assert_eq!(strip_version("hm-session-vars.sh"), "hm-session-vars.sh");
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::prelude::*;
+
+/// Tool versions managed by mise. Packages are written as `<tool>@<version>`, e.g.
+/// `node@20.5.0`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Mise {
+    /// Whether to use a version globally after installing it, keyed by package.
+    pub global: BTreeMap<String, bool>,
+    pub extra_args: Vec<String>,
+}
+impl Mise {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            global: config.mise_global.clone(),
+            extra_args: config.extra_args_for("mise"),
+        }
+    }
+}
+
+impl Backend for Mise {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "mise".to_string(),
+            section: "mise",
+            switches_info: &["ls-remote"],
+            switches_install: &["install"],
+            switches_noconfirm: &["--yes"],
+            switches_remove: &["uninstall"],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.args(["ls", "--json"]);
+
+        let output = run_command_for_stdout(cmd).context("listing mise tools")?;
+        let json: Value = serde_json::from_str(&output).context("parsing JSON from mise")?;
+
+        extract_packages(&json).context("extracting packages from mise output")
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        self.get_all_installed_packages()
+            .context("getting all installed packages")
+    }
+
+    /// Install the packages, then use the ones with `global` set globally.
+    fn install_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(&backend_info.binary);
+        cmd.args(backend_info.switches_install);
+
+        if noconfirm {
+            cmd.args(backend_info.switches_noconfirm);
+        }
+
+        cmd.args(&backend_info.extra_install_args);
+
+        for p in packages {
+            cmd.arg(format!("{p}"));
+        }
+
+        run_external_command(cmd)?;
+
+        for package in packages {
+            if self.global.get(&package.name).copied().unwrap_or_default() {
+                let mut cmd = Command::new(&backend_info.binary);
+                cmd.args(["use", "--global"]).arg(format!("{package}"));
+                run_external_command(cmd).with_context(|| format!("using {package} globally"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let tool = package.name.split('@').next().unwrap_or(&package.name);
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(backend_info.switches_info);
+        cmd.arg(tool);
+
+        run_external_command(cmd)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+}
+
+/// The output of `mise ls --json` maps every tool to a list of versions. Versions
+/// that are requested by a config file but not installed are skipped.
+fn extract_packages(json: &Value) -> Result<Packages> {
+    let mut result = Packages::new();
+
+    for (tool, versions) in json.as_object().context("getting tools object")? {
+        for version in versions.as_array().context("getting versions array")? {
+            if version["installed"].as_bool() == Some(false) {
+                continue;
+            }
+
+            let version = version["version"]
+                .as_str()
+                .with_context(|| format!("getting version of {tool}"))?;
+
+            result.insert(Package::from(format!("{tool}@{version}")));
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn mise_ls() {
+        let json = serde_json::json!({
+            "node": [
+                { "version": "20.5.0", "installed": true, "active": true },
+                { "version": "21.0.0", "installed": false }
+            ],
+            "python": [{ "version": "3.11.4", "installed": true }]
+        });
+
+        let expected: Packages =
+            [Package::from("node@20.5.0"), Package::from("python@3.11.4")].into();
+        assert_eq!(
+            extract_packages(&json).expect("valid mise output"),
+            expected
+        );
+    }
+
+    #[test]
+    fn install_and_use_globally() {
+        let mise = Mise {
+            global: [
+                ("node@20.5.0".into(), true),
+                ("python@3.11.4".into(), false),
+            ]
+            .into(),
+            extra_args: vec![],
+        };
+        let packages: Packages =
+            [Package::from("node@20.5.0"), Package::from("python@3.11.4")].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || mise.install_packages(&packages, true))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "mise install --yes node@20.5.0 python@3.11.4",
+                "mise use --global node@20.5.0",
+            ]
+        );
+    }
+}
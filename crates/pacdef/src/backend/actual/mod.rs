@@ -1,10 +1,22 @@
 #[cfg(feature = "arch")]
 pub mod arch;
+pub mod asdf;
+pub mod cabal;
+pub mod container;
+pub mod cpanm;
 #[cfg(feature = "debian")]
 pub mod debian;
+pub mod emacs;
+pub mod external;
 pub mod fedora;
 pub mod flatpak;
+pub mod gnome;
+pub mod home_manager;
+pub mod mise;
 pub mod python;
 pub mod rust;
 pub mod rustup;
+pub mod tpm;
+pub mod vim;
 pub mod void;
+pub mod zig;
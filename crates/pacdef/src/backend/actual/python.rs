@@ -4,6 +4,7 @@ use anyhow::Context;
 use anyhow::Result;
 use serde_json::Value;
 
+use crate::cmd::run_external_command;
 use crate::prelude::*;
 
 macro_rules! ERROR{
@@ -15,11 +16,13 @@ macro_rules! ERROR{
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Python {
     pub binary: String,
+    pub extra_args: Vec<String>,
 }
 impl Python {
     pub fn new(config: &Config) -> Self {
         Self {
             binary: config.pip_binary.to_string(),
+            extra_args: config.extra_args_for("python"),
         }
     }
 
@@ -38,6 +41,14 @@ impl Python {
         }
     }
 
+    fn extract_versions(&self, output: &Value) -> Result<Versions> {
+        match self.backend_info().binary.as_str() {
+            "pip" => extract_versions(output),
+            "pipx" => extract_versions_pipx(output),
+            _ => ERROR!(self.backend_info().binary),
+        }
+    }
+
     fn extract_packages(&self, output: Value) -> Result<Packages> {
         match self.backend_info().binary.as_str() {
             "pip" => extract_pacdef_packages(output),
@@ -57,6 +68,7 @@ impl Backend for Python {
             switches_noconfirm: &[],
             switches_remove: &["uninstall"],
             switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
@@ -75,6 +87,62 @@ impl Backend for Python {
     fn make_dependency(&self, _packages: &Packages) -> Result<()> {
         panic!("not supported by {}", self.binary)
     }
+
+    fn get_installed_versions(&self) -> Result<Versions> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        let output = run_pip_command(&mut cmd, self.get_switches_explicit())?;
+        self.extract_versions(&output)
+    }
+
+    /// Both pip and pipx accept a requirement specifier instead of a plain name.
+    fn pin_version(&self, package: &Package, version: &str) -> Option<Package> {
+        Some(Package::from(format!("{}=={version}", package.name)))
+    }
+
+    fn supports_reinstall(&self) -> bool {
+        true
+    }
+
+    /// pip reinstalls with `--force-reinstall`, pipx with `--force`.
+    fn reinstall_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(backend_info.switches_install);
+
+        match self.binary.as_str() {
+            "pip" => cmd.arg("--force-reinstall"),
+            "pipx" => cmd.arg("--force"),
+            _ => ERROR!(self.binary),
+        };
+
+        cmd.args(&backend_info.extra_install_args);
+
+        for p in packages {
+            cmd.arg(format!("{p}"));
+        }
+
+        run_external_command(cmd)
+    }
+
+    /// Remove the specified packages. Of the supported binaries, only `pip` asks for
+    /// confirmation when uninstalling.
+    fn remove_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let backend_info = self.backend_info();
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(backend_info.switches_remove);
+
+        if noconfirm && self.binary == "pip" {
+            cmd.arg("--yes");
+        }
+
+        for p in packages {
+            cmd.arg(format!("{p}"));
+        }
+
+        run_external_command(cmd)
+    }
 }
 
 fn run_pip_command(cmd: &mut Command, args: &[&str]) -> Result<Value> {
@@ -104,3 +172,27 @@ fn extract_pacdef_packages_pipx(value: Value) -> Result<Packages> {
         .collect();
     Ok(result)
 }
+
+fn extract_versions(value: &Value) -> Result<Versions> {
+    let result = value
+        .as_array()
+        .context("getting inner json array")?
+        .iter()
+        .filter_map(|node| Some((node["name"].as_str()?, node["version"].as_str()?)))
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+        .collect();
+    Ok(result)
+}
+
+fn extract_versions_pipx(value: &Value) -> Result<Versions> {
+    let result = value["venvs"]
+        .as_object()
+        .context("getting inner json object")?
+        .iter()
+        .filter_map(|(name, venv)| {
+            let version = venv["metadata"]["main_package"]["package_version"].as_str()?;
+            Some((name.to_string(), version.to_string()))
+        })
+        .collect();
+    Ok(result)
+}
@@ -1,25 +1,376 @@
-use std::fs::read_to_string;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_dir, read_to_string};
 use std::io::ErrorKind::NotFound;
 use std::path::PathBuf;
+use std::process::Command;
 
 use anyhow::{bail, Context, Result};
 use serde_json::Value;
 
+use crate::cmd::{run_command_for_output, run_command_for_stdout, run_external_command};
+use crate::grouping::options::PackageOptions;
 use crate::prelude::*;
 
+use super::rustup::Rustup;
+
+/// The crates.io API endpoint for the versions of crates.
+const CRATES_IO_API: &str = "https://crates.io/api/v1/crates";
+
+/// The maximum time a request to crates.io may take, in seconds.
+const CRATES_IO_TIMEOUT: &str = "5";
+
+/// The exit codes of curl that mean that the server could not be reached at all:
+/// proxy or host not resolved, connection failed, timeout and TLS handshake failed.
+const CURL_CONNECTION_ERRORS: [i32; 5] = [5, 6, 7, 28, 35];
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Rust {}
+pub struct Rust {
+    pub extra_args: Vec<String>,
+    /// Consider a crate installed regardless of its features and git source.
+    pub ignore_feature_drift: bool,
+    /// The target triple to install each crate for, keyed by crate name. Crates not
+    /// listed here are installed for the host.
+    pub targets: BTreeMap<String, String>,
+    /// The rustup toolchain to install each crate with, keyed by crate name. Crates
+    /// not listed here are installed with the active toolchain.
+    pub toolchains: BTreeMap<String, String>,
+    /// The git repository to install each crate from, keyed by crate name. Crates not
+    /// listed here are installed from crates.io.
+    pub git: BTreeMap<String, GitSource>,
+    /// The features to install each crate with, keyed by crate name. Crates not
+    /// listed here are installed with their default features.
+    pub features: BTreeMap<String, CrateFeatures>,
+    /// The binaries of each crate, keyed by crate name. Crates not listed here
+    /// provide a binary named like the crate.
+    pub binaries: BTreeMap<String, Vec<String>>,
+    /// Install every crate in a cargo invocation of its own and keep going if one
+    /// fails. All failures are reported at the end.
+    pub continue_on_error: bool,
+}
 impl Rust {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: &Config) -> Self {
+        let git = config
+            .cargo_git_urls
+            .iter()
+            .map(|(name, url)| {
+                let source = GitSource {
+                    url: url.clone(),
+                    branch: config.cargo_git_branches.get(name).cloned(),
+                    tag: config.cargo_git_tags.get(name).cloned(),
+                    rev: config.cargo_git_revs.get(name).cloned(),
+                };
+                (name.clone(), source)
+            })
+            .collect();
+
+        let names = config
+            .cargo_features
+            .keys()
+            .chain(config.cargo_all_features.keys())
+            .chain(config.cargo_no_default_features.keys());
+        let features = names
+            .map(|name| {
+                let mut features = CrateFeatures {
+                    features: config.cargo_features.get(name).cloned().unwrap_or_default(),
+                    all_features: config
+                        .cargo_all_features
+                        .get(name)
+                        .copied()
+                        .unwrap_or_default(),
+                    no_default_features: config
+                        .cargo_no_default_features
+                        .get(name)
+                        .copied()
+                        .unwrap_or_default(),
+                };
+                // the crates file lists the features sorted
+                features.features.sort();
+                (name.clone(), features)
+            })
+            .collect();
+
+        Self {
+            extra_args: config.extra_args_for("rust"),
+            ignore_feature_drift: config.cargo_ignore_feature_drift,
+            targets: config.cargo_targets.clone(),
+            toolchains: config.cargo_toolchains.clone(),
+            git,
+            features,
+            binaries: config.cargo_binaries.clone(),
+            continue_on_error: config.continues_on_error("rust"),
+        }
+    }
+
+    /// Install the crates, one cargo invocation per toolchain, target, git source
+    /// and features, or per crate with `continue_on_error`. With `force`, crates that are
+    /// installed already are rebuilt.
+    fn install_crates(&self, packages: &Packages, force: bool) -> Result<()> {
+        type Batch<'a> = (
+            Option<&'a str>,
+            Option<&'a str>,
+            Option<&'a GitSource>,
+            Option<&'a CrateFeatures>,
+        );
+
+        let mut batches: BTreeMap<Batch, Vec<&Package>> = BTreeMap::new();
+        for package in packages {
+            let name = package.name.split('@').next().unwrap_or_default();
+            let toolchain = self.toolchains.get(name).map(String::as_str);
+            let target = self.targets.get(name).map(String::as_str);
+            let git = self.git.get(name);
+            let features = self.features.get(name);
+            batches
+                .entry((toolchain, target, git, features))
+                .or_default()
+                .push(package);
+        }
+
+        warn_missing_toolchains(batches.keys().filter_map(|(toolchain, _, _, _)| *toolchain));
+
+        let mut failed = vec![];
+        for (batch, packages) in batches {
+            let invocations = if self.continue_on_error {
+                packages.into_iter().map(|package| vec![package]).collect()
+            } else {
+                vec![packages]
+            };
+
+            for packages in invocations {
+                let result = run_external_command(self.install_command(batch, force, &packages));
+                let (toolchain, target, _, _) = batch;
+                let result = result.with_context(|| {
+                    format!(
+                        "installing crates with toolchain {} for target {}",
+                        toolchain.unwrap_or("active"),
+                        target.unwrap_or("host")
+                    )
+                });
+
+                match result {
+                    Err(error) if self.continue_on_error => {
+                        let name = packages[0].to_string();
+                        log::warn!(backend = "rust", package:% = name; "could not install crate {name}: {error:#}");
+                        failed.push(name);
+                    }
+                    result => result?,
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            bail!(
+                "could not install {} crates: {}",
+                failed.len(),
+                failed.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Get the crates among `packages` that provide any of the `untracked` binaries.
+    fn untracked_crates(&self, packages: &Packages, untracked: &BTreeSet<String>) -> Packages {
+        packages
+            .iter()
+            .filter(|package| {
+                let name = package.name.split('@').next().unwrap_or_default();
+                self.binaries.get(name).map_or_else(
+                    || untracked.contains(name),
+                    |binaries| binaries.iter().any(|binary| untracked.contains(binary)),
+                )
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Get the crates of the crates file `json` that drifted from their declared
+    /// features, git source or target, with `host` as the default target. Features
+    /// and git source are skipped with `ignore_feature_drift`.
+    fn extract_drifted(&self, json: &Value, host: Option<&str>) -> Result<Packages> {
+        let mut drifted = Packages::new();
+
+        if !self.ignore_feature_drift {
+            let packages = extract_drifted_packages(json, &self.features)
+                .context("extracting install options")?;
+            for package in &packages {
+                log::info!(backend = "rust", package:% = package; "crate {package} is installed with different features");
+            }
+            drifted.extend(packages);
+
+            let packages = extract_source_drifted_packages(json, &self.git)
+                .context("extracting install sources")?;
+            for package in &packages {
+                log::info!(backend = "rust", package:% = package; "crate {package} is installed from a different source");
+            }
+            drifted.extend(packages);
+        }
+
+        let packages = extract_target_drifted_packages(json, &self.targets, host)
+            .context("extracting install targets")?;
+        for package in &packages {
+            log::info!(backend = "rust", package:% = package; "crate {package} is installed for a different target");
+        }
+        drifted.extend(packages);
+
+        Ok(drifted)
+    }
+
+    /// Get the `cargo install` command for the `packages` of one toolchain, target,
+    /// git source and features.
+    fn install_command(
+        &self,
+        (toolchain, target, git, features): (
+            Option<&str>,
+            Option<&str>,
+            Option<&GitSource>,
+            Option<&CrateFeatures>,
+        ),
+        force: bool,
+        packages: &[&Package],
+    ) -> Command {
+        let mut cmd = Command::new(self.backend_info().binary);
+        if let Some(toolchain) = toolchain {
+            cmd.arg(format!("+{toolchain}"));
+        }
+        cmd.args(self.backend_info().switches_install);
+        if force {
+            cmd.arg("--force");
+        }
+        if let Some(target) = target {
+            cmd.args(["--target", target]);
+        }
+        if let Some(git) = git {
+            cmd.args(git.args());
+        }
+        if let Some(features) = features {
+            cmd.args(features.args());
+        }
+        cmd.args(&self.extra_args);
+        cmd.args(packages.iter().map(ToString::to_string));
+        cmd
     }
 }
-impl Default for Rust {
-    fn default() -> Self {
-        Self::new()
+
+/// The git repository a crate is installed from, as passed to `cargo install --git`.
+/// cargo accepts at most one of branch, tag and rev.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GitSource {
+    pub url: String,
+    pub branch: Option<String>,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+}
+impl GitSource {
+    /// Parse the source of a crate in the crates file, which has the form
+    /// `git+<url>[?branch=<branch>|?tag=<tag>|?rev=<rev>]#<commit>`. Returns `None`
+    /// for sources other than git.
+    fn parse(source: &str) -> Option<Self> {
+        let source = source.strip_prefix("git+")?;
+        let source = source.split_once('#').map_or(source, |(source, _)| source);
+        let (url, query) = source.split_once('?').unwrap_or((source, ""));
+
+        let mut result = Self {
+            url: url.to_string(),
+            ..Self::default()
+        };
+
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let value = Some(percent_decode(value));
+            match key {
+                "branch" => result.branch = value,
+                "tag" => result.tag = value,
+                "rev" => result.rev = value,
+                _ => (),
+            }
+        }
+
+        Some(result)
+    }
+
+    fn args(&self) -> Vec<&str> {
+        let mut args = vec!["--git", self.url.as_str()];
+
+        let refs = [
+            ("--branch", &self.branch),
+            ("--tag", &self.tag),
+            ("--rev", &self.rev),
+        ];
+        for (switch, value) in refs {
+            if let Some(value) = value {
+                args.extend([switch, value.as_str()]);
+            }
+        }
+
+        args
     }
 }
 
+/// The features a crate is installed with, as passed to `cargo install`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CrateFeatures {
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+}
+impl CrateFeatures {
+    /// Read the features of an install from the crates file.
+    fn from_install(install: &Value) -> Self {
+        let mut features: Vec<String> = install["features"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|feature| feature.as_str().map(String::from))
+            .collect();
+        features.sort();
+
+        Self {
+            features,
+            all_features: install["all_features"].as_bool().unwrap_or_default(),
+            no_default_features: install["no_default_features"].as_bool().unwrap_or_default(),
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if !self.features.is_empty() {
+            args.extend(["--features".to_string(), self.features.join(",")]);
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        args
+    }
+}
+
+/// Decode the `%XX` escapes cargo uses for branch and tag names in the crates file.
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
 impl Backend for Rust {
     fn backend_info(&self) -> BackendInfo {
         BackendInfo {
@@ -30,34 +381,152 @@ impl Backend for Rust {
             switches_noconfirm: &[],
             switches_remove: &["uninstall"],
             switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
     fn get_all_installed_packages(&self) -> Result<Packages> {
-        let file = get_crates_file().context("getting path to crates file")?;
-
-        let content = match read_to_string(file) {
-            Ok(string) => string,
-            Err(err) if err.kind() == NotFound => {
-                log::warn!("no crates file found for cargo. Assuming no crates installed yet.");
-                return Ok(Packages::new());
-            }
-            Err(err) => bail!(err),
+        let Some(json) = read_crates_file()? else {
+            return Ok(Packages::new());
         };
 
-        let json: Value =
-            serde_json::from_str(&content).context("parsing JSON from crates file")?;
         extract_packages(&json).context("extracting packages from crates file")
     }
 
+    /// Crates installed with `--no-track` are missing from the crates file. A
+    /// declared crate is therefore considered installed if one of its binaries is in
+    /// the cargo bin dir without a tracked crate providing it, so that it is not
+    /// reinstalled forever.
+    fn get_untracked_packages(&self, packages: &Packages) -> Result<Packages> {
+        let tracked_bins = match read_crates_file()? {
+            Some(json) => extract_bins(&json).context("extracting binaries from crates file")?,
+            None => BTreeSet::new(),
+        };
+
+        let binaries = read_bin_dir().context("reading cargo bin dir")?;
+        let untracked = untracked_binaries(binaries, &tracked_bins);
+
+        let result = self.untracked_crates(packages, &untracked);
+        for package in &result {
+            log::info!(backend = "rust", package:% = package; "crate {package} is only detected by its binary, cargo does not track it");
+        }
+
+        Ok(result)
+    }
+
+    /// Only crates from the crates file are reported, as untracked binaries might not
+    /// be crates at all.
     fn get_explicitly_installed_packages(&self) -> Result<Packages> {
-        self.get_all_installed_packages()
-            .context("getting all installed packages")
+        let Some(json) = read_crates_file()? else {
+            return Ok(Packages::new());
+        };
+
+        extract_packages(&json).context("extracting packages from crates file")
+    }
+
+    /// Every crate that was installed with other features than the declared ones has
+    /// drifted. The same holds for crates that were installed from a different git
+    /// source or for a different target than the configured one. With
+    /// `ignore_feature_drift`, only the target is compared.
+    fn get_drifted_packages(&self) -> Result<Packages> {
+        let Some(json) = read_crates_file()? else {
+            return Ok(Packages::new());
+        };
+
+        self.extract_drifted(&json, get_host_target().as_deref())
+    }
+
+    /// Crates with a configured target are installed with `--target`, crates with a
+    /// configured toolchain with `cargo +<toolchain>`, and crates with a configured
+    /// git source with `--git`, one cargo invocation per toolchain, target and source.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        self.install_crates(packages, false)
+    }
+
+    fn supports_reinstall(&self) -> bool {
+        true
+    }
+
+    /// Install the crates with `--force`, which makes cargo rebuild them.
+    fn reinstall_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        self.install_crates(packages, true)
     }
 
     fn make_dependency(&self, _: &Packages) -> Result<()> {
         panic!("not supported by {}", self.backend_info().binary)
     }
+
+    fn get_installed_versions(&self) -> Result<Versions> {
+        let Some(json) = read_crates_file()? else {
+            return Ok(Versions::new());
+        };
+
+        extract_versions(&json).context("extracting versions from crates file")
+    }
+
+    /// Ask crates.io whether the installed version of each crate from crates.io was
+    /// yanked, see [`query_yanked`].
+    fn get_yanked_packages(&self) -> Result<Versions> {
+        let Some(json) = read_crates_file()? else {
+            return Ok(Versions::new());
+        };
+
+        let versions =
+            extract_crates_io_versions(&json).context("extracting versions from crates file")?;
+        query_yanked(versions)
+    }
+
+    /// Crates that were installed for a different target than the host get that
+    /// target as option, and crates that were installed from git their git source.
+    fn get_install_options(&self, packages: &Packages) -> Result<InstallOptions> {
+        let Some(json) = read_crates_file()? else {
+            return Ok(InstallOptions::new());
+        };
+
+        let mut result = InstallOptions::new();
+
+        if let Some(host) = get_host_target() {
+            let targets = extract_installed_targets(&json).context("extracting install targets")?;
+            for (name, target) in targets {
+                if target != host {
+                    result.entry(name).or_default().target = Some(target);
+                }
+            }
+        }
+
+        let sources = extract_git_sources(&json).context("extracting install sources")?;
+        for (name, source) in sources {
+            let options: &mut PackageOptions = result.entry(name).or_default();
+            options.url = Some(source.url);
+            options.branch = source.branch;
+            options.tag = source.tag;
+            options.rev = source.rev;
+        }
+
+        result.retain(|name, _| packages.contains(&Package::from(name.as_str())));
+        Ok(result)
+    }
+
+    fn pin_version(&self, package: &Package, version: &str) -> Option<Package> {
+        Some(Package::from(format!("{}@{version}", package.name)))
+    }
+}
+
+/// Read and parse the crates file. Returns `None` if it does not exist yet.
+fn read_crates_file() -> Result<Option<Value>> {
+    let file = get_crates_file().context("getting path to crates file")?;
+
+    let content = match read_to_string(file) {
+        Ok(string) => string,
+        Err(err) if err.kind() == NotFound => {
+            log::warn!("no crates file found for cargo. Assuming no crates installed yet.");
+            return Ok(None);
+        }
+        Err(err) => bail!(err),
+    };
+
+    let json = serde_json::from_str(&content).context("parsing JSON from crates file")?;
+    Ok(Some(json))
 }
 
 fn extract_packages(json: &Value) -> Result<Packages> {
@@ -66,21 +535,835 @@ fn extract_packages(json: &Value) -> Result<Packages> {
         .context("get 'installs' field from json")?
         .as_object()
         .context("getting object")?
+        .keys()
+        .filter_map(|key| {
+            let parsed = parse_key(key).map(|parsed| Package::from(parsed.name));
+            if parsed.is_none() {
+                log::warn!("cannot read the entry '{key}' of the crates file");
+            }
+            parsed
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// Get the binaries of all crates in the crates file.
+fn extract_bins(json: &Value) -> Result<BTreeSet<String>> {
+    let result = json
+        .get("installs")
+        .context("get 'installs' field from json")?
+        .as_object()
+        .context("getting object")?
+        .values()
+        .filter_map(|install| install["bins"].as_array())
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(ToString::to_string)
+        .collect();
+
+    Ok(result)
+}
+
+/// Get the names of all files in the cargo bin dir. Returns no names if the dir does
+/// not exist.
+fn read_bin_dir() -> Result<Vec<String>> {
+    let mut dir = crate::path::get_cargo_home().context("getting cargo home dir")?;
+    dir.push("bin");
+
+    let entries = match read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == NotFound => return Ok(vec![]),
+        Err(err) => bail!(err),
+    };
+
+    let mut result = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            continue;
+        }
+        result.push(entry.file_name().to_string_lossy().to_string());
+    }
+
+    Ok(result)
+}
+
+/// Binaries that rustup installs into the cargo bin dir.
+const RUSTUP_PROXIES: [&str; 14] = [
+    "cargo",
+    "cargo-clippy",
+    "cargo-fmt",
+    "cargo-miri",
+    "clippy-driver",
+    "rls",
+    "rust-analyzer",
+    "rust-gdb",
+    "rust-gdbgui",
+    "rust-lldb",
+    "rustc",
+    "rustdoc",
+    "rustfmt",
+    "rustup",
+];
+
+/// Get the binaries that are neither provided by a tracked crate nor by rustup.
+fn untracked_binaries(binaries: Vec<String>, tracked: &BTreeSet<String>) -> BTreeSet<String> {
+    binaries
         .into_iter()
-        .map(|(name, _)| name)
-        .map(|name| {
-            name.split_whitespace()
-                .next()
-                .expect("identifier is whitespace-delimited")
+        .filter(|binary| !tracked.contains(binary) && !RUSTUP_PROXIES.contains(&binary.as_str()))
+        .collect()
+}
+
+fn extract_versions(json: &Value) -> Result<Versions> {
+    let result = json
+        .get("installs")
+        .context("get 'installs' field from json")?
+        .as_object()
+        .context("getting object")?
+        .keys()
+        .filter_map(|key| parse_key(key))
+        .map(|key| (key.name.to_string(), key.version.to_string()))
+        .collect();
+
+    Ok(result)
+}
+
+/// Get the version of every crate that was installed from crates.io, keyed by crate
+/// name.
+fn extract_crates_io_versions(json: &Value) -> Result<Versions> {
+    let result = json
+        .get("installs")
+        .context("get 'installs' field from json")?
+        .as_object()
+        .context("getting object")?
+        .keys()
+        .filter_map(|key| parse_key(key))
+        .filter(|key| is_crates_io(key.source))
+        .map(|key| (key.name.to_string(), key.version.to_string()))
+        .collect();
+
+    Ok(result)
+}
+
+/// Check whether the source of a crate in the crates file is the crates.io index,
+/// either through git or the sparse protocol.
+fn is_crates_io(source: &str) -> bool {
+    source == "registry+https://github.com/rust-lang/crates.io-index"
+        || source == "sparse+https://index.crates.io/"
+}
+
+/// Read from a crates.io API response for a single version whether it was yanked.
+fn is_yanked(response: &str) -> bool {
+    serde_json::from_str::<Value>(response)
+        .ok()
+        .and_then(|json| json["version"]["yanked"].as_bool())
+        .unwrap_or_default()
+}
+
+/// Ask crates.io which of the `versions` were yanked. A crate that cannot be
+/// checked, e.g. because crates.io does not know it, is skipped with a warning. The
+/// first failure to reach crates.io at all ends the check, so that being offline
+/// does not cause a timeout per crate.
+fn query_yanked(versions: Versions) -> Result<Versions> {
+    let mut result = Versions::new();
+
+    for (name, version) in versions {
+        let mut cmd = Command::new("curl");
+        cmd.args(["--silent", "--fail", "--location"]);
+        cmd.args(["--max-time", CRATES_IO_TIMEOUT]);
+        cmd.args([
+            "--user-agent",
+            concat!("pacdef/", env!("CARGO_PKG_VERSION")),
+        ]);
+        cmd.arg(format!("{CRATES_IO_API}/{name}/{version}"));
+
+        let output = run_command_for_output(cmd).context("querying crates.io")?;
+        let Err(error) = output.ensure_success() else {
+            if is_yanked(&output.stdout) {
+                result.insert(name, version);
+            }
+            continue;
+        };
+
+        if output
+            .code
+            .is_some_and(|code| CURL_CONNECTION_ERRORS.contains(&code))
+        {
+            log::warn!(
+                backend = "rust";
+                "cannot reach crates.io, skipping the check for yanked crates from {name} on: {error:#}"
+            );
+            break;
+        }
+
+        log::warn!(backend = "rust"; "cannot check whether {name} {version} was yanked: {error:#}");
+    }
+
+    Ok(result)
+}
+
+/// Get the crates that were installed with other features than the `declared`
+/// ones. Crates without declared features must be installed with the default
+/// features only.
+fn extract_drifted_packages(
+    json: &Value,
+    declared: &BTreeMap<String, CrateFeatures>,
+) -> Result<Packages> {
+    let default = CrateFeatures::default();
+    let result = json
+        .get("installs")
+        .context("get 'installs' field from json")?
+        .as_object()
+        .context("getting object")?
+        .iter()
+        .filter_map(|(key, install)| Some((parse_key(key)?.name, install)))
+        .filter(|(name, install)| {
+            let declared = declared.get(*name).unwrap_or(&default);
+            CrateFeatures::from_install(install) != *declared
         })
-        .map(|name| Package::try_from(name).expect("name is valid"))
+        .map(|(name, _)| Package::from(name))
         .collect();
 
     Ok(result)
 }
 
+/// A key of the crates file, which has the form `<name> <version> (<source>)`.
+#[derive(Debug, PartialEq, Eq)]
+struct CrateKey<'a> {
+    name: &'a str,
+    version: &'a str,
+    /// The source, e.g. `registry+<index URL>`, or empty if the key has none.
+    source: &'a str,
+}
+
+/// Split a key of the crates file into name, version and source. The name ends at
+/// the first space. The source is enclosed by the last closing parenthesis and the
+/// opening parenthesis that matches it, so that it may contain spaces and
+/// parentheses itself, like the sources of some alternative registries. Everything
+/// in between is the version.
+fn parse_key(key: &str) -> Option<CrateKey<'_>> {
+    let (name, rest) = key.trim().split_once(' ')?;
+    let rest = rest.trim();
+
+    let Some(inner) = rest.strip_suffix(')') else {
+        return (!rest.is_empty()).then_some(CrateKey {
+            name,
+            version: rest,
+            source: "",
+        });
+    };
+
+    let mut depth = 0;
+    let open = inner.char_indices().rev().find_map(|(i, c)| {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => return Some(i),
+            '(' => depth -= 1,
+            _ => (),
+        }
+        None
+    })?;
+
+    let version = inner[..open].trim();
+    (!name.is_empty() && !version.is_empty()).then_some(CrateKey {
+        name,
+        version,
+        source: &inner[open + 1..],
+    })
+}
+
+/// Get the git source of each crate that was installed from git, keyed by crate
+/// name.
+fn extract_git_sources(json: &Value) -> Result<BTreeMap<String, GitSource>> {
+    let result = json
+        .get("installs")
+        .context("get 'installs' field from json")?
+        .as_object()
+        .context("getting object")?
+        .keys()
+        .filter_map(|key| {
+            let key = parse_key(key)?;
+            Some((key.name.to_string(), GitSource::parse(key.source)?))
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// Get the crates that were installed from a different source than the one in
+/// `git`, i.e. from crates.io although a git source is configured, from git without
+/// a configured source, or from a different repository, branch, tag or rev.
+fn extract_source_drifted_packages(
+    json: &Value,
+    git: &BTreeMap<String, GitSource>,
+) -> Result<Packages> {
+    let result = json
+        .get("installs")
+        .context("get 'installs' field from json")?
+        .as_object()
+        .context("getting object")?
+        .keys()
+        .filter_map(|key| {
+            let CrateKey { name, source, .. } = parse_key(key)?;
+            let installed = GitSource::parse(source);
+            let drifted = match (installed, git.get(name)) {
+                (None, None) => false,
+                (Some(_), None) => true,
+                (installed, configured) => installed.as_ref() != configured,
+            };
+            drifted.then(|| Package::from(name))
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// Get the target each crate was installed for, keyed by crate name. Entries of the
+/// crates file without a recorded target are left out.
+fn extract_installed_targets(json: &Value) -> Result<BTreeMap<String, String>> {
+    let result = json
+        .get("installs")
+        .context("get 'installs' field from json")?
+        .as_object()
+        .context("getting object")?
+        .iter()
+        .filter_map(|(key, install)| {
+            let name = parse_key(key)?.name;
+            let target = install["target"].as_str()?;
+            Some((name.to_string(), target.to_string()))
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// Get the crates that were installed for a different target than the one in
+/// `targets`, or `host` for crates without a configured target. Entries of the
+/// crates file without a recorded target are never considered drifted.
+fn extract_target_drifted_packages(
+    json: &Value,
+    targets: &BTreeMap<String, String>,
+    host: Option<&str>,
+) -> Result<Packages> {
+    let result = extract_installed_targets(json)?
+        .into_iter()
+        .filter_map(|(name, installed)| {
+            let expected = targets.get(&name).map(String::as_str).or(host)?;
+            (installed != expected).then(|| Package::from(name))
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// Get the target triple of the host from `rustc`. Returns `None` if `rustc` cannot
+/// be run.
+fn get_host_target() -> Option<String> {
+    let mut cmd = Command::new("rustc");
+    cmd.arg("-vV");
+
+    let output = run_command_for_stdout(cmd)
+        .map_err(|error| log::warn!("cannot determine host target: {error}"))
+        .ok()?;
+
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(ToString::to_string)
+}
+
+/// Warn about each of `toolchains` that rustup does not report as installed. cargo
+/// fails for these crates, unless rustup installs the toolchain on demand.
+fn warn_missing_toolchains<'a>(toolchains: impl Iterator<Item = &'a str>) {
+    let toolchains: BTreeSet<&str> = toolchains.collect();
+    if toolchains.is_empty() {
+        return;
+    }
+
+    let rustup = Rustup {
+        profiles: BTreeMap::new(),
+        extra_args: vec![],
+    };
+    let installed = match rustup.installed_toolchains() {
+        Ok(installed) => installed,
+        Err(error) => {
+            log::warn!("cannot determine installed toolchains: {error}");
+            return;
+        }
+    };
+
+    for toolchain in toolchains {
+        if !installed.iter().any(|name| name == toolchain) {
+            log::warn!("toolchain {toolchain} is not installed, declare it in [rustup]");
+        }
+    }
+}
+
 fn get_crates_file() -> Result<PathBuf> {
     let mut result = crate::path::get_cargo_home().context("getting cargo home dir")?;
     result.push(".crates2.json");
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, CommandOutput, FakeRunner};
+
+    #[test]
+    fn install_groups_crates_by_toolchain_and_target() {
+        let rust = Rust {
+            extra_args: vec!["--locked".into()],
+            ignore_feature_drift: false,
+            targets: [("fd-find".into(), "x86_64-unknown-linux-musl".into())].into(),
+            toolchains: [("bat".into(), "nightly".into())].into(),
+            git: [(
+                "helix-term".into(),
+                GitSource {
+                    url: "https://github.com/helix-editor/helix".into(),
+                    tag: Some("24.03".into()),
+                    ..GitSource::default()
+                },
+            )]
+            .into(),
+            features: [(
+                "ripgrep".into(),
+                CrateFeatures {
+                    features: vec!["pcre2".into()],
+                    no_default_features: true,
+                    ..CrateFeatures::default()
+                },
+            )]
+            .into(),
+            binaries: BTreeMap::new(),
+            continue_on_error: false,
+        };
+        let packages: Packages = [
+            Package::from("ripgrep"),
+            Package::from("fd-find@9.0.0"),
+            Package::from("bat"),
+            Package::from("helix-term"),
+        ]
+        .into();
+
+        let runner = Rc::new(FakeRunner {
+            stdout: "stable-x86_64-unknown-linux-gnu (default)\n".into(),
+            ..FakeRunner::default()
+        });
+        with_runner(runner.clone(), || rust.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "rustup toolchain list",
+                "cargo install --features pcre2 --no-default-features --locked ripgrep",
+                "cargo install --git https://github.com/helix-editor/helix --tag 24.03 --locked helix-term",
+                "cargo install --target x86_64-unknown-linux-musl --locked fd-find@9.0.0",
+                "cargo +nightly install --locked bat",
+            ]
+        );
+    }
+
+    #[test]
+    fn reinstall_forces_cargo() {
+        let rust = Rust {
+            extra_args: vec![],
+            ignore_feature_drift: false,
+            targets: BTreeMap::new(),
+            toolchains: BTreeMap::new(),
+            git: BTreeMap::new(),
+            features: BTreeMap::new(),
+            binaries: BTreeMap::new(),
+            continue_on_error: false,
+        };
+        let packages: Packages = [Package::from("ripgrep")].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || rust.reinstall_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        assert_eq!(*runner.commands.borrow(), ["cargo install --force ripgrep"]);
+    }
+
+    #[test]
+    fn continue_after_failed_crate() {
+        let rust = Rust {
+            extra_args: vec![],
+            ignore_feature_drift: false,
+            targets: BTreeMap::new(),
+            toolchains: BTreeMap::new(),
+            git: BTreeMap::new(),
+            features: BTreeMap::new(),
+            binaries: BTreeMap::new(),
+            continue_on_error: true,
+        };
+        let packages: Packages = [
+            Package::from("bat"),
+            Package::from("broken"),
+            Package::from("ripgrep"),
+        ]
+        .into();
+
+        let runner = Rc::new(FakeRunner {
+            failing: vec!["broken".into()],
+            ..FakeRunner::default()
+        });
+        let error = with_runner(runner.clone(), || rust.install_packages(&packages, false))
+            .expect_err("broken crate fails");
+
+        assert_eq!(error.to_string(), "could not install 1 crates: broken");
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "cargo install bat",
+                "cargo install broken",
+                "cargo install ripgrep"
+            ]
+        );
+    }
+
+    #[test]
+    fn untracked_crates() {
+        let json = serde_json::json!({
+            "installs": {
+                "ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "bins": ["rg"]
+                }
+            }
+        });
+        let tracked = extract_bins(&json).expect("valid crates file");
+        let binaries = ["rg", "cargo", "rustup", "just"].map(String::from).to_vec();
+
+        assert_eq!(
+            untracked_binaries(binaries, &tracked),
+            ["just".into()].into()
+        );
+    }
+
+    #[test]
+    fn untracked_crate_with_other_binary_name() {
+        let rust = Rust {
+            binaries: [("ripgrep".into(), vec!["rg".into()])].into(),
+            ..Rust::new(&Config::default())
+        };
+        let declared: Packages = [Package::from("ripgrep"), Package::from("just")].into();
+        let untracked = ["rg".into()].into();
+
+        assert_eq!(
+            rust.untracked_crates(&declared, &untracked),
+            [Package::from("ripgrep")].into()
+        );
+    }
+
+    #[test]
+    fn stray_binary_is_no_crate() {
+        let rust = Rust::new(&Config::default());
+        let declared: Packages = [Package::from("just")].into();
+        let untracked = ["backup.sh".into(), "rg".into()].into();
+
+        assert!(rust.untracked_crates(&declared, &untracked).is_empty());
+    }
+
+    #[test]
+    fn drifted_crates() {
+        let json = serde_json::json!({
+            "installs": {
+                "ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "features": [], "all_features": false, "no_default_features": false
+                },
+                "bat 0.24.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "features": [], "all_features": true, "no_default_features": false
+                },
+                "helix-term 24.3.0 (git+https://github.com/helix-editor/helix#abc)": {
+                    "features": [], "all_features": false, "no_default_features": false
+                },
+                "fd-find 9.0.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "features": ["completions"], "all_features": false, "no_default_features": false
+                }
+            }
+        });
+        let declared = [(
+            "fd-find".to_string(),
+            CrateFeatures {
+                features: vec!["completions".into()],
+                ..CrateFeatures::default()
+            },
+        )]
+        .into();
+
+        let drifted = extract_drifted_packages(&json, &declared).expect("valid crates file");
+        let expected: Packages = [Package::from("bat")].into();
+        assert_eq!(drifted, expected);
+    }
+
+    #[test]
+    fn yanked_check_skips_failures() {
+        let versions: Versions = ["a", "b", "c", "d"]
+            .map(|name| (name.to_string(), "1.0.0".to_string()))
+            .into();
+        let output = |code, stdout: &str| CommandOutput {
+            success: code == 0,
+            code: Some(code),
+            stdout: stdout.into(),
+            ..CommandOutput::default()
+        };
+        let runner = Rc::new(FakeRunner {
+            outputs: RefCell::new(
+                [
+                    output(22, ""),
+                    output(0, r#"{"version": {"num": "1.0.0", "yanked": true}}"#),
+                    output(7, ""),
+                ]
+                .into(),
+            ),
+            ..FakeRunner::default()
+        });
+
+        let yanked =
+            with_runner(runner.clone(), || query_yanked(versions)).expect("curl can be run");
+
+        assert_eq!(yanked, [("b".to_string(), "1.0.0".to_string())].into());
+        assert_eq!(runner.commands.borrow().len(), 3);
+    }
+
+    #[test]
+    fn yanked_crates() {
+        let json = serde_json::json!({
+            "installs": {
+                "ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)": {},
+                "bat 0.24.0 (sparse+https://index.crates.io/)": {},
+                "helix-term 24.3.0 (git+https://github.com/helix-editor/helix#abc)": {},
+                "tool 1.0.0 (registry+https://example.org/index)": {}
+            }
+        });
+
+        let versions = extract_crates_io_versions(&json).expect("valid crates file");
+        let expected: Versions = [
+            ("bat".to_string(), "0.24.0".to_string()),
+            ("ripgrep".to_string(), "14.1.0".to_string()),
+        ]
+        .into();
+        assert_eq!(versions, expected);
+
+        assert!(is_yanked(
+            r#"{"version": {"num": "0.24.0", "yanked": true}}"#
+        ));
+        assert!(!is_yanked(
+            r#"{"version": {"num": "14.1.0", "yanked": false}}"#
+        ));
+        assert!(!is_yanked("not json"));
+    }
+
+    #[test]
+    fn git_sources_are_parsed() {
+        let url = "https://github.com/helix-editor/helix";
+        let source = |branch: Option<&str>, tag: Option<&str>, rev: Option<&str>| GitSource {
+            url: url.into(),
+            branch: branch.map(Into::into),
+            tag: tag.map(Into::into),
+            rev: rev.map(Into::into),
+        };
+
+        assert_eq!(
+            GitSource::parse(&format!("git+{url}#0123abc")),
+            Some(source(None, None, None))
+        );
+        assert_eq!(
+            GitSource::parse(&format!("git+{url}?branch=feature%2Fthemes#0123abc")),
+            Some(source(Some("feature/themes"), None, None))
+        );
+        assert_eq!(
+            GitSource::parse(&format!("git+{url}?tag=24.03#0123abc")),
+            Some(source(None, Some("24.03"), None))
+        );
+        assert_eq!(
+            GitSource::parse(&format!("git+{url}?rev=0123#0123abc")),
+            Some(source(None, None, Some("0123")))
+        );
+        assert_eq!(
+            GitSource::parse("registry+https://github.com/rust-lang/crates.io-index"),
+            None
+        );
+        assert_eq!(
+            parse_key("helix-term 24.3.0 (git+https://github.com/helix-editor/helix#abc)"),
+            Some(CrateKey {
+                name: "helix-term",
+                version: "24.3.0",
+                source: "git+https://github.com/helix-editor/helix#abc"
+            })
+        );
+    }
+
+    #[test]
+    fn keys_of_custom_registries() {
+        assert_eq!(
+            parse_key("tool 1.2.3+corp.4 (registry+https://crates.corp.example/index (mirror))"),
+            Some(CrateKey {
+                name: "tool",
+                version: "1.2.3+corp.4",
+                source: "registry+https://crates.corp.example/index (mirror)"
+            })
+        );
+        assert_eq!(
+            parse_key("tool  1.0.0 build 7  (sparse+https://corp.example/My Index/)"),
+            Some(CrateKey {
+                name: "tool",
+                version: "1.0.0 build 7",
+                source: "sparse+https://corp.example/My Index/"
+            })
+        );
+        assert_eq!(
+            parse_key("tool 1.0.0"),
+            Some(CrateKey {
+                name: "tool",
+                version: "1.0.0",
+                source: ""
+            })
+        );
+        assert_eq!(
+            parse_key("tool 1.0.0 (registry+https://example.org))"),
+            None
+        );
+        assert_eq!(parse_key("tool"), None);
+
+        let json = serde_json::json!({
+            "installs": {
+                "tool 1.2.3+corp.4 (registry+https://crates.corp.example/index (mirror))": {},
+                "ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)": {}
+            }
+        });
+        let versions = extract_versions(&json).expect("valid crates file");
+        assert_eq!(versions["tool"], "1.2.3+corp.4");
+        assert_eq!(
+            extract_packages(&json).expect("valid crates file"),
+            [Package::from("ripgrep"), Package::from("tool")].into()
+        );
+    }
+
+    #[test]
+    fn crates_from_other_sources() {
+        let json = serde_json::json!({
+            "installs": {
+                "ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)": {},
+                "bat 0.24.0 (registry+https://github.com/rust-lang/crates.io-index)": {},
+                "helix-term 24.3.0 (git+https://github.com/helix-editor/helix?branch=master#abc)": {},
+                "jj-cli 0.16.0 (git+https://github.com/martinvonz/jj?tag=v0.16.0#def)": {},
+                "zellij 0.40.0 (git+https://github.com/zellij-org/zellij#123)": {}
+            }
+        });
+        let git = [
+            (
+                "bat".to_string(),
+                GitSource {
+                    url: "https://github.com/sharkdp/bat".into(),
+                    ..GitSource::default()
+                },
+            ),
+            (
+                "helix-term".to_string(),
+                GitSource {
+                    url: "https://github.com/helix-editor/helix".into(),
+                    branch: Some("master".into()),
+                    ..GitSource::default()
+                },
+            ),
+            (
+                "jj-cli".to_string(),
+                GitSource {
+                    url: "https://github.com/martinvonz/jj".into(),
+                    tag: Some("v0.17.0".into()),
+                    ..GitSource::default()
+                },
+            ),
+        ]
+        .into();
+
+        let drifted = extract_source_drifted_packages(&json, &git).expect("valid crates file");
+        let expected: Packages = [
+            Package::from("bat"),
+            Package::from("jj-cli"),
+            Package::from("zellij"),
+        ]
+        .into();
+        assert_eq!(drifted, expected);
+    }
+
+    #[test]
+    fn feature_drift_can_be_ignored() {
+        let mut rust = Rust {
+            ignore_feature_drift: false,
+            ..Rust::new(&Config::default())
+        };
+        rust.git.insert(
+            "helix-term".into(),
+            GitSource {
+                url: "https://github.com/helix-editor/helix".into(),
+                tag: Some("24.03".into()),
+                ..GitSource::default()
+            },
+        );
+        let json = serde_json::json!({
+            "installs": {
+                "helix-term 24.3.0 (git+https://github.com/helix-editor/helix?branch=master#abc)": {
+                    "features": [], "all_features": false, "no_default_features": false,
+                    "target": "x86_64-unknown-linux-gnu"
+                }
+            }
+        });
+
+        let drifted = rust
+            .extract_drifted(&json, None)
+            .expect("valid crates file");
+        assert_eq!(drifted, [Package::from("helix-term")].into());
+
+        rust.ignore_feature_drift = true;
+        assert!(rust
+            .extract_drifted(&json, None)
+            .expect("valid crates file")
+            .is_empty());
+
+        rust.targets
+            .insert("helix-term".into(), "x86_64-unknown-linux-musl".into());
+        let drifted = rust
+            .extract_drifted(&json, Some("x86_64-unknown-linux-gnu"))
+            .expect("valid crates file");
+        assert_eq!(drifted, [Package::from("helix-term")].into());
+    }
+
+    #[test]
+    fn crates_for_other_targets() {
+        let json = serde_json::json!({
+            "installs": {
+                "ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "target": "x86_64-unknown-linux-gnu"
+                },
+                "bat 0.24.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "target": "x86_64-unknown-linux-gnu"
+                },
+                "fd-find 9.0.0 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "target": "x86_64-unknown-linux-musl"
+                },
+                "zoxide 0.9.4 (registry+https://github.com/rust-lang/crates.io-index)": {}
+            }
+        });
+        let targets = [
+            ("bat".to_string(), "x86_64-unknown-linux-musl".to_string()),
+            (
+                "fd-find".to_string(),
+                "x86_64-unknown-linux-musl".to_string(),
+            ),
+        ]
+        .into();
+
+        let drifted =
+            extract_target_drifted_packages(&json, &targets, Some("x86_64-unknown-linux-gnu"))
+                .expect("valid crates file");
+        let expected: Packages = [Package::from("bat")].into();
+        assert_eq!(drifted, expected);
+    }
+}
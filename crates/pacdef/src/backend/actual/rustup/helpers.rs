@@ -1,58 +1,75 @@
-use super::types::RustupPackage;
+use std::collections::BTreeMap;
 
-pub fn toolchain_of_component_was_already_removed(
-    removed_toolchains: &[String],
-    component: &RustupPackage,
-) -> bool {
-    removed_toolchains.contains(&component.toolchain)
+use super::types::RustupInstallOptions;
+
+/// Extract the toolchain name from a line of `rustup toolchain list`, which has the
+/// form `<name>-<host triple>`, optionally followed by a marker like `(default)`.
+pub fn toolchain_name_from_line(line: &str) -> Option<&str> {
+    let full_name = line.split_whitespace().next()?;
+    let name = full_name.rsplitn(5, '-').last()?;
+    (!name.is_empty()).then_some(name)
 }
 
-pub fn install_components(line: &str, toolchain: &str, val: &mut Vec<String>) {
-    let mut chunks = line.splitn(3, '-');
-    let component = chunks.next().expect("Component name is empty!");
+/// Extract the component name from a line of `rustup component list --installed`,
+/// which has the form `<component>[-<host triple>]`.
+pub fn component_name_from_line(line: &str) -> Option<String> {
+    let mut chunks = line.trim().splitn(3, '-');
+    let component = chunks.next().filter(|c| !c.is_empty())?;
     match component {
         // these are the only components that have a single word name
-        "cargo" | "rustfmt" | "clippy" | "miri" | "rls" | "rustc" => {
-            val.push([toolchain, component].join("/"));
-        }
+        "cargo" | "rustfmt" | "clippy" | "miri" | "rls" | "rustc" => Some(component.to_string()),
         // all the others have two words hyphenated as component names
-        _ => {
-            let component = [
-                component,
-                chunks
-                    .next()
-                    .expect("No such component is managed by rustup"),
-            ]
-            .join("-");
-            val.push([toolchain, component.as_str()].join("/"));
-        }
+        _ => chunks.next().map(|second| [component, second].join("-")),
     }
 }
 
-pub fn group_components_by_toolchains(components: Vec<RustupPackage>) -> Vec<Vec<RustupPackage>> {
-    let mut result = vec![];
-
-    let mut toolchains: Vec<String> = vec![];
-
-    for component in components {
-        let index = toolchains
-            .iter()
-            .enumerate()
-            .find(|(_, toolchain)| toolchain == &&component.toolchain)
-            .map(|(idx, _)| idx)
-            .unwrap_or_else(|| {
-                toolchains.push(component.toolchain.clone());
-                result.push(vec![]);
-                toolchains.len() - 1
-            });
-
-        result
-            .get_mut(index)
-            .expect(
-                "either the index already existed or we just pushed the element with that index",
-            )
-            .push(component);
+/// Group the names of components or targets by the toolchain they belong to, so that
+/// one rustup invocation per toolchain suffices.
+pub fn group_by_toolchain<'a, I>(packages: I) -> BTreeMap<&'a str, Vec<&'a str>>
+where
+    I: IntoIterator<Item = &'a RustupInstallOptions>,
+{
+    let mut result: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+
+    for package in packages {
+        let name = match package {
+            RustupInstallOptions::Toolchain { .. } => continue,
+            RustupInstallOptions::Component { component, .. } => component,
+            RustupInstallOptions::Target { target, .. } => target,
+        };
+        result.entry(package.toolchain()).or_default().push(name);
     }
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{component_name_from_line, toolchain_name_from_line};
+
+    #[test]
+    fn toolchain_name() {
+        let line = "stable-x86_64-unknown-linux-gnu (default)";
+        assert_eq!(toolchain_name_from_line(line), Some("stable"));
+
+        let line = "nightly-2024-01-01-x86_64-unknown-linux-gnu";
+        assert_eq!(toolchain_name_from_line(line), Some("nightly-2024-01-01"));
+
+        assert_eq!(toolchain_name_from_line(""), None);
+    }
+
+    #[test]
+    fn component_name() {
+        let line = "clippy-x86_64-unknown-linux-gnu";
+        assert_eq!(component_name_from_line(line), Some("clippy".into()));
+
+        let line = "rust-analyzer-x86_64-unknown-linux-gnu";
+        assert_eq!(component_name_from_line(line), Some("rust-analyzer".into()));
+
+        assert_eq!(
+            component_name_from_line("rust-src"),
+            Some("rust-src".into())
+        );
+        assert_eq!(component_name_from_line("weird"), None);
+    }
+}
@@ -1,26 +1,29 @@
 mod helpers;
 mod types;
 
-use crate::cmd::run_external_command;
-use crate::prelude::*;
-use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
 use std::process::Command;
 
-use self::helpers::{
-    group_components_by_toolchains, install_components, toolchain_of_component_was_already_removed,
-};
-use self::types::{Repotype, RustupPackage};
+use anyhow::{bail, Context, Result};
+
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::prelude::*;
+
+use self::helpers::{component_name_from_line, group_by_toolchain, toolchain_name_from_line};
+use self::types::{Repotype, RustupInstallOptions, RustupQueryInfo};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Rustup {}
-impl Rustup {
-    pub fn new() -> Self {
-        Self {}
-    }
+pub struct Rustup {
+    /// The profile to install each toolchain with, keyed by toolchain name.
+    pub profiles: BTreeMap<String, String>,
+    pub extra_args: Vec<String>,
 }
-impl Default for Rustup {
-    fn default() -> Self {
-        Self::new()
+impl Rustup {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            profiles: config.rustup_profiles.clone(),
+            extra_args: config.extra_args_for("rustup"),
+        }
     }
 }
 
@@ -34,198 +37,170 @@ impl Backend for Rustup {
             switches_noconfirm: &[],
             switches_remove: &["component", "remove"],
             switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
     fn get_all_installed_packages(&self) -> Result<Packages> {
-        let toolchains_vec = self
-            .run_toolchain_command(Repotype::Toolchain.get_info_switches())
-            .context("Getting installed toolchains")?;
-
-        let toolchains: Packages = toolchains_vec
-            .iter()
-            .map(|name| ["toolchain", name].join("/").into())
-            .collect();
-
-        let components: Packages = self
-            .run_component_command(Repotype::Component.get_info_switches(), &toolchains_vec)
-            .context("Getting installed components")?
-            .iter()
-            .map(|name| ["component", name].join("/").into())
-            .collect();
-
         let mut packages = Packages::new();
 
-        packages.extend(toolchains);
-        packages.extend(components);
+        for info in self.query_installed().context("querying rustup")? {
+            packages.extend(info.to_packages());
+        }
 
         Ok(packages)
     }
 
     fn get_explicitly_installed_packages(&self) -> Result<Packages> {
         self.get_all_installed_packages()
-            .context("Getting all installed packages")
+            .context("getting all installed packages")
     }
 
     fn make_dependency(&self, _: &Packages) -> Result<()> {
-        panic!("Not supported by {}", self.backend_info().binary)
-    }
-
-    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
-        let packages = RustupPackage::from_pacdef_packages(packages)?;
-
-        let (toolchains, components) =
-            RustupPackage::sort_packages_into_toolchains_and_components(packages);
-
-        self.install_toolchains(toolchains)?;
-        self.install_components(components)?;
-
-        Ok(())
+        bail!("not supported by {}", self.backend_info().binary)
     }
 
-    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
-        let rustup_packages = RustupPackage::from_pacdef_packages(packages)?;
+    fn install_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let packages = RustupInstallOptions::from_pacdef_packages(packages, &self.profiles)?;
 
-        let (toolchains, components) =
-            RustupPackage::sort_packages_into_toolchains_and_components(rustup_packages);
-
-        let removed_toolchains = self.remove_toolchains(toolchains)?;
-
-        self.remove_components(components, removed_toolchains)?;
-
-        Ok(())
-    }
-}
+        let mut toolchains_by_profile: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for package in &packages {
+            if let RustupInstallOptions::Toolchain { toolchain, profile } = package {
+                toolchains_by_profile
+                    .entry(profile)
+                    .or_default()
+                    .push(toolchain);
+            }
+        }
 
-impl Rustup {
-    fn run_component_command(&self, args: &[&str], toolchains: &[String]) -> Result<Vec<String>> {
-        let mut val = Vec::new();
+        for (profile, mut args) in toolchains_by_profile {
+            args.extend(["--profile", profile]);
+            args.extend(self.extra_args.iter().map(String::as_str));
+            self.run_rustup(Repotype::Toolchain.get_install_switches(), &args, noconfirm)
+                .with_context(|| format!("installing toolchains with profile {profile}"))?;
+        }
 
-        for toolchain in toolchains {
-            let mut cmd = Command::new(self.backend_info().binary);
-            cmd.args(args).arg(toolchain);
+        for repotype in [Repotype::Component, Repotype::Target] {
+            let of_type = packages.iter().filter(|p| p.repotype() == repotype);
 
-            let output = String::from_utf8(cmd.output()?.stdout)?;
+            for (toolchain, names) in group_by_toolchain(of_type) {
+                let mut args = vec![toolchain];
+                args.extend(names);
+                args.extend(self.extra_args.iter().map(String::as_str));
 
-            for component in output.lines() {
-                install_components(component, toolchain, &mut val);
+                self.run_rustup(repotype.get_install_switches(), &args, noconfirm)
+                    .with_context(|| {
+                        format!("installing {}s for {toolchain}", repotype.as_str())
+                    })?;
             }
         }
 
-        Ok(val)
+        Ok(())
     }
 
-    fn run_toolchain_command(&self, args: &[&str]) -> Result<Vec<String>> {
-        let mut cmd = Command::new(self.backend_info().binary);
-        cmd.args(args);
-
-        let output = String::from_utf8(cmd.output()?.stdout)?;
+    fn remove_packages(&self, packages: &Packages, noconfirm: bool) -> Result<()> {
+        let packages = RustupInstallOptions::from_pacdef_packages(packages, &self.profiles)?;
 
-        let mut val = Vec::new();
+        let removed_toolchains: Vec<_> = packages
+            .iter()
+            .filter(|p| p.repotype() == Repotype::Toolchain)
+            .map(RustupInstallOptions::toolchain)
+            .collect();
 
-        for line in output.lines() {
-            let toolchain = line.rsplitn(5, '-').last();
-            match toolchain {
-                Some(name) => val.push(name.to_string()),
-                None => bail!("Toolchain name not provided!"),
-            }
+        if !removed_toolchains.is_empty() {
+            self.run_rustup(
+                Repotype::Toolchain.get_remove_switches(),
+                &removed_toolchains,
+                noconfirm,
+            )
+            .context("removing toolchains")?;
         }
 
-        Ok(val)
-    }
+        for repotype in [Repotype::Component, Repotype::Target] {
+            // components and targets vanish together with their toolchain
+            let of_type = packages.iter().filter(|p| {
+                p.repotype() == repotype && !removed_toolchains.contains(&p.toolchain())
+            });
 
-    fn install_toolchains(&self, toolchains: Vec<RustupPackage>) -> Result<()> {
-        if toolchains.is_empty() {
-            return Ok(());
-        }
-        let mut cmd = Command::new(self.backend_info().binary);
-        cmd.args(Repotype::Toolchain.get_install_switches());
+            for (toolchain, names) in group_by_toolchain(of_type) {
+                let mut args = vec![toolchain];
+                args.extend(names);
 
-        for toolchain in toolchains {
-            cmd.arg(&toolchain.toolchain);
+                self.run_rustup(repotype.get_remove_switches(), &args, noconfirm)
+                    .with_context(|| format!("removing {}s for {toolchain}", repotype.as_str()))?;
+            }
         }
 
-        run_external_command(cmd).context("installing toolchains")?;
-
         Ok(())
     }
+}
 
-    fn install_components(&self, components: Vec<RustupPackage>) -> Result<()> {
-        if components.is_empty() {
-            return Ok(());
+impl Rustup {
+    /// Query rustup for all installed toolchains, and their components and targets.
+    fn query_installed(&self) -> Result<Vec<RustupQueryInfo>> {
+        let mut result = vec![];
+
+        for toolchain in self.installed_toolchains()? {
+            let components = self
+                .list_for_toolchain(Repotype::Component, &toolchain)
+                .with_context(|| format!("getting installed components for {toolchain}"))?
+                .iter()
+                .filter_map(|line| component_name_from_line(line))
+                .collect();
+
+            let targets = self
+                .list_for_toolchain(Repotype::Target, &toolchain)
+                .with_context(|| format!("getting installed targets for {toolchain}"))?;
+
+            result.push(RustupQueryInfo {
+                toolchain,
+                components,
+                targets,
+            });
         }
 
-        let components_by_toolchain = group_components_by_toolchains(components);
-
-        for components_for_one_toolchain in components_by_toolchain {
-            let mut cmd = Command::new(self.backend_info().binary);
-            cmd.args(Repotype::Component.get_install_switches());
-
-            let the_toolchain = &components_for_one_toolchain
-                .first()
-                .expect("will have at least one element")
-                .toolchain;
-
-            cmd.arg(the_toolchain);
-
-            for component_package in &components_for_one_toolchain {
-                let actual_component = component_package
-                    .component
-                    .as_ref()
-                    .expect("constructor makes sure this is Some");
-
-                cmd.arg(actual_component);
-            }
-
-            run_external_command(cmd)
-                .with_context(|| format!("installing [{components_for_one_toolchain:?}]"))?;
-        }
+        Ok(result)
+    }
 
-        Ok(())
+    /// Get the names of all installed toolchains, without the host triple.
+    pub fn installed_toolchains(&self) -> Result<Vec<String>> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.args(Repotype::Toolchain.get_info_switches());
+
+        let output = run_command_for_stdout(cmd).context("getting installed toolchains")?;
+
+        output
+            .lines()
+            .map(|line| {
+                toolchain_name_from_line(line)
+                    .map(str::to_string)
+                    .with_context(|| format!("parsing toolchain name from '{line}'"))
+            })
+            .collect()
     }
 
-    fn remove_toolchains(&self, toolchains: Vec<RustupPackage>) -> Result<Vec<String>> {
-        let mut removed_toolchains = vec![];
-        if !toolchains.is_empty() {
-            let mut cmd = Command::new(self.backend_info().binary);
-            cmd.args(Repotype::Toolchain.get_remove_switches());
+    /// Get the lines rustup prints for the installed items of `repotype` in `toolchain`.
+    fn list_for_toolchain(&self, repotype: Repotype, toolchain: &str) -> Result<Vec<String>> {
+        let mut cmd = Command::new(self.backend_info().binary);
+        cmd.args(repotype.get_info_switches()).arg(toolchain);
 
-            for toolchain_package in &toolchains {
-                let name = toolchain_package.toolchain.as_str();
-                cmd.arg(name);
-                removed_toolchains.push(name.to_string());
-            }
+        let output = run_command_for_stdout(cmd)?;
 
-            run_external_command(cmd)
-                .with_context(|| format!("removing toolchains [{toolchains:?}]"))?;
-        }
-        Ok(removed_toolchains)
+        Ok(output.lines().map(|line| line.trim().to_string()).collect())
     }
 
-    fn remove_components(
-        &self,
-        components: Vec<RustupPackage>,
-        removed_toolchains: Vec<String>,
-    ) -> Result<()> {
-        for component_package in components {
-            let mut cmd = Command::new(self.backend_info().binary);
-            cmd.args(Repotype::Component.get_remove_switches());
-
-            if toolchain_of_component_was_already_removed(&removed_toolchains, &component_package) {
-                continue;
-            }
+    fn run_rustup(&self, switches: Switches, args: &[&str], noconfirm: bool) -> Result<()> {
+        let backend_info = self.backend_info();
 
-            cmd.arg(&component_package.toolchain);
-            cmd.arg(
-                component_package
-                    .component
-                    .as_ref()
-                    .expect("the constructor ensures this cannot be None"),
-            );
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.args(switches).args(args);
 
-            run_external_command(cmd)
-                .with_context(|| format!("removing component {component_package:?}"))?;
+        // rustup does not prompt at the moment, so this is empty. It is honoured anyway
+        // so that all backends treat `noconfirm` the same way.
+        if noconfirm {
+            cmd.args(backend_info.switches_noconfirm);
         }
-        Ok(())
+
+        run_external_command(cmd)
     }
 }
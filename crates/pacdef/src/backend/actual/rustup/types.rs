@@ -1,25 +1,16 @@
+use std::collections::BTreeMap;
+
 use anyhow::{bail, Context, Result};
 
 use crate::prelude::*;
 
-#[derive(Debug)]
+/// The kind of item rustup manages. This is stored as the repository of a pacdef
+/// package, i.e. the part before the first `/`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Repotype {
     Toolchain,
     Component,
-}
-
-/// A package as used exclusively in the rustup backend. Contrary to other packages, this does not
-/// have an (optional) repository and a name, but is either a component or a toolchain, has a
-/// toolchain version, and if it is a toolchain also a name.
-#[derive(Debug)]
-pub struct RustupPackage {
-    /// Whether it is a toolchain or a component.
-    pub repotype: Repotype,
-    /// The name of the toolchain this belongs to (stable, nightly, a pinned version)
-    pub toolchain: String,
-    /// If it is a toolchain, it will not have a component name.
-    /// If it is a component, this will be its name.
-    pub component: Option<String>,
+    Target,
 }
 
 impl Repotype {
@@ -31,105 +22,166 @@ impl Repotype {
         let result = match value {
             "toolchain" => Self::Toolchain,
             "component" => Self::Component,
-            _ => bail!("{} is neither toolchain nor component", value),
+            "target" => Self::Target,
+            _ => bail!("{} is neither toolchain, component nor target", value),
         };
         Ok(result)
     }
 
-    pub fn get_install_switches(self) -> Switches {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Toolchain => "toolchain",
+            Self::Component => "component",
+            Self::Target => "target",
+        }
+    }
+
+    pub const fn get_install_switches(self) -> Switches {
         match self {
             Self::Toolchain => &["toolchain", "install"],
             Self::Component => &["component", "add", "--toolchain"],
+            Self::Target => &["target", "add", "--toolchain"],
         }
     }
 
-    pub fn get_remove_switches(self) -> Switches {
+    pub const fn get_remove_switches(self) -> Switches {
         match self {
             Self::Toolchain => &["toolchain", "uninstall"],
             Self::Component => &["component", "remove", "--toolchain"],
+            Self::Target => &["target", "remove", "--toolchain"],
         }
     }
 
-    pub fn get_info_switches(self) -> Switches {
+    pub const fn get_info_switches(self) -> Switches {
         match self {
             Self::Toolchain => &["toolchain", "list"],
             Self::Component => &["component", "list", "--installed", "--toolchain"],
+            Self::Target => &["target", "list", "--installed", "--toolchain"],
         }
     }
 }
 
-impl RustupPackage {
-    /// Creates a new [`RustupPackage`].
-    ///
-    /// # Panics
-    ///
-    /// Panics if
-    /// - repotype is Toolchain and component is Some, or
-    /// - repotype is Component and component is None.
-    fn new(repotype: Repotype, toolchain: String, component: Option<String>) -> Self {
-        match repotype {
-            Repotype::Toolchain => assert!(component.is_none()),
-            Repotype::Component => assert!(component.is_some()),
-        };
+/// The profile rustup uses when none is configured for a toolchain.
+const DEFAULT_PROFILE: &str = "default";
+
+/// A package as used exclusively in the rustup backend. Contrary to other packages, this does not
+/// have an (optional) repository and a name, but is either a toolchain, or a component or target
+/// that belongs to a toolchain.
+///
+/// In group files these are written as `toolchain/<name>`, `component/<toolchain>/<name>` and
+/// `target/<toolchain>/<triple>`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RustupInstallOptions {
+    Toolchain {
+        toolchain: String,
+        profile: String,
+    },
+    Component {
+        toolchain: String,
+        component: String,
+    },
+    Target {
+        toolchain: String,
+        target: String,
+    },
+}
 
-        Self {
-            repotype,
-            toolchain,
-            component,
+impl RustupInstallOptions {
+    /// The toolchain this package is or belongs to.
+    pub fn toolchain(&self) -> &str {
+        match self {
+            Self::Toolchain { toolchain, .. }
+            | Self::Component { toolchain, .. }
+            | Self::Target { toolchain, .. } => toolchain,
         }
     }
 
-    pub fn sort_packages_into_toolchains_and_components(
-        packages: Vec<Self>,
-    ) -> (Vec<Self>, Vec<Self>) {
-        let mut toolchains = vec![];
-        let mut components = vec![];
-
-        for package in packages {
-            match package.repotype {
-                Repotype::Toolchain => toolchains.push(package),
-                Repotype::Component => components.push(package),
-            }
+    pub const fn repotype(&self) -> Repotype {
+        match self {
+            Self::Toolchain { .. } => Repotype::Toolchain,
+            Self::Component { .. } => Repotype::Component,
+            Self::Target { .. } => Repotype::Target,
         }
-
-        (toolchains, components)
     }
 
-    pub fn from_pacdef_packages(packages: &Packages) -> Result<Vec<Self>> {
+    /// Convert pacdef packages to rustup packages. Toolchains get the profile
+    /// configured for them in `profiles`, or the `default` profile.
+    pub fn from_pacdef_packages(
+        packages: &Packages,
+        profiles: &BTreeMap<String, String>,
+    ) -> Result<Vec<Self>> {
         let mut result = vec![];
 
         for package in packages {
-            let rustup_package = Self::try_from(package).with_context(|| {
-                format!(
-                    "converting pacdef package {} to rustup package",
-                    package.name
-                )
+            let options = Self::from_package(package, profiles).with_context(|| {
+                format!("converting pacdef package {package} to rustup package")
             })?;
-            result.push(rustup_package);
+            result.push(options);
         }
 
         Ok(result)
     }
-}
 
-impl TryFrom<&Package> for RustupPackage {
-    type Error = anyhow::Error;
-
-    fn try_from(package: &Package) -> Result<Self> {
+    fn from_package(package: &Package, profiles: &BTreeMap<String, String>) -> Result<Self> {
         let repo = package.repo.as_ref().context("getting repo from package")?;
         let repotype = Repotype::try_from(repo).context("getting repotype")?;
 
-        let (toolchain, component) = match repotype {
-            Repotype::Toolchain => (package.name.to_string(), None),
-            Repotype::Component => {
-                let (toolchain, component) = package
-                    .name
-                    .split_once('/')
-                    .context("splitting package into toolchain and component")?;
-                (toolchain.to_string(), Some(component.into()))
+        if repotype == Repotype::Toolchain {
+            let profile = profiles
+                .get(&package.name)
+                .map_or(DEFAULT_PROFILE, String::as_str);
+
+            return Ok(Self::Toolchain {
+                toolchain: package.name.clone(),
+                profile: profile.to_string(),
+            });
+        }
+
+        let (toolchain, name) = package
+            .name
+            .split_once('/')
+            .with_context(|| format!("splitting package into toolchain and {}", repo))?;
+        let toolchain = toolchain.to_string();
+        let name = name.to_string();
+
+        Ok(match repotype {
+            Repotype::Component => Self::Component {
+                toolchain,
+                component: name,
+            },
+            _ => Self::Target {
+                toolchain,
+                target: name,
+            },
+        })
+    }
+}
+
+/// Everything rustup reports as installed for a single toolchain.
+#[derive(Debug)]
+pub struct RustupQueryInfo {
+    pub toolchain: String,
+    pub components: Vec<String>,
+    pub targets: Vec<String>,
+}
+
+impl RustupQueryInfo {
+    /// Convert the query result to pacdef packages, using the same naming scheme as in
+    /// the group files.
+    pub fn to_packages(&self) -> Packages {
+        let mut packages = Packages::new();
+
+        packages.insert(format!("{}/{}", Repotype::Toolchain.as_str(), self.toolchain).into());
+
+        for (repotype, names) in [
+            (Repotype::Component, &self.components),
+            (Repotype::Target, &self.targets),
+        ] {
+            for name in names {
+                packages.insert(format!("{}/{}/{name}", repotype.as_str(), self.toolchain).into());
             }
-        };
+        }
 
-        Ok(Self::new(repotype, toolchain, component))
+        packages
     }
 }
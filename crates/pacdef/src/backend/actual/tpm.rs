@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::backend::actual::vim::{clone_plugin, get_github_url, list_plugin_dirs};
+use crate::cmd::{run_command_for_stdout, run_external_command};
+use crate::grouping::options::PackageOptions;
+use crate::path::get_home_dir;
+use crate::prelude::*;
+
+/// Tmux plugins in the plugin dir of the tmux plugin manager (tpm), cloned with git.
+/// Each plugin is a directory named like the package. Packages can be written as
+/// `<owner>/<repo>` to clone them from GitHub.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tpm {
+    /// The directory the plugins are cloned into. Defaults to `~/.tmux/plugins`.
+    pub plugin_dir: Option<String>,
+    /// The git URL to clone each plugin from, keyed by plugin name.
+    pub urls: BTreeMap<String, String>,
+    pub extra_args: Vec<String>,
+}
+impl Tpm {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            plugin_dir: config.tpm_plugin_dir.clone(),
+            urls: config.tpm_plugin_urls.clone(),
+            extra_args: config.extra_args_for("tpm"),
+        }
+    }
+
+    fn get_plugin_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = &self.plugin_dir {
+            return Ok(dir.into());
+        }
+
+        let mut dir = get_home_dir().context("getting home dir")?;
+        dir.push(".tmux/plugins");
+        Ok(dir)
+    }
+
+    /// The URL from the config, or else the GitHub repository `<owner>/<repo>`.
+    fn get_url(&self, package: &Package) -> Result<String> {
+        if let Some(url) = self.urls.get(&package.name) {
+            return Ok(url.clone());
+        }
+
+        get_github_url(package).with_context(|| {
+            format!("no URL for tmux plugin {package}, write it as <owner>/<repo> or set it in tpm_plugin_urls")
+        })
+    }
+}
+
+impl Backend for Tpm {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "git".to_string(),
+            section: "tpm",
+            switches_info: &["log", "--max-count=1"],
+            switches_install: &["clone", "--depth", "1"],
+            switches_noconfirm: &[],
+            switches_remove: &[],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        list_plugin_dirs(&self.get_plugin_dir()?)
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        self.get_all_installed_packages()
+            .context("getting all installed packages")
+    }
+
+    /// Clone each plugin on its own. The plugin dir is created if it does not exist
+    /// yet, e.g. before tpm itself is installed.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let dir = self.get_plugin_dir()?;
+        let mut cmd = Command::new("mkdir");
+        cmd.arg("--parents").arg(&dir);
+        run_external_command(cmd).with_context(|| format!("creating {}", dir.to_string_lossy()))?;
+
+        for package in packages {
+            let url = self.get_url(package)?;
+            clone_plugin(&dir, package, &url, None, &self.extra_args)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let dir = self.get_plugin_dir()?;
+
+        let mut cmd = Command::new("rm");
+        cmd.arg("--recursive").arg("--force");
+        cmd.args(packages.iter().map(|package| dir.join(&package.name)));
+        run_external_command(cmd).context("removing tmux plugins")
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let backend_info = self.backend_info();
+        let dir = self.get_plugin_dir()?;
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.arg("-C").arg(dir.join(&package.name));
+        cmd.args(backend_info.switches_info);
+
+        run_external_command(cmd)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+
+    /// The URL each plugin was cloned from, as the plugin dir only records the name.
+    fn get_install_options(&self, packages: &Packages) -> Result<InstallOptions> {
+        let dir = self.get_plugin_dir()?;
+        let mut result = InstallOptions::new();
+
+        for package in packages {
+            let mut cmd = Command::new("git");
+            cmd.arg("-C").arg(dir.join(&package.name));
+            cmd.args(["remote", "get-url", "origin"]);
+
+            let Ok(url) = run_command_for_stdout(cmd) else {
+                log::warn!(backend = "tpm", package:% = package; "could not get the URL of tmux plugin {package}");
+                continue;
+            };
+
+            let options = PackageOptions {
+                url: Some(url.trim().to_string()),
+                ..PackageOptions::default()
+            };
+            result.insert(package.name.clone(), options);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn install_clones_plugins() {
+        let dir = std::env::temp_dir().join("pacdef-tpm-test");
+        let tpm = Tpm {
+            plugin_dir: Some(dir.to_string_lossy().into()),
+            urls: [("tmux-yank".into(), "https://example.org/yank.git".into())].into(),
+            extra_args: vec![],
+        };
+        let packages: Packages = [
+            Package::from("tmux-plugins/tmux-sensible"),
+            Package::from("tmux-yank"),
+        ]
+        .into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || tpm.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        let dir = dir.to_string_lossy();
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                format!("mkdir --parents {dir}"),
+                format!("git clone --depth 1 https://github.com/tmux-plugins/tmux-sensible.git {dir}/tmux-sensible"),
+                format!("git clone --depth 1 https://example.org/yank.git {dir}/tmux-yank"),
+            ]
+        );
+    }
+}
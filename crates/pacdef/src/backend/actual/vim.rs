@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::fs::read_dir;
+use std::io::ErrorKind::NotFound;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cmd::run_external_command;
+use crate::path::get_xdg_data_home;
+use crate::prelude::*;
+
+/// Vim and neovim plugins, cloned with git into a pack directory. Each plugin is a
+/// directory named like the package. Packages can be written as `<owner>/<repo>` to
+/// clone them from GitHub.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VimPlugins {
+    /// The directory the plugins are cloned into. Defaults to the start dir of the
+    /// `pacdef` package in the neovim data dir.
+    pub plugin_dir: Option<String>,
+    /// Shell command to run after plugins were installed or removed.
+    pub manager_command: Option<String>,
+    /// The git URL to clone each plugin from, keyed by plugin name.
+    pub urls: BTreeMap<String, String>,
+    /// The branch to clone for each plugin, keyed by plugin name.
+    pub branches: BTreeMap<String, String>,
+    pub extra_args: Vec<String>,
+}
+impl VimPlugins {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            plugin_dir: config.vim_plugin_dir.clone(),
+            manager_command: config.vim_manager_command.clone(),
+            urls: config.vim_plugin_urls.clone(),
+            branches: config.vim_plugin_branches.clone(),
+            extra_args: config.extra_args_for("vim"),
+        }
+    }
+
+    fn get_plugin_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = &self.plugin_dir {
+            return Ok(dir.into());
+        }
+
+        let mut dir = get_xdg_data_home().context("getting XDG_DATA_HOME")?;
+        dir.push("nvim/site/pack/pacdef/start");
+        Ok(dir)
+    }
+
+    /// The URL from the config, or else the GitHub repository `<owner>/<repo>`.
+    fn get_url(&self, package: &Package) -> Result<String> {
+        if let Some(url) = self.urls.get(&package.name) {
+            return Ok(url.clone());
+        }
+
+        get_github_url(package).with_context(|| {
+            format!("no URL for vim plugin {package}, write it as <owner>/<repo> or set it in vim_plugin_urls")
+        })
+    }
+
+    /// Let the plugin manager pick up the changed plugins. A failing command is only
+    /// a warning, as the plugins themselves were changed already.
+    fn run_manager_command(&self) {
+        let Some(command) = &self.manager_command else {
+            return;
+        };
+
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+
+        if let Err(error) = run_external_command(cmd) {
+            log::warn!("vim plugin manager command failed: {error}");
+        }
+    }
+}
+
+/// The URL of the GitHub repository of a plugin written as `<owner>/<repo>`.
+pub fn get_github_url(package: &Package) -> Option<String> {
+    let owner = package.repo.as_ref()?;
+    Some(format!("https://github.com/{owner}/{}.git", package.name))
+}
+
+/// Every directory in `dir` is a plugin. If `dir` does not exist, no plugins are
+/// installed.
+pub fn list_plugin_dirs(dir: &Path) -> Result<Packages> {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == NotFound => return Ok(Packages::new()),
+        Err(err) => bail!(err),
+    };
+
+    let mut result = Packages::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            result.insert(Package::from(entry.file_name().to_string_lossy().as_ref()));
+        }
+    }
+
+    Ok(result)
+}
+
+/// Clone the plugin from `url` with git into a directory in `dir` named like the
+/// `package`.
+pub fn clone_plugin(
+    dir: &Path,
+    package: &Package,
+    url: &str,
+    branch: Option<&String>,
+    extra_args: &[String],
+) -> Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.args(["clone", "--depth", "1"]);
+    if let Some(branch) = branch {
+        cmd.args(["--branch", branch]);
+    }
+    cmd.args(extra_args);
+    cmd.arg(url).arg(dir.join(&package.name));
+
+    run_external_command(cmd).with_context(|| format!("cloning plugin {package}"))
+}
+
+impl Backend for VimPlugins {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: "git".to_string(),
+            section: "vim",
+            switches_info: &["log", "--max-count=1"],
+            switches_install: &["clone", "--depth", "1"],
+            switches_noconfirm: &[],
+            switches_remove: &[],
+            switches_make_dependency: None,
+            extra_install_args: self.extra_args.clone(),
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        list_plugin_dirs(&self.get_plugin_dir()?)
+    }
+
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        self.get_all_installed_packages()
+            .context("getting all installed packages")
+    }
+
+    /// Clone each plugin on its own, then run the manager command once.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let dir = self.get_plugin_dir()?;
+
+        for package in packages {
+            let url = self.get_url(package)?;
+            let branch = self.branches.get(&package.name);
+            clone_plugin(&dir, package, &url, branch, &self.extra_args)?;
+        }
+
+        self.run_manager_command();
+        Ok(())
+    }
+
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let dir = self.get_plugin_dir()?;
+
+        let mut cmd = Command::new("rm");
+        cmd.arg("--recursive").arg("--force");
+        cmd.args(packages.iter().map(|package| dir.join(&package.name)));
+        run_external_command(cmd).context("removing vim plugins")?;
+
+        self.run_manager_command();
+        Ok(())
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let backend_info = self.backend_info();
+        let dir = self.get_plugin_dir()?;
+
+        let mut cmd = Command::new(backend_info.binary);
+        cmd.arg("-C").arg(dir.join(&package.name));
+        cmd.args(backend_info.switches_info);
+
+        run_external_command(cmd)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn install_clones_plugins() {
+        let vim = VimPlugins {
+            plugin_dir: Some("/pack/start".into()),
+            manager_command: None,
+            urls: [("lualine".into(), "https://example.org/lualine.git".into())].into(),
+            branches: [("lualine".into(), "stable".into())].into(),
+            extra_args: vec![],
+        };
+        let packages: Packages = [
+            Package::from("tpope/vim-fugitive"),
+            Package::from("lualine"),
+        ]
+        .into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || vim.install_packages(&packages, false))
+            .expect("fake runner does not fail");
+
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                "git clone --depth 1 --branch stable https://example.org/lualine.git /pack/start/lualine",
+                "git clone --depth 1 https://github.com/tpope/vim-fugitive.git /pack/start/vim-fugitive",
+            ]
+        );
+    }
+}
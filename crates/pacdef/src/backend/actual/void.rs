@@ -8,15 +8,14 @@ use crate::cmd::run_external_command;
 use crate::prelude::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Void {}
-impl Void {
-    pub fn new() -> Self {
-        Self {}
-    }
+pub struct Void {
+    pub extra_args: Vec<String>,
 }
-impl Default for Void {
-    fn default() -> Self {
-        Self::new()
+impl Void {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            extra_args: config.extra_args_for("void"),
+        }
     }
 }
 
@@ -35,6 +34,7 @@ impl Backend for Void {
             switches_noconfirm: &["-y"],
             switches_remove: &["-R"],
             switches_make_dependency: Some(&["-m", "auto"]),
+            extra_install_args: self.extra_args.clone(),
         }
     }
 
@@ -89,6 +89,8 @@ impl Backend for Void {
             cmd.args(backend_info.switches_noconfirm);
         }
 
+        cmd.args(&backend_info.extra_install_args);
+
         for p in packages {
             cmd.arg(format!("{p}"));
         }
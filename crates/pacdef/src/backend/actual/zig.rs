@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::fs::read_dir;
+use std::io::ErrorKind::NotFound;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::backend::actual::vim::get_github_url;
+use crate::cmd::run_external_command;
+use crate::grouping::options::PackageOptions;
+use crate::path::get_xdg_data_home;
+use crate::prelude::*;
+
+/// The default command to install a tool: clone its repository, build it in release
+/// mode and put its executables into the bin dir.
+pub const DEFAULT_INSTALL_COMMAND: &str = r#"set -e
+dir=$(mktemp -d)
+trap 'rm -rf "$dir"' EXIT
+git clone --quiet "$PACDEF_SOURCE" "$dir"
+cd "$dir"
+if [ -n "$PACDEF_REV" ]; then git checkout --quiet "$PACDEF_REV"; fi
+"$PACDEF_MANAGER" build -Doptimize=ReleaseSafe --prefix-exe-dir "$PACDEF_BIN_DIR""#;
+
+/// Tools written in Zig, installed by a configurable command into a bin dir. Zig
+/// has no standard way to install tools globally, so each executable in the bin dir
+/// is a package. Packages can be written as `<owner>/<repo>` to build them from
+/// GitHub.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Zig {
+    /// The binary of the package manager, `zig` by default.
+    pub manager: String,
+    /// The shell command that installs a tool. Defaults to
+    /// [`DEFAULT_INSTALL_COMMAND`].
+    pub install_command: Option<String>,
+    /// The directory the executables are installed into. Defaults to
+    /// `$XDG_DATA_HOME/zig/bin`.
+    pub bin_dir: Option<String>,
+    /// The URL to install each tool from, keyed by tool name.
+    pub urls: BTreeMap<String, String>,
+    /// The git ref to install each tool from, keyed by tool name.
+    pub revs: BTreeMap<String, String>,
+}
+impl Zig {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            manager: config.zig_manager.clone(),
+            install_command: config.zig_install_command.clone(),
+            bin_dir: config.zig_bin_dir.clone(),
+            urls: config.zig_urls.clone(),
+            revs: config.zig_revs.clone(),
+        }
+    }
+
+    fn get_bin_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = &self.bin_dir {
+            return Ok(dir.into());
+        }
+
+        let mut dir = get_xdg_data_home().context("getting XDG_DATA_HOME")?;
+        dir.push("zig/bin");
+        Ok(dir)
+    }
+
+    /// The URL from the config, or else the GitHub repository `<owner>/<repo>`.
+    fn get_url(&self, package: &Package) -> Result<String> {
+        if let Some(url) = self.urls.get(&package.name) {
+            return Ok(url.clone());
+        }
+
+        get_github_url(package).with_context(|| {
+            format!(
+                "no URL for zig tool {package}, write it as <owner>/<repo> or set it in zig_urls"
+            )
+        })
+    }
+}
+
+impl Backend for Zig {
+    fn backend_info(&self) -> BackendInfo {
+        BackendInfo {
+            binary: self.manager.clone(),
+            section: "zig",
+            switches_info: &["--version"],
+            switches_install: &[],
+            switches_noconfirm: &[],
+            switches_remove: &[],
+            switches_make_dependency: None,
+            extra_install_args: vec![],
+        }
+    }
+
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        self.get_explicitly_installed_packages()
+    }
+
+    /// Every file in the bin dir is a tool. If the bin dir does not exist, no tools
+    /// are installed.
+    fn get_explicitly_installed_packages(&self) -> Result<Packages> {
+        let dir = self.get_bin_dir()?;
+        let entries = match read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == NotFound => return Ok(Packages::new()),
+            Err(err) => bail!(err),
+        };
+
+        let mut result = Packages::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                result.insert(Package::from(entry.file_name().to_string_lossy().as_ref()));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Run the install command once per tool. It gets the tool in `PACDEF_PACKAGE`,
+    /// its URL in `PACDEF_SOURCE`, the git ref in `PACDEF_REV`, the bin dir in
+    /// `PACDEF_BIN_DIR` and the package manager in `PACDEF_MANAGER`.
+    fn install_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let dir = self.get_bin_dir()?;
+        let mut cmd = Command::new("mkdir");
+        cmd.arg("--parents").arg(&dir);
+        run_external_command(cmd).with_context(|| format!("creating {}", dir.to_string_lossy()))?;
+
+        let command = self
+            .install_command
+            .as_deref()
+            .unwrap_or(DEFAULT_INSTALL_COMMAND);
+
+        for package in packages {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(command);
+            cmd.env("PACDEF_PACKAGE", &package.name)
+                .env("PACDEF_SOURCE", self.get_url(package)?)
+                .env(
+                    "PACDEF_REV",
+                    self.revs.get(&package.name).map_or("", String::as_str),
+                )
+                .env("PACDEF_BIN_DIR", &dir)
+                .env("PACDEF_MANAGER", &self.manager);
+
+            run_external_command(cmd).with_context(|| format!("installing zig tool {package}"))?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_packages(&self, packages: &Packages, _: bool) -> Result<()> {
+        let dir = self.get_bin_dir()?;
+
+        let mut cmd = Command::new("rm");
+        cmd.arg("--force");
+        cmd.args(packages.iter().map(|package| dir.join(&package.name)));
+        run_external_command(cmd).context("removing zig tools")
+    }
+
+    fn show_package_info(&self, package: &Package) -> Result<()> {
+        let mut cmd = Command::new(self.get_bin_dir()?.join(&package.name));
+        cmd.args(self.backend_info().switches_info);
+
+        run_external_command(cmd)
+    }
+
+    fn make_dependency(&self, _: &Packages) -> Result<()> {
+        panic!("not supported by {}", self.backend_info().binary)
+    }
+
+    /// The configured URL and git ref of each tool, as the bin dir only records
+    /// the name.
+    fn get_install_options(&self, packages: &Packages) -> Result<InstallOptions> {
+        let mut result = InstallOptions::new();
+
+        for package in packages {
+            let options = PackageOptions {
+                url: self.urls.get(&package.name).cloned(),
+                rev: self.revs.get(&package.name).cloned(),
+                ..PackageOptions::default()
+            };
+            if options.url.is_some() || options.rev.is_some() {
+                result.insert(package.name.clone(), options);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cmd::{with_runner, FakeRunner};
+
+    #[test]
+    fn install_and_remove_tools() {
+        let dir = std::env::temp_dir().join("pacdef-zig-test");
+        let zig = Zig {
+            manager: "zig".into(),
+            install_command: Some("install-tool".into()),
+            bin_dir: Some(dir.to_string_lossy().into()),
+            urls: [("zls".into(), "https://example.org/zls.git".into())].into(),
+            revs: BTreeMap::new(),
+        };
+        let packages: Packages = [Package::from("zls"), Package::from("someone/ztool")].into();
+
+        let runner = Rc::new(FakeRunner::default());
+        with_runner(runner.clone(), || {
+            zig.install_packages(&packages, false)?;
+            zig.remove_packages(&packages, false)
+        })
+        .expect("fake runner does not fail");
+
+        let dir = dir.to_string_lossy();
+        assert_eq!(
+            *runner.commands.borrow(),
+            [
+                format!("mkdir --parents {dir}"),
+                format!("PACDEF_BIN_DIR={dir} PACDEF_MANAGER=zig PACDEF_PACKAGE=zls PACDEF_REV='' PACDEF_SOURCE=https://example.org/zls.git sh -c install-tool"),
+                format!("PACDEF_BIN_DIR={dir} PACDEF_MANAGER=zig PACDEF_PACKAGE=ztool PACDEF_REV='' PACDEF_SOURCE=https://github.com/someone/ztool.git sh -c install-tool"),
+                format!("rm --force {dir}/zls {dir}/ztool"),
+            ]
+        );
+
+        assert!(zig.get_url(&Package::from("ztool")).is_err());
+    }
+}
@@ -1,13 +1,55 @@
 use std::collections::BTreeMap;
+use std::fmt::Display;
 use std::process::Command;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use crate::cmd::run_external_command;
+use crate::grouping::options::PackageOptions;
 use crate::prelude::*;
 
 pub type Switches = &'static [&'static str];
 pub type Text = &'static str;
+/// Installed package versions, keyed by package name.
+pub type Versions = BTreeMap<String, String>;
+/// The options installed packages were installed with, keyed by package name.
+pub type InstallOptions = BTreeMap<String, PackageOptions>;
+
+/// A file of an installed package that differs from the file the package shipped
+/// with.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VerifyIssue {
+    pub package: Package,
+    /// The affected file and what differs, as reported by the package manager.
+    pub description: String,
+}
+
+/// A package that was installed or removed at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub package: Package,
+    pub action: HistoryAction,
+    /// The time of the change, as written by the package manager.
+    pub timestamp: String,
+}
+
+/// What happened to a package in a [`HistoryEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// only the Arch backend has a history
+#[cfg_attr(not(feature = "arch"), allow(dead_code))]
+pub enum HistoryAction {
+    Installed,
+    Removed,
+}
+
+impl Display for HistoryAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Installed => write!(f, "installed"),
+            Self::Removed => write!(f, "removed"),
+        }
+    }
+}
 
 /// A bundle of small of bits of info associated with a backend.
 pub struct BackendInfo {
@@ -21,13 +63,17 @@ pub struct BackendInfo {
     /// CLI switches for the package manager to install packages.
     pub switches_install: Switches,
     /// CLI switches for the package manager to perform `sync` and `clean` without
-    /// confirmation.
+    /// confirmation. Backends whose package manager never prompts leave this empty,
+    /// but still pass it on whenever `noconfirm` is requested.
     pub switches_noconfirm: Switches,
     /// CLI switches for the package manager to remove packages.
     pub switches_remove: Switches,
     /// CLI switches for the package manager to mark packages as
     /// dependency. This is not supported by all package managers.
     pub switches_make_dependency: Option<Switches>,
+    /// Additional arguments from the config that are appended to every install
+    /// command, but not to any other command.
+    pub extra_install_args: Vec<String>,
 }
 
 /// The trait of a struct that is used as a backend.
@@ -40,6 +86,13 @@ pub trait Backend {
         self.backend_info().switches_make_dependency.is_some()
     }
 
+    /// Whether the package manager may prompt on the terminal while installing or
+    /// removing packages, even with `noconfirm`. Such backends are never run with
+    /// their output captured.
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
     /// Get all packages that are installed in the system.
     ///
     /// # Errors
@@ -56,6 +109,215 @@ pub trait Backend {
     /// cannot be determined.
     fn get_explicitly_installed_packages(&self) -> Result<Packages>;
 
+    /// Get installed packages whose install options differ from the options pacdef
+    /// would install them with. Managed packages among these are reinstalled by
+    /// `sync`. Backends without install options return no packages.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the installed packages cannot be
+    /// determined.
+    fn get_drifted_packages(&self) -> Result<Packages> {
+        Ok(Packages::new())
+    }
+
+    /// Get the installed packages that replace any of the `packages`, which are
+    /// managed but not installed under their name, e.g. because they were renamed.
+    /// The result is keyed by the replaced package. Backends without replacements
+    /// return an empty map.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn get_replacements(&self, _packages: &Packages) -> Result<BTreeMap<Package, Package>> {
+        Ok(BTreeMap::new())
+    }
+
+    /// Get the packages among `packages`, which are managed but not installed under
+    /// their name, that are installed without the package manager keeping track of
+    /// them. These count as installed. Backends that track every package return no
+    /// packages.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the installed files cannot be read.
+    fn get_untracked_packages(&self, _packages: &Packages) -> Result<Packages> {
+        Ok(Packages::new())
+    }
+
+    /// Get the installed packages that are built together with any of the
+    /// `packages`, which are managed, keyed by the managed package. A managed package
+    /// covers these siblings, which are therefore not unmanaged. Backends without
+    /// such packages return an empty map.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn get_split_siblings(&self, _packages: &Packages) -> Result<BTreeMap<Package, Packages>> {
+        Ok(BTreeMap::new())
+    }
+
+    /// Get the packages among `packages` that the package manager itself is
+    /// configured to ignore. pacdef neither installs nor removes them, so as not to
+    /// work against that configuration. Backends without such a setting return no
+    /// packages.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn get_ignored_packages(&self, _packages: &Packages) -> Result<Packages> {
+        Ok(Packages::new())
+    }
+
+    /// Get the packages that would be removed in addition to `packages`, because
+    /// the backend also removes dependencies that are not needed anymore.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn get_removal_cascade(&self, _packages: &Packages) -> Result<Packages> {
+        Ok(Packages::new())
+    }
+
+    /// Get all packages that were installed as a dependency, but are not required by
+    /// any other package anymore. Backends without such a concept return no packages.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn list_orphans(&self) -> Result<Packages> {
+        Ok(Packages::new())
+    }
+
+    /// Get the installed version of every package, keyed by package name. Backends
+    /// that cannot determine versions return an empty map.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn get_installed_versions(&self) -> Result<Versions> {
+        Ok(Versions::new())
+    }
+
+    /// Get the installed packages whose installed version was withdrawn upstream,
+    /// e.g. yanked from crates.io, with that version, keyed by package name. This
+    /// queries the network. Backends without this information return an empty map.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the installed versions or their
+    /// status cannot be determined, e.g. when offline.
+    fn get_yanked_packages(&self) -> Result<Versions> {
+        Ok(Versions::new())
+    }
+
+    /// Check the files of all installed packages against the files the packages
+    /// shipped with. Backends that cannot verify packages return no issues.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn verify_packages(&self) -> Result<Vec<VerifyIssue>> {
+        Ok(vec![])
+    }
+
+    /// Get all packages that were installed or removed at or after `since`, in
+    /// seconds since the epoch, oldest first. Backends without a history return no
+    /// changes.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the history cannot be read.
+    fn get_history(&self, _since: i64) -> Result<Vec<HistoryEntry>> {
+        Ok(vec![])
+    }
+
+    /// Recover the options that the installed `packages` were installed with, keyed
+    /// by package name. Packages that were installed with the default options are
+    /// left out, and so are all packages of backends without install options.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager cannot be queried.
+    fn get_install_options(&self, _packages: &Packages) -> Result<InstallOptions> {
+        Ok(InstallOptions::new())
+    }
+
+    /// Return the package that makes the package manager install exactly `version`
+    /// of `package`, or `None` if the backend cannot install a specific version.
+    fn pin_version(&self, _package: &Package, _version: &str) -> Option<Package> {
+        None
+    }
+
+    /// Remove cached package files that are not needed anymore. If `noconfirm` is
+    /// `true`, pass the corresponding switch to the package manager. Backends without
+    /// a cache do nothing.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager fails.
+    fn clean_cache(&self, _noconfirm: bool) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether [`Backend::download_packages`] is supported.
+    fn supports_download(&self) -> bool {
+        false
+    }
+
+    /// Download the packages into the cache of the package manager without
+    /// installing them. If `noconfirm` is `true`, pass the corresponding switch to the
+    /// package manager.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager fails, or if the
+    /// backend does not support downloading packages.
+    fn download_packages(&self, _packages: &Packages, _noconfirm: bool) -> Result<()> {
+        bail!("{} cannot download packages", self.backend_info().section)
+    }
+
+    /// Whether [`Backend::reinstall_packages`] is supported.
+    fn supports_reinstall(&self) -> bool {
+        false
+    }
+
+    /// Install the packages again, even though they are installed already. If
+    /// `noconfirm` is `true`, pass the corresponding switch to the package manager.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager fails, or if the
+    /// backend does not support reinstalling packages.
+    fn reinstall_packages(&self, _packages: &Packages, _noconfirm: bool) -> Result<()> {
+        bail!("{} cannot reinstall packages", self.backend_info().section)
+    }
+
+    /// Get the tree of the dependencies of the installed `package`, ready to be
+    /// printed.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager fails, or if the
+    /// backend cannot show dependency trees.
+    fn dependency_tree(&self, _package: &Package) -> Result<String> {
+        bail!(
+            "{} cannot show dependency trees",
+            self.backend_info().section
+        )
+    }
+
+    /// Upgrade all installed packages of the backend, except the `held` packages. If
+    /// `noconfirm` is `true`, pass the corresponding switch to the package manager.
+    /// Backends that cannot upgrade all packages at once do nothing.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the package manager fails.
+    fn upgrade_system(&self, _noconfirm: bool, _held: &Packages) -> Result<()> {
+        Ok(())
+    }
+
     /// Assign each of the packages to an individual group by editing the
     /// group files.
     ///
@@ -69,10 +331,10 @@ pub trait Backend {
             group_package_map.entry(group).or_default().insert(package);
         }
 
-        let section_header = format!("[{}]", self.backend_info().section);
+        let section = self.backend_info().section;
 
         for (group, packages) in group_package_map {
-            group.save_packages(&section_header, &packages)?;
+            group.save_packages(section, &packages)?;
         }
 
         Ok(())
@@ -95,6 +357,8 @@ pub trait Backend {
             cmd.args(backend_info.switches_noconfirm);
         }
 
+        cmd.args(&backend_info.extra_install_args);
+
         for p in packages {
             cmd.arg(format!("{p}"));
         }
@@ -165,3 +429,25 @@ pub trait Backend {
         run_external_command(cmd)
     }
 }
+
+/// Parse the output of a package manager that prints one `<name> <version>` pair
+/// per line.
+pub fn parse_versions(output: &str) -> Versions {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .map(|(name, version)| (name.to_string(), version.trim().to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_versions;
+
+    #[test]
+    fn versions_from_lines() {
+        let versions = parse_versions("ripgrep 14.1.0-1\nfd 9.0.0-1\n\n");
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions.get("fd").map(String::as_str), Some("9.0.0-1"));
+    }
+}
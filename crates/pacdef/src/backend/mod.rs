@@ -1,13 +1,22 @@
 pub mod actual;
 pub mod backend_trait;
+pub mod moved;
 mod root;
 pub mod todo_per_backend;
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
+use std::sync::Mutex;
 
+use crate::cache::{cached, Query};
 use crate::prelude::*;
+use crate::trace::{trace, traced};
 use anyhow::{Context, Result};
 
+/// The replaced packages that were already warned about, so that each is only
+/// warned about once per run.
+static WARNED_REPLACEMENTS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
 /// A backend with its associated managed packages
 pub struct ManagedBackend {
     /// All managed packages for this backend, i.e. all packages
@@ -23,31 +32,190 @@ impl ManagedBackend {
     ///
     /// Returns an error if the backend fails to get the explicitly installed packages.
     pub fn get_unmanaged_packages_sorted(&self) -> Result<Packages> {
-        let installed = self
+        let installed = cached(&self.any_backend, Query::Explicit, || {
+            self.any_backend.get_explicitly_installed_packages()
+        })
+        .context("could not get explicitly installed packages")?;
+
+        let replaced = self.get_replaced_packages(&installed)?;
+
+        let siblings = self
             .any_backend
-            .get_explicitly_installed_packages()
-            .context("could not get explicitly installed packages")?;
+            .get_split_siblings(&self.packages)
+            .context("could not get split packages")?;
 
-        let diff = installed.difference(&self.packages).cloned().collect();
+        let diff = installed
+            .difference(&self.packages)
+            .filter(|package| !replaced.values().any(|new| new == *package))
+            .filter(|package| !siblings.values().any(|covered| covered.contains(*package)))
+            .cloned()
+            .collect();
+        let diff = self.without_ignored(diff)?;
+
+        self.trace_unmanaged(&installed, &replaced, &siblings, &diff);
 
         Ok(diff)
     }
 
-    /// Get missing packages
+    /// Get the managed packages that are installed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend fails to get the installed packages.
+    pub fn get_installed_packages_sorted(&self) -> Result<Packages> {
+        let installed = self.get_all_installed_packages()?;
+
+        Ok(self.packages.intersection(&installed).cloned().collect())
+    }
+
+    /// Get missing packages. Managed packages that are installed with different
+    /// install options count as missing, so that they are reinstalled.
     ///
     /// # Errors
     ///
     /// Returns an error if the backend fails to get the installed packages.
     pub fn get_missing_packages_sorted(&self) -> Result<Packages> {
-        let installed = self
+        let installed = self.get_all_installed_packages()?;
+
+        let drifted = self
             .any_backend
-            .get_all_installed_packages()
-            .context("could not get installed packages")?;
+            .get_drifted_packages()
+            .context("could not get drifted packages")?;
+
+        let replaced = self.get_replaced_packages(&installed)?;
+
+        let mut diff: Packages = self
+            .packages
+            .difference(&installed)
+            .filter(|package| !replaced.contains_key(*package))
+            .cloned()
+            .collect();
+        diff.extend(self.packages.intersection(&drifted).cloned());
+        let diff = self.without_ignored(diff)?;
 
-        let diff = self.packages.difference(&installed).cloned().collect();
+        self.trace_missing(&installed, &replaced, &drifted, &diff);
 
         Ok(diff)
     }
+
+    /// Get all installed packages, including the managed packages that are installed
+    /// without the package manager keeping track of them.
+    fn get_all_installed_packages(&self) -> Result<Packages> {
+        let mut installed = cached(&self.any_backend, Query::All, || {
+            self.any_backend.get_all_installed_packages()
+        })
+        .context("could not get installed packages")?;
+
+        let not_installed = self.packages.difference(&installed).cloned().collect();
+        let untracked = self
+            .any_backend
+            .get_untracked_packages(&not_installed)
+            .context("could not get untracked packages")?;
+        installed.extend(untracked);
+
+        Ok(installed)
+    }
+
+    /// Remove the packages that the package manager is configured to ignore from
+    /// `packages`, and log each of them.
+    fn without_ignored(&self, mut packages: Packages) -> Result<Packages> {
+        let ignored = self
+            .any_backend
+            .get_ignored_packages(&packages)
+            .context("could not get ignored packages")?;
+
+        let section = self.any_backend.backend_info().section;
+        for package in &ignored {
+            log::info!(
+                backend = section, package:% = package;
+                "skipping {package} [{section}], which the package manager is configured to ignore"
+            );
+            packages.remove(package);
+        }
+
+        Ok(packages)
+    }
+
+    /// Get the managed packages that are not among the `installed` packages, but
+    /// replaced by an installed package, keyed by the managed package. A managed
+    /// package is satisfied by its replacement, with a warning to rename it.
+    fn get_replaced_packages(&self, installed: &Packages) -> Result<BTreeMap<Package, Package>> {
+        let not_installed = self.packages.difference(installed).cloned().collect();
+        let replaced = self
+            .any_backend
+            .get_replacements(&not_installed)
+            .context("could not get replaced packages")?;
+
+        let section = self.any_backend.backend_info().section;
+        let mut warned = WARNED_REPLACEMENTS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        for (old, new) in &replaced {
+            if warned.insert(format!("{section}/{old}")) {
+                log::warn!(
+                    backend = section, package:% = old;
+                    "{old} [{section}] is replaced by the installed {new}, rename it in your group file"
+                );
+            }
+        }
+
+        Ok(replaced)
+    }
+
+    /// Log how the traced package, if it is explicitly `installed`, ended up among
+    /// the `unmanaged` packages or not.
+    fn trace_unmanaged(
+        &self,
+        installed: &Packages,
+        replaced: &BTreeMap<Package, Package>,
+        siblings: &BTreeMap<Package, Packages>,
+        unmanaged: &Packages,
+    ) {
+        let section = self.any_backend.backend_info().section;
+
+        for package in traced(installed) {
+            trace(section, package, || {
+                if self.packages.contains(package) {
+                    "explicitly installed and managed".to_string()
+                } else if let Some((old, _)) = replaced.iter().find(|(_, new)| *new == package) {
+                    format!("explicitly installed, managed as {old}, which it replaces")
+                } else if let Some((declared, _)) = siblings
+                    .iter()
+                    .find(|(_, covered)| covered.contains(package))
+                {
+                    format!("explicitly installed, managed as split package of {declared}")
+                } else if unmanaged.contains(package) {
+                    "explicitly installed, but not managed: unmanaged".to_string()
+                } else {
+                    "explicitly installed, not unmanaged".to_string()
+                }
+            });
+        }
+    }
+
+    /// Log how the traced package, if it is managed, ended up among the `missing`
+    /// packages or not.
+    fn trace_missing(
+        &self,
+        installed: &Packages,
+        replaced: &BTreeMap<Package, Package>,
+        drifted: &Packages,
+        missing: &Packages,
+    ) {
+        let section = self.any_backend.backend_info().section;
+
+        for package in traced(&self.packages) {
+            trace(section, package, || match replaced.get(package) {
+                Some(new) => format!("not installed, but replaced by the installed {new}"),
+                None if drifted.contains(package) => {
+                    "installed with other install options: missing, to be reinstalled".to_string()
+                }
+                None if installed.contains(package) => "installed".to_string(),
+                None if missing.contains(package) => "not installed: missing".to_string(),
+                None => "not installed, not missing".to_string(),
+            });
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -55,48 +223,87 @@ impl ManagedBackend {
 pub enum AnyBackend {
     #[cfg(feature = "arch")]
     Arch(actual::arch::Arch),
+    Asdf(Asdf),
+    Cabal(Cabal),
+    Container(Container),
+    Cpanm(Cpanm),
     #[cfg(feature = "debian")]
     Debian(actual::debian::Debian),
+    Emacs(Emacs),
+    External(External),
     Flatpak(Flatpak),
     Fedora(Fedora),
+    GnomeExtensions(GnomeExtensions),
+    HomeManager(HomeManager),
+    Mise(Mise),
     Python(Python),
     Rust(Rust),
     Rustup(Rustup),
+    Tpm(Tpm),
+    VimPlugins(VimPlugins),
     Void(Void),
+    Zig(Zig),
 }
 impl AnyBackend {
-    /// Returns an iterator of every variant of backend.
+    /// Returns an iterator of every built-in backend, followed by every external
+    /// backend in `PATH` that is not named like a built-in one.
     pub fn all(config: &Config) -> impl Iterator<Item = Self> {
-        vec![
+        let mut result = vec![
             #[cfg(feature = "arch")]
             Self::Arch(actual::arch::Arch::new(config)),
+            Self::Asdf(Asdf::new(config)),
+            Self::Cabal(Cabal::new(config)),
+            Self::Container(Container::new(config)),
+            Self::Cpanm(Cpanm::new(config)),
             #[cfg(feature = "debian")]
-            Self::Debian(actual::debian::Debian::new()),
+            Self::Debian(actual::debian::Debian::new(config)),
+            Self::Emacs(Emacs::new(config)),
             Self::Flatpak(Flatpak::new(config)),
-            Self::Fedora(Fedora::new()),
+            Self::Fedora(Fedora::new(config)),
+            Self::GnomeExtensions(GnomeExtensions::new(config)),
+            Self::HomeManager(HomeManager),
+            Self::Mise(Mise::new(config)),
             Self::Python(Python::new(config)),
-            Self::Rust(Rust::new()),
-            Self::Rustup(Rustup::new()),
-            Self::Void(Void::new()),
-        ]
-        .into_iter()
+            Self::Rust(Rust::new(config)),
+            Self::Rustup(Rustup::new(config)),
+            Self::Tpm(Tpm::new(config)),
+            Self::VimPlugins(VimPlugins::new(config)),
+            Self::Void(Void::new(config)),
+            Self::Zig(Zig::new(config)),
+        ];
+
+        let builtin: Vec<_> = result.iter().map(|b| b.backend_info().section).collect();
+        result.extend(External::discover(&builtin).into_iter().map(Self::External));
+
+        result.into_iter()
     }
 
     pub fn from_section(section: &str, config: &Config) -> Result<Self> {
         match section {
             #[cfg(feature = "arch")]
             "arch" => Ok(Self::Arch(actual::arch::Arch::new(config))),
+            "asdf" => Ok(Self::Asdf(Asdf::new(config))),
+            "cabal" => Ok(Self::Cabal(Cabal::new(config))),
+            "container" => Ok(Self::Container(Container::new(config))),
+            "cpanm" => Ok(Self::Cpanm(Cpanm::new(config))),
             #[cfg(feature = "debian")]
-            "debian" => Ok(Self::Debian(actual::debian::Debian::new())),
+            "debian" => Ok(Self::Debian(actual::debian::Debian::new(config))),
+            "emacs" => Ok(Self::Emacs(Emacs::new(config))),
             "flatpak" => Ok(Self::Flatpak(Flatpak::new(config))),
-            "fedora" => Ok(Self::Fedora(Fedora::new())),
+            "fedora" => Ok(Self::Fedora(Fedora::new(config))),
+            "gnome" => Ok(Self::GnomeExtensions(GnomeExtensions::new(config))),
+            "home-manager" => Ok(Self::HomeManager(HomeManager)),
+            "mise" => Ok(Self::Mise(Mise::new(config))),
             "python" => Ok(Self::Python(Python::new(config))),
-            "rust" => Ok(Self::Rust(Rust::new())),
-            "rustup" => Ok(Self::Rustup(Rustup::new())),
-            "void" => Ok(Self::Void(Void::new())),
-            _ => Err(anyhow::anyhow!(
-                "no matching backend for the section: {section}"
-            )),
+            "rust" => Ok(Self::Rust(Rust::new(config))),
+            "rustup" => Ok(Self::Rustup(Rustup::new(config))),
+            "tpm" => Ok(Self::Tpm(Tpm::new(config))),
+            "vim" => Ok(Self::VimPlugins(VimPlugins::new(config))),
+            "void" => Ok(Self::Void(Void::new(config))),
+            "zig" => Ok(Self::Zig(Zig::new(config))),
+            _ => External::find(section)
+                .map(Self::External)
+                .ok_or_else(|| anyhow::anyhow!("no matching backend for the section: {section}")),
         }
     }
 }
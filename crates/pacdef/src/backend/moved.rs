@@ -0,0 +1,125 @@
+use crate::grouping::group::BackendPackages;
+use crate::prelude::*;
+
+/// Package names that differ between ecosystems although they refer to the same
+/// program. The second element is the name the package is compared by.
+const KNOWN_ALIASES: &[(&str, &str)] = &[
+    ("du-dust", "dust"),
+    ("fd-find", "fd"),
+    ("git-delta", "delta"),
+    ("tealdeer", "tldr"),
+];
+
+/// Prefixes distributions put in front of packages that also exist in a language
+/// ecosystem.
+const ECOSYSTEM_PREFIXES: &[&str] = &["python3-", "python-"];
+
+/// A package that is declared for one backend, but installed without being managed by
+/// another one. This usually happens after a package was moved to a different backend
+/// in the group files, without removing the old installation.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MovedPackage {
+    pub declared_in: AnyBackend,
+    pub declared: Package,
+    pub installed_in: AnyBackend,
+    pub installed: Package,
+}
+
+/// Find packages that are declared in one backend and installed as unmanaged
+/// packages in another one.
+pub fn find_moved_packages(
+    managed: &BackendPackages,
+    unmanaged: &ToDoPerBackend,
+) -> Vec<MovedPackage> {
+    let mut result = vec![];
+
+    for (declared_in, declared_packages) in managed {
+        for declared in declared_packages {
+            let name = normalize_name(&declared.name);
+
+            for (installed_in, installed_packages) in unmanaged.iter() {
+                if installed_in == declared_in {
+                    continue;
+                }
+
+                let matching = installed_packages
+                    .iter()
+                    .filter(|installed| normalize_name(&installed.name) == name);
+
+                for installed in matching {
+                    result.push(MovedPackage {
+                        declared_in: declared_in.clone(),
+                        declared: declared.clone(),
+                        installed_in: installed_in.clone(),
+                        installed: installed.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Print a warning for every package that seems to have moved between backends.
+pub fn warn_about_moved_packages(managed: &BackendPackages, unmanaged: &ToDoPerBackend) {
+    for moved in find_moved_packages(managed, unmanaged) {
+        log::warn!(
+            backend:% = moved.declared_in, package:% = moved.declared;
+            "'{}' is managed under [{}], but '{}' is also installed by [{}]. Consider removing one of them.",
+            moved.declared,
+            moved.declared_in,
+            moved.installed,
+            moved.installed_in,
+        );
+    }
+}
+
+/// Reduce a package name to the name it is compared by across backends.
+fn normalize_name(name: &str) -> &str {
+    // rustup components are prefixed by their toolchain
+    let name = name.rsplit('/').next().unwrap_or(name);
+
+    let name = ECOSYSTEM_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+
+    KNOWN_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, canonical)| canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_name("ripgrep"), "ripgrep");
+        assert_eq!(normalize_name("python-black"), "black");
+        assert_eq!(normalize_name("fd-find"), "fd");
+        assert_eq!(normalize_name("stable/rust-analyzer"), "rust-analyzer");
+    }
+
+    #[test]
+    fn moved_from_fedora_to_python() {
+        let config = Config::default();
+        let python = AnyBackend::Python(Python::new(&config));
+        let fedora = AnyBackend::Fedora(Fedora::new(&config));
+
+        let mut managed = BackendPackages::new();
+        managed.insert(python.clone(), [Package::from("black")].into());
+        managed.insert(fedora.clone(), [Package::from("ripgrep")].into());
+
+        let mut unmanaged = ToDoPerBackend::new();
+        unmanaged.push((python, Packages::new()));
+        unmanaged.push((fedora.clone(), [Package::from("python3-black")].into()));
+
+        let moved = find_moved_packages(&managed, &unmanaged);
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].installed_in, fedora);
+        assert_eq!(moved[0].installed.name, "python3-black");
+    }
+}
@@ -1,8 +1,14 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::fmt::Write;
+use std::io::{stdin, IsTerminal};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
+use crate::cache::invalidate;
+use crate::cmd::with_timeout_of;
 use crate::prelude::*;
+use crate::ui::{colored, Color};
 
 /// A vector of tuples containing a Backends and a vector of unmanaged packages
 /// for that backend.
@@ -24,36 +30,189 @@ impl ToDoPerBackend {
         self.0.iter()
     }
 
+    /// The total number of packages across all backends.
+    pub fn count(&self) -> usize {
+        self.0.iter().map(|(_, packages)| packages.len()).sum()
+    }
+
     pub fn nothing_to_do_for_all_backends(&self) -> bool {
         self.0.iter().all(|(_, diff)| diff.is_empty())
     }
 
-    pub fn install_missing_packages(&self, noconfirm: bool) -> Result<()> {
+    /// Get the backends and their packages sorted by the `backend_order` from the
+    /// `config`. The order of backends that are not listed there is kept.
+    fn in_backend_order(&self, config: &Config) -> Vec<&(AnyBackend, Packages)> {
+        let mut result: Vec<_> = self.0.iter().collect();
+        result.sort_by_key(|(backend, _)| config.backend_position(backend.backend_info().section));
+        result
+    }
+
+    /// Install the packages of each backend, in the `backend_order` from the
+    /// `config`. If the packages of a backend have different priorities in the
+    /// `config`, they are installed in separate batches, starting with the highest
+    /// priority. Optional packages are installed one by one after the other packages
+    /// of their batch, and failing to install them only results in a warning.
+    ///
+    /// With `continue_on_error` in the `config`, a failing batch does not stop the
+    /// installation, unless `stop_on_error` is set for its backend. All failed
+    /// backends are reported at the end instead.
+    ///
+    /// Returns the number of packages that were installed.
+    pub fn install_missing_packages(&self, noconfirm: bool, config: &Config) -> Result<usize> {
+        let mut installed = 0;
+        let mut failed = vec![];
+        for (backend, packages) in self.in_backend_order(config) {
+            let section = backend.backend_info().section;
+            let noconfirm = config.no_confirm_for(backend, noconfirm);
+            warn_if_prompt_unanswerable(backend, config);
+
+            let mut batches: BTreeMap<Reverse<i32>, Packages> = BTreeMap::new();
+            for package in packages {
+                batches
+                    .entry(Reverse(config.priority_of(section, &package.name)))
+                    .or_default()
+                    .insert(package.clone());
+            }
+
+            invalidate(backend);
+
+            for batch in batches.values() {
+                match install_batch(backend, batch, noconfirm, config) {
+                    Ok(count) => installed += count,
+                    Err(error) if config.continues_on_error(section) => {
+                        log::warn!(backend:% = backend; "{error:#}");
+                        failed.push(section);
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+
+        if !failed.is_empty() {
+            failed.dedup();
+            bail!("could not install all packages for {}", failed.join(", "));
+        }
+        Ok(installed)
+    }
+
+    /// Download the packages of each backend without installing them. Backends that
+    /// cannot download packages are skipped with a warning.
+    pub fn download_missing_packages(&self, noconfirm: bool, config: &Config) -> Result<()> {
         for (backend, packages) in &self.0 {
             if packages.is_empty() {
                 continue;
             }
 
-            backend
-                .install_packages(packages, noconfirm)
-                .with_context(|| format!("installing packages for {backend}"))?;
+            if !backend.supports_download() {
+                log::warn!(
+                    backend:% = backend;
+                    "{backend} cannot download packages without installing them, skipping {} packages",
+                    packages.len()
+                );
+                continue;
+            }
+
+            let noconfirm = config.no_confirm_for(backend, noconfirm);
+            with_timeout_of(backend, config, || {
+                backend.download_packages(packages, noconfirm)
+            })
+            .with_context(|| format!("downloading packages for {backend}"))?;
         }
         Ok(())
     }
 
-    pub fn remove_unmanaged_packages(&self, noconfirm: bool) -> Result<()> {
-        for (backend, packages) in &self.0 {
+    /// Reinstall the packages of each backend, in the `backend_order` from the
+    /// `config`. Backends that cannot reinstall packages are skipped with a warning.
+    pub fn reinstall_packages(&self, noconfirm: bool, config: &Config) -> Result<()> {
+        for (backend, packages) in self.in_backend_order(config) {
             if packages.is_empty() {
                 continue;
             }
 
-            backend
-                .remove_packages(packages, noconfirm)
-                .with_context(|| format!("removing packages for {backend}"))?;
+            if !backend.supports_reinstall() {
+                log::warn!(
+                    backend:% = backend;
+                    "{backend} cannot reinstall packages, skipping {} packages",
+                    packages.len()
+                );
+                continue;
+            }
+
+            let noconfirm = config.no_confirm_for(backend, noconfirm);
+            warn_if_prompt_unanswerable(backend, config);
+            invalidate(backend);
+            with_timeout_of(backend, config, || {
+                backend.reinstall_packages(packages, noconfirm)
+            })
+            .with_context(|| format!("reinstalling packages for {backend}"))?;
         }
         Ok(())
     }
 
+    /// Remove the packages of each backend, in the reverse of the `backend_order`
+    /// from the `config`.
+    pub fn remove_unmanaged_packages(&self, noconfirm: bool, config: &Config) -> Result<()> {
+        for (backend, packages) in self.in_backend_order(config).into_iter().rev() {
+            if packages.is_empty() {
+                continue;
+            }
+
+            let noconfirm = config.no_confirm_for(backend, noconfirm);
+            warn_if_prompt_unanswerable(backend, config);
+            invalidate(backend);
+            with_timeout_of(backend, config, || {
+                backend.remove_packages(packages, noconfirm)
+            })
+            .with_context(|| format!("removing packages for {backend}"))?;
+        }
+        Ok(())
+    }
+
+    /// Get the dependencies that the backends would remove together with the
+    /// packages, because nothing else requires them anymore.
+    pub fn removal_cascades(&self) -> Self {
+        let mut result = Self::new();
+
+        for (backend, packages) in &self.0 {
+            if packages.is_empty() {
+                continue;
+            }
+
+            match backend.get_removal_cascade(packages) {
+                Ok(cascade) => result.push((backend.clone(), cascade)),
+                Err(error) => {
+                    log::warn!(
+                        backend:% = backend;
+                        "could not determine dependencies removed by {backend}: {error}"
+                    );
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Print the packages per backend as a list of changes, prefixed by `marker` and
+    /// highlighted in `color`.
+    pub fn show_changes(&self, marker: char, color: Color) {
+        let mut iter = self
+            .0
+            .iter()
+            .filter(|(_, packages)| !packages.is_empty())
+            .peekable();
+
+        while let Some((backend, packages)) = iter.next() {
+            println!("[{backend}]");
+            for package in packages {
+                println!("{}", colored(&format!("{marker} {package}"), color));
+            }
+
+            if iter.peek().is_some() {
+                println!();
+            }
+        }
+    }
+
     pub fn show(&self) -> Result<()> {
         let mut parts = vec![];
 
@@ -102,3 +261,53 @@ impl IntoIterator for ToDoPerBackend {
         self.0.into_iter()
     }
 }
+
+/// Install one `batch` of packages of the same priority and return the number of
+/// installed packages. Optional packages are installed one by one after the others,
+/// and failing to install them only results in a warning.
+fn install_batch(
+    backend: &AnyBackend,
+    batch: &Packages,
+    noconfirm: bool,
+    config: &Config,
+) -> Result<usize> {
+    let section = backend.backend_info().section;
+    let (optional, required): (Packages, Packages) = batch
+        .iter()
+        .cloned()
+        .partition(|package| config.is_optional(section, &package.name));
+
+    if !required.is_empty() {
+        with_timeout_of(backend, config, || {
+            backend.install_packages(&required, noconfirm)
+        })
+        .with_context(|| format!("installing packages for {backend}"))?;
+    }
+
+    let mut installed = required.len();
+
+    // one at a time, so that a missing package does not prevent the others
+    for package in optional {
+        let name = package.to_string();
+        let packages = [package].into();
+        match with_timeout_of(backend, config, || {
+            backend.install_packages(&packages, noconfirm)
+        }) {
+            Ok(()) => installed += 1,
+            Err(error) => log::warn!(
+                backend:% = backend, package:% = name;
+                "could not install optional package {name} for {backend}: {error:#}"
+            ),
+        }
+    }
+
+    Ok(installed)
+}
+
+/// Interactive backends may prompt even with `noconfirm`, which cannot be answered
+/// if stdin is not a terminal.
+fn warn_if_prompt_unanswerable(backend: &AnyBackend, config: &Config) {
+    if config.is_interactive(backend) && !stdin().is_terminal() {
+        log::warn!(backend:% = backend; "{backend} may prompt for confirmation, but stdin is not a terminal");
+    }
+}
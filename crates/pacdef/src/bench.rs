@@ -0,0 +1,179 @@
+/*!
+Timings of the query path, as a guardrail against performance regressions.
+
+Every backend is asked for its unmanaged and missing packages, the same way
+`package unmanaged` and `package sync` do, while the backend commands return
+fixture data of [`PACKAGES`] packages through a [`CommandRunner`]. This measures
+the time pacdef itself spends on parsing and comparing, not the time of the
+package managers. cargo crates are read from a fixture crates file in a temporary
+`$CARGO_HOME` instead.
+
+The Arch backend is not measured, as it queries libalpm directly instead of running
+commands.
+
+The timings are not part of the regular test run. Run them with
+
+```text
+cargo test --release bench -- --ignored --nocapture
+```
+*/
+
+use std::cell::Cell;
+use std::env::{set_var, temp_dir};
+use std::fmt::Write;
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::backend::actual::asdf::Asdf;
+use crate::backend::actual::container::Container;
+use crate::backend::actual::home_manager::HomeManager;
+use crate::backend::actual::rust::Rust;
+use crate::cmd::{with_runner, CommandOutput, CommandRunner};
+use crate::prelude::*;
+
+/// The number of installed packages each backend reports.
+const PACKAGES: usize = 5000;
+
+/// The number of times each query is repeated.
+const ITERATIONS: u32 = 20;
+
+/// A [`CommandRunner`] that returns the same fixture output for every command, and
+/// counts the commands.
+struct FixtureRunner {
+    stdout: String,
+    commands: Cell<usize>,
+}
+
+impl CommandRunner for FixtureRunner {
+    fn run(&self, _: Command) -> Result<()> {
+        self.commands.set(self.commands.get() + 1);
+        Ok(())
+    }
+
+    fn run_for_stdout(&self, _: Command) -> Result<String> {
+        self.commands.set(self.commands.get() + 1);
+        Ok(self.stdout.clone())
+    }
+
+    fn run_for_output(&self, _: Command, _: bool) -> Result<CommandOutput> {
+        self.commands.set(self.commands.get() + 1);
+        Ok(CommandOutput {
+            success: true,
+            code: Some(0),
+            stdout: self.stdout.clone(),
+            ..CommandOutput::default()
+        })
+    }
+}
+
+/// The output of listing [`PACKAGES`] installed packages, in the format of the
+/// backend.
+fn fixture_output(backend: &AnyBackend) -> String {
+    let mut stdout = String::new();
+
+    for i in 0..PACKAGES {
+        let line = match backend {
+            AnyBackend::Asdf(_) => format!("package{i}\n  1.0.0"),
+            AnyBackend::Container(_) => format!("docker.io/library/package{i}:1.0"),
+            _ => format!("package{i}-1.0.0"),
+        };
+        writeln!(stdout, "{line}").expect("writing to a string");
+    }
+
+    stdout
+}
+
+/// Write a crates file with [`PACKAGES`] crates into `cargo_home`, in the format of
+/// `.crates2.json`.
+fn write_crates_file(cargo_home: &Path) {
+    let installs: serde_json::Map<_, _> = (0..PACKAGES)
+        .map(|i| {
+            let key =
+                format!("package{i} 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)");
+            let install = serde_json::json!({
+                "bins": [format!("package{i}")],
+                "features": [],
+                "all_features": false,
+                "no_default_features": false
+            });
+            (key, install)
+        })
+        .collect();
+    let json = serde_json::json!({ "installs": installs });
+
+    create_dir_all(cargo_home).expect("temp dir is writable");
+    write(cargo_home.join(".crates2.json"), json.to_string()).expect("temp dir is writable");
+}
+
+/// Run `query` [`ITERATIONS`] times and return the mean duration and the number of
+/// commands of a single run.
+fn measure(runner: &FixtureRunner, query: impl Fn() -> Result<Packages>) -> (Duration, usize) {
+    runner.commands.set(0);
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        query().expect("fixture data can be parsed");
+    }
+
+    let commands = runner.commands.get() / usize::try_from(ITERATIONS).unwrap_or(1);
+    (start.elapsed() / ITERATIONS, commands)
+}
+
+#[test]
+#[ignore = "timings, run explicitly"]
+fn bench_query_path() {
+    let cargo_home = temp_dir().join(format!("pacdef-bench-{}", std::process::id()));
+    write_crates_file(&cargo_home);
+    set_var("CARGO_HOME", &cargo_home);
+
+    let config = Config::default();
+    let backends: Vec<AnyBackend> = vec![
+        Asdf::new(&config).into(),
+        Container {
+            runtime: "docker".into(),
+            extra_args: vec![],
+        }
+        .into(),
+        HomeManager.into(),
+        Rust::new(&config).into(),
+    ];
+
+    println!("{PACKAGES} installed packages, mean of {ITERATIONS} runs\n");
+
+    for any_backend in backends {
+        let runner = Rc::new(FixtureRunner {
+            stdout: fixture_output(&any_backend),
+            commands: Cell::new(0),
+        });
+
+        with_runner(runner.clone(), || {
+            // every second installed package is managed
+            let installed = any_backend
+                .get_all_installed_packages()
+                .expect("fixture data can be parsed");
+            let managed_backend = ManagedBackend {
+                packages: installed.into_iter().step_by(2).collect(),
+                any_backend: any_backend.clone(),
+            };
+
+            let (unmanaged, unmanaged_commands) =
+                measure(&runner, || managed_backend.get_unmanaged_packages_sorted());
+            let (missing, missing_commands) =
+                measure(&runner, || managed_backend.get_missing_packages_sorted());
+
+            println!(
+                "{:<14} unmanaged {:>8.3} ms ({unmanaged_commands} commands)  missing {:>8.3} ms ({missing_commands} commands)",
+                any_backend.backend_info().section,
+                unmanaged.as_secs_f64() * 1000.0,
+                missing.as_secs_f64() * 1000.0,
+            );
+        });
+    }
+
+    remove_dir_all(&cargo_home).expect("temp dir can be removed");
+}
@@ -0,0 +1,159 @@
+/*!
+Cache the installed packages of each backend on disk.
+
+Read-only commands like `package unmanaged` are run often, e.g. in a shell prompt,
+and querying every backend each time is slow. With `query_cache_ttl` set in the
+config, the installed packages of a backend are kept in the state dir and reused for
+that many seconds. Every change pacdef makes to a backend removes its cache, but
+changes made outside of pacdef are only noticed once the cache expired.
+*/
+
+use std::fs::{create_dir_all, read_to_string, remove_file, write};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+
+use crate::history::now;
+use crate::prelude::*;
+
+/// The time in seconds a cached query stays valid. `0` disables the cache.
+static TTL: AtomicU64 = AtomicU64::new(0);
+
+const CACHE_DIR_NAME: &str = "query_cache";
+
+/// Which packages of a backend a query returns.
+#[derive(Clone, Copy)]
+pub enum Query {
+    /// All installed packages.
+    All,
+    /// Only the explicitly installed packages.
+    Explicit,
+}
+
+impl Query {
+    const ALL: [Self; 2] = [Self::All, Self::Explicit];
+
+    const fn file_extension(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Explicit => "explicit",
+        }
+    }
+}
+
+/// Keep query results for `ttl` seconds. With `0`, every query is run.
+pub fn set_ttl(ttl: u64) {
+    TTL.store(ttl, Ordering::Relaxed);
+}
+
+/// Return the cached result of the `query` for `backend` if it is fresh, or else run
+/// `run_query` and cache its result. A cache that cannot be read or written is
+/// ignored.
+///
+/// # Errors
+///
+/// This function returns the errors of `run_query`.
+pub fn cached(
+    backend: &AnyBackend,
+    query: Query,
+    run_query: impl FnOnce() -> Result<Packages>,
+) -> Result<Packages> {
+    let ttl = TTL.load(Ordering::Relaxed);
+    if ttl == 0 {
+        return run_query();
+    }
+
+    let Ok(path) = get_cache_path(backend, query) else {
+        return run_query();
+    };
+
+    if let Some(packages) = read_cache(&path, ttl) {
+        return Ok(packages);
+    }
+
+    let packages = run_query()?;
+    if let Err(error) = write_cache(&path, &packages) {
+        log::debug!("could not cache packages of {backend}: {error:#}");
+    }
+
+    Ok(packages)
+}
+
+/// Remove the cached queries of `backend`, because its packages are about to change.
+pub fn invalidate(backend: &AnyBackend) {
+    for query in Query::ALL {
+        let Ok(path) = get_cache_path(backend, query) else {
+            return;
+        };
+
+        match remove_file(&path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                log::warn!(
+                    "could not remove cached packages {}: {error}",
+                    path.to_string_lossy()
+                );
+            }
+            _ => (),
+        }
+    }
+}
+
+fn get_cache_path(backend: &AnyBackend, query: Query) -> Result<PathBuf> {
+    let mut path = get_pacdef_state_dir()?;
+    path.push(CACHE_DIR_NAME);
+    path.push(backend.backend_info().section);
+    path.set_extension(query.file_extension());
+    Ok(path)
+}
+
+/// Read the packages from the cache file at `path`, unless it is older than `ttl`
+/// seconds. The first line of the file is the time it was written, followed by one
+/// package per line.
+fn read_cache(path: &Path, ttl: u64) -> Option<Packages> {
+    let content = read_to_string(path).ok()?;
+    let mut lines = content.lines();
+
+    let written: i64 = lines.next()?.parse().ok()?;
+    let age = u64::try_from(now().ok()? - written).ok()?;
+    if age >= ttl {
+        return None;
+    }
+
+    Some(lines.map(Package::from).collect())
+}
+
+fn write_cache(path: &Path, packages: &Packages) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir).context("creating cache dir")?;
+    }
+
+    let mut content = now()?.to_string();
+    for package in packages {
+        content.push('\n');
+        content.push_str(&package.to_string());
+    }
+
+    write(path, content).context("writing cache file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_expires() {
+        let path = std::env::temp_dir().join("pacdef-query-cache-test.all");
+        let packages: Packages = [Package::from("neovim"), Package::from("extra/ripgrep")].into();
+
+        write_cache(&path, &packages).expect("temp dir is writable");
+        assert_eq!(read_cache(&path, 60), Some(packages));
+        assert_eq!(read_cache(&path, 0), None);
+
+        write(&path, "0\nneovim").expect("temp dir is writable");
+        assert_eq!(read_cache(&path, 60), None);
+
+        remove_file(&path).expect("cache file exists");
+    }
+}
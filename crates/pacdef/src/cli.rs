@@ -1,8 +1,9 @@
 //! The clap declarative command line interface
 
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(
@@ -15,14 +16,90 @@ use clap::{Args, Parser, Subcommand};
 )]
 /// multi-backend declarative package manager for Linux
 pub struct MainArguments {
+    #[arg(long, global(true), value_name("FILE"))]
+    /// use this config file instead of the default one
+    pub config_path: Option<PathBuf>,
+
+    #[arg(long, global(true), value_name("DIR"))]
+    /// use this directory for group files instead of the default one
+    pub group_dir: Option<PathBuf>,
+
+    #[arg(long, global(true), value_name("GLOB"))]
+    /// ignore packages whose name matches the glob, may be repeated
+    pub exclude: Vec<String>,
+
+    #[arg(long, global(true))]
+    /// print the commands that would change the system instead of running them
+    pub print_commands: bool,
+
+    #[arg(long, global(true))]
+    /// fail if a backend with declared packages is not installed, instead of skipping it
+    pub strict_backends: bool,
+
+    #[arg(long, global(true), value_name("BACKEND"))]
+    /// do not let this backend ask for confirmation, may be repeated
+    pub assume_yes_for: Vec<String>,
+
+    #[arg(long, global(true), value_name("FILE"))]
+    /// install the backends in the order listed in this file instead of `backend_order`
+    pub backend_order_file: Option<PathBuf>,
+
+    #[arg(long, global(true), value_enum, default_value_t = ColorChoice::Auto)]
+    /// when to color the output
+    pub color: ColorChoice,
+
+    #[arg(long, global(true), value_enum, default_value_t = LogFormat::Text)]
+    /// how to print warnings and errors
+    pub log_format: LogFormat,
+
+    #[arg(long, short, global(true), value_name("N"))]
+    /// query at most N backends at the same time [default: number of CPUs]
+    pub jobs: Option<NonZeroUsize>,
+
+    #[arg(long, global(true))]
+    /// query all backends, even if `query_cache_ttl` is set and a cached result is fresh
+    pub no_cache: bool,
+
+    #[arg(long, global(true), value_name("PACKAGE"))]
+    /// log every step that decides whether the package is managed, missing or unmanaged
+    pub trace: Option<String>,
+
     #[command(subcommand)]
     pub subcommand: MainSubcommand,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// only if the output is a terminal and `NO_COLOR` is not set
+    Auto,
+    /// always
+    Always,
+    /// never
+    Never,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// `LEVEL - message`
+    Text,
+    /// one JSON object per line with level, target, message, and the backend and
+    /// package where known
+    Json,
+}
+
 #[derive(Subcommand)]
 pub enum MainSubcommand {
+    Bootstrap(BootstrapArguments),
+    Config(ConfigArguments),
+    Deps(DepsArguments),
+    Fmt(FmtArguments),
     Group(GroupArguments),
+    Log(LogArguments),
     Package(PackageArguments),
+    Repair(RepairArguments),
+    Restore(RestoreArguments),
+    Validate(ValidateArguments),
+    Verify(VerifyArguments),
     Version(VersionArguments),
 }
 
@@ -42,9 +119,11 @@ pub struct GroupArguments {
 pub enum GroupAction {
     Edit(EditGroupAction),
     Export(ExportGroupAction),
+    Graph(GraphGroupAction),
     Import(ImportGroupAction),
     List(ListGroupAction),
     New(NewGroupAction),
+    Prune(PruneGroupAction),
     Remove(RemoveGroupAction),
     Show(ShowGroupAction),
 }
@@ -75,6 +154,21 @@ pub struct ExportGroupAction {
     pub force: bool,
 }
 
+#[derive(Args)]
+#[command(visible_alias("gr"))]
+/// print which groups declare which packages as a graph
+pub struct GraphGroupAction {
+    #[arg(short, long, value_enum, default_value_t = GraphFormat::Dot)]
+    /// the output format of the graph
+    pub format: GraphFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+}
+
 #[derive(Args)]
 #[command(arg_required_else_help(true), visible_alias("i"))]
 /// import one or more group files
@@ -102,6 +196,19 @@ pub struct NewGroupAction {
     pub edit: bool,
 }
 
+#[derive(Args)]
+#[command(visible_alias("p"))]
+/// remove group files without packages, and dangling symlinks to group files
+pub struct PruneGroupAction {
+    #[arg(long)]
+    /// only show the files that would be removed
+    pub dry_run: bool,
+
+    #[arg(long)]
+    /// do not ask for any confirmation
+    pub no_confirm: bool,
+}
+
 #[derive(Args)]
 #[command(arg_required_else_help(true), visible_alias("r"))]
 /// remove one or more previously imported groups
@@ -135,6 +242,10 @@ pub struct PackageArguments {
 #[derive(Subcommand)]
 pub enum PackageAction {
     Clean(CleanPackageAction),
+    CleanCache(CleanCachePackageAction),
+    Lock(LockPackageAction),
+    Orphans(OrphansPackageAction),
+    Prefetch(PrefetchPackageAction),
     Review(ReviewPackageAction),
     Search(SearchPackageAction),
     Sync(SyncPackageAction),
@@ -148,12 +259,101 @@ pub struct CleanPackageAction {
     #[arg(long)]
     /// do not ask for any confirmation
     pub no_confirm: bool,
+
+    #[arg(long)]
+    /// remove the packages even if there are more than `max_removals`
+    pub force: bool,
+
+    #[arg(long)]
+    /// remove the packages if `require_apply` is set, instead of only showing them
+    pub apply: bool,
+
+    #[arg(long, value_name("FILE"))]
+    /// before removing, write the packages to FILE, which `pacdef restore` reinstalls
+    pub save_removed: Option<PathBuf>,
+}
+
+#[derive(Args)]
+#[command(visible_alias("cc"))]
+/// remove cached package files of all backends
+pub struct CleanCachePackageAction {
+    #[arg(long)]
+    /// do not ask for any confirmation
+    pub no_confirm: bool,
+}
+
+#[derive(Args)]
+#[command(visible_alias("lo"))]
+/// write the installed versions of all managed packages to the lock file
+pub struct LockPackageAction {}
+
+#[derive(Args)]
+#[command(visible_alias("o"))]
+/// show packages installed as dependency that are not required anymore
+pub struct OrphansPackageAction {}
+
+#[derive(Args)]
+#[command(visible_alias("pf"))]
+/// download missing managed packages without installing them
+pub struct PrefetchPackageAction {
+    #[arg(long)]
+    /// do not ask for any confirmation
+    pub no_confirm: bool,
 }
 
 #[derive(Args)]
 #[command(visible_alias("r"))]
 /// review unmanaged packages
-pub struct ReviewPackageAction {}
+pub struct ReviewPackageAction {
+    #[arg(short, long)]
+    /// pick missing and unmanaged packages from a list before applying
+    pub interactive: bool,
+
+    #[arg(short, long, value_enum)]
+    /// how to show the packages and what will be done with them [default: plain
+    /// on a terminal, terse otherwise]
+    pub output: Option<ReviewOutput>,
+
+    #[arg(long, overrides_with("no_interactive_output"))]
+    /// show the changes as plain output even if stdout is not a terminal
+    pub interactive_output: bool,
+
+    #[arg(long, overrides_with("interactive_output"))]
+    /// show the changes as terse output even if stdout is a terminal
+    pub no_interactive_output: bool,
+
+    #[arg(long, value_enum, default_value_t = GroupBy::Backend)]
+    /// list the changes under their backend, or under the group that declares
+    /// each package
+    pub group_by: GroupBy,
+
+    #[arg(long)]
+    /// ask crates.io whether the installed version of each crate was yanked, and
+    /// list the yanked crates after the review
+    pub check_yanked: bool,
+
+    #[arg(long)]
+    /// remove the packages even if there are more than `max_removals`
+    pub force: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReviewOutput {
+    /// one line per package, grouped by backend
+    Plain,
+    /// aligned columns of backend, package, status and options
+    Table,
+    /// one line per change, for logs
+    Terse,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// the backend of each package
+    Backend,
+    /// the group that declares each package, or `unmanaged`
+    Group,
+}
 
 #[derive(Args)]
 #[command(arg_required_else_help(true), visible_alias("se"))]
@@ -171,6 +371,30 @@ pub struct SyncPackageAction {
     #[arg(long)]
     /// do not ask for any confirmation
     pub no_confirm: bool,
+
+    #[arg(long)]
+    /// install the versions recorded in the lock file
+    pub locked: bool,
+
+    #[arg(long)]
+    /// upgrade all installed packages before installing (Arch)
+    pub sysupgrade: bool,
+
+    #[arg(long)]
+    /// install the packages if `require_apply` is set, instead of only showing them
+    pub apply: bool,
+
+    #[arg(long)]
+    /// keep installing the other packages if some fail, and report all failures at the end
+    pub continue_on_error: bool,
+
+    #[arg(short, long = "group", value_name = "GROUP")]
+    /// only install the packages of this group and of `always_groups`, can be repeated
+    pub groups: Vec<String>,
+
+    #[arg(long = "exclude-group", value_name = "GROUP")]
+    /// do not install the packages of this group, can be repeated
+    pub exclude_groups: Vec<String>,
 }
 
 #[derive(Args)]
@@ -178,5 +402,95 @@ pub struct SyncPackageAction {
 /// show explicitly installed packages not managed by pacdef
 pub struct UnmanagedPackageAction {}
 
+#[derive(Args)]
+#[command(arg_required_else_help(true))]
+/// create a new group from all unmanaged packages
+pub struct BootstrapArguments {
+    /// name of the new group
+    pub group: String,
+
+    #[arg(long)]
+    /// do not ask for any confirmation
+    pub no_confirm: bool,
+}
+
+#[derive(Args)]
+#[command(arg_required_else_help(true))]
+/// inspect the configuration
+pub struct ConfigArguments {
+    #[arg(long)]
+    /// print the effective config, after merging all fragments, command line
+    /// arguments and sidecar files, as TOML
+    pub dump: bool,
+}
+
+#[derive(Args)]
+/// sort the packages of all group files and normalize their formatting
+pub struct FmtArguments {
+    #[arg(long)]
+    /// only list the group files that are not normalized, and fail if there are any
+    pub check: bool,
+}
+
+#[derive(Args)]
+/// show packages that were installed or removed recently
+pub struct LogArguments {
+    #[arg(long, conflicts_with("since"))]
+    /// show changes since the last boot (default)
+    pub since_boot: bool,
+
+    #[arg(long, value_name("DURATION"))]
+    /// show changes in the given time, e.g. 30m, 2h or 7d
+    pub since: Option<String>,
+}
+
+#[derive(Args)]
+#[command(arg_required_else_help(true))]
+/// show the dependency tree of an installed package (Arch)
+pub struct DepsArguments {
+    /// the backend of the package, i.e. its section in the group files
+    pub backend: String,
+
+    /// the package whose dependencies to show
+    pub package: String,
+}
+
+#[derive(Args)]
+#[command(arg_required_else_help(true))]
+/// reinstall all installed managed packages, e.g. after file system damage
+pub struct RepairArguments {
+    #[arg(long)]
+    /// confirm that every managed package shall be reinstalled, which is required
+    pub force: bool,
+
+    #[arg(long, short, value_name("BACKEND"))]
+    /// only reinstall the packages of this backend, may be repeated
+    pub backend: Vec<String>,
+
+    #[arg(long)]
+    /// do not ask for any confirmation
+    pub no_confirm: bool,
+}
+
+#[derive(Args)]
+#[command(arg_required_else_help(true))]
+/// reinstall the packages from a file written by `package clean --save-removed`
+pub struct RestoreArguments {
+    /// the file to read the packages from, in the format of a group file
+    pub file: PathBuf,
+
+    #[arg(long)]
+    /// do not ask for any confirmation
+    pub no_confirm: bool,
+}
+
+#[derive(Args)]
+/// check all group files for errors, without querying any backend
+pub struct ValidateArguments {}
+
+#[derive(Args)]
+/// show installed packages with modified or missing files
+pub struct VerifyArguments {}
+
 #[derive(Args)]
 pub struct VersionArguments {}
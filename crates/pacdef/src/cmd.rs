@@ -1,9 +1,53 @@
+use std::cell::{Cell, RefCell};
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
 use std::path::Path;
-use std::process::Command;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use anyhow::{ensure, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 
 use crate::env::{get_editor, should_print_debug_info};
+use crate::prelude::*;
+
+/// Whether commands that change the system are printed instead of run.
+static PRINT_COMMANDS: AtomicBool = AtomicBool::new(false);
+
+/// How long a command that timed out gets to exit after `SIGTERM`, before it is
+/// killed.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+thread_local! {
+    /// The timeout of the commands run on this thread, see [`with_timeout_of`].
+    static TIMEOUT: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// Print commands that would change the system instead of running them. Read-only
+/// queries are still run, as their output determines which commands are printed.
+pub fn set_print_commands(value: bool) {
+    PRINT_COMMANDS.store(value, Ordering::Relaxed);
+}
+
+/// Whether commands that would change the system are only printed, see
+/// [`set_print_commands`].
+pub fn prints_commands() -> bool {
+    PRINT_COMMANDS.load(Ordering::Relaxed)
+}
+
+/// Run `f` with the timeout of `backend` from the `config` applied to all commands
+/// on the current thread. Commands that are run outside of this never time out.
+pub fn with_timeout_of<T>(backend: &AnyBackend, config: &Config, f: impl FnOnce() -> T) -> T {
+    let timeout = config.command_timeout_for(backend);
+
+    let previous = TIMEOUT.with(|current| current.replace(timeout));
+    let result = f();
+    TIMEOUT.with(|current| current.set(previous));
+    result
+}
 
 /// Run the editor and pass the provided files as arguments. The workdir is set
 /// to the parent of the first file.
@@ -28,27 +72,473 @@ where
     inner(&files)
 }
 
+/// Runs external commands on behalf of [`run_external_command`] and
+/// [`run_command_for_stdout`]. Tests replace the [`SystemRunner`] with a runner that
+/// returns canned output, so backends can be tested without touching the system.
+pub trait CommandRunner {
+    /// Run the command and wait for it to finish.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the command cannot be run or if it
+    /// returns a non-zero exit status.
+    fn run(&self, cmd: Command) -> Result<()>;
+
+    /// Run the command and return its stdout.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the command cannot be run, if it
+    /// returns a non-zero exit status, or if its output is not valid UTF-8.
+    fn run_for_stdout(&self, cmd: Command) -> Result<String>;
+
+    /// Run the command and return its exit status and output, whatever the exit
+    /// status is. With `echo`, the output is also shown as it arrives and stdin is
+    /// inherited, so the user can follow and answer prompts.
+    ///
+    /// # Errors
+    ///
+    /// This function shall return an error if the command cannot be run.
+    fn run_for_output(&self, cmd: Command, echo: bool) -> Result<CommandOutput>;
+}
+
+/// The exit status and output of a command that ran to completion.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// The full command line, as [`format_command`] renders it.
+    pub command: String,
+    pub success: bool,
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+impl CommandOutput {
+    /// Return an error if the command returned a non-zero exit status.
+    pub fn ensure_success(&self) -> Result<()> {
+        ensure!(
+            self.success,
+            "command [{}] returned non-zero exit status {}",
+            self.command,
+            self.code
+                .map_or_else(|| "unknown".to_string(), |code| code.to_string())
+        );
+        Ok(())
+    }
+}
+
+/// Runs commands as actual subprocesses.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, mut cmd: Command) -> Result<()> {
+        let exit_status = match TIMEOUT.with(Cell::get) {
+            Some(timeout) => {
+                let mut child = cmd
+                    .spawn()
+                    .with_context(|| format!("running command [{cmd:?}]"))?;
+                wait_with_timeout(&mut child, timeout, &cmd)?
+            }
+            None => cmd
+                .status()
+                .with_context(|| format!("running command [{cmd:?}]"))?,
+        };
+
+        let success = exit_status.success();
+        ensure!(
+            success,
+            "command [{cmd:?}] returned non-zero exit status {success}"
+        );
+        Ok(())
+    }
+
+    fn run_for_stdout(&self, mut cmd: Command) -> Result<String> {
+        let (status, stdout) = match TIMEOUT.with(Cell::get) {
+            Some(timeout) => output_with_timeout(&mut cmd, timeout)?,
+            None => {
+                let output = cmd
+                    .output()
+                    .with_context(|| format!("running command [{cmd:?}]"))?;
+                (output.status, output.stdout)
+            }
+        };
+
+        ensure!(
+            status.success(),
+            "command [{cmd:?}] returned non-zero exit status {status}"
+        );
+
+        String::from_utf8(stdout).with_context(|| format!("reading output of [{cmd:?}]"))
+    }
+
+    fn run_for_output(&self, mut cmd: Command, echo: bool) -> Result<CommandOutput> {
+        if !echo {
+            cmd.stdin(Stdio::null());
+        }
+        let mut child = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("running command [{cmd:?}]"))?;
+
+        let stdout_echo = echo.then(|| -> Box<dyn Write + Send> { Box::new(io::stdout()) });
+        let stderr_echo = echo.then(|| -> Box<dyn Write + Send> { Box::new(io::stderr()) });
+        let stdout = read_in_background(child.stdout.take(), stdout_echo);
+        let stderr = read_in_background(child.stderr.take(), stderr_echo);
+
+        let status = match TIMEOUT.with(Cell::get) {
+            Some(timeout) => wait_with_timeout(&mut child, timeout, &cmd)?,
+            None => child.wait().context("waiting for command")?,
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+
+        Ok(CommandOutput {
+            command: format_command(&cmd),
+            success: status.success(),
+            code: status.code(),
+            stdout: String::from_utf8_lossy(&stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        })
+    }
+}
+
+/// Like [`Command::output`], but the command is killed after `timeout`. Only stdout
+/// is returned, stderr is discarded just the same.
+fn output_with_timeout(cmd: &mut Command, timeout: Duration) -> Result<(ExitStatus, Vec<u8>)> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("running command [{cmd:?}]"))?;
+
+    // both pipes are drained while waiting, so that a full pipe cannot block the command
+    let stdout = read_in_background(child.stdout.take(), None);
+    let stderr = read_in_background(child.stderr.take(), None);
+
+    let status = wait_with_timeout(&mut child, timeout, cmd)?;
+    let stdout = stdout.join().unwrap_or_default();
+    let _ = stderr.join();
+
+    Ok((status, stdout))
+}
+
+/// Read all of `pipe` on a separate thread. Everything read is also written to
+/// `echo` right away, if given, so prompts without a trailing newline show up.
+fn read_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    mut echo: Option<Box<dyn Write + Send>>,
+) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        let Some(mut pipe) = pipe else {
+            return buffer;
+        };
+
+        let mut chunk = [0; 4096];
+        loop {
+            match pipe.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => {
+                    if let Some(echo) = &mut echo {
+                        let _ = echo.write_all(&chunk[..read]);
+                        let _ = echo.flush();
+                    }
+                    buffer.extend_from_slice(&chunk[..read]);
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+        buffer
+    })
+}
+
+/// Wait for `child` to exit. If it is still running after `timeout`, it is sent
+/// `SIGTERM`, which `sudo` passes on to the command it runs, and killed if it does
+/// not exit within [`KILL_GRACE_PERIOD`].
+fn wait_with_timeout(child: &mut Child, timeout: Duration, cmd: &Command) -> Result<ExitStatus> {
+    if let Some(status) = wait_until(child, Instant::now() + timeout)? {
+        return Ok(status);
+    }
+
+    if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+    }
+    if wait_until(child, Instant::now() + KILL_GRACE_PERIOD)?.is_none() {
+        child.kill().context("killing command")?;
+        child.wait().context("waiting for killed command")?;
+    }
+
+    bail!(
+        "command [{cmd:?}] timed out after {} seconds",
+        timeout.as_secs()
+    )
+}
+
+/// Wait for `child` to exit until `deadline`. Returns `None` if it is still running.
+fn wait_until(child: &mut Child, deadline: Instant) -> Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait().context("waiting for command")? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+thread_local! {
+    /// The runner used on this thread, or `None` for the [`SystemRunner`].
+    static RUNNER: RefCell<Option<Rc<dyn CommandRunner>>> = RefCell::new(None);
+}
+
+/// Run `f` with all commands on the current thread passed to `runner` instead of
+/// being run.
+#[cfg(test)]
+pub fn with_runner<T>(runner: Rc<dyn CommandRunner>, f: impl FnOnce() -> T) -> T {
+    let previous = RUNNER.with(|current| current.replace(Some(runner)));
+    let result = f();
+    RUNNER.with(|current| current.replace(previous));
+    result
+}
+
+fn get_runner() -> Rc<dyn CommandRunner> {
+    RUNNER.with(|current| {
+        current
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| Rc::new(SystemRunner))
+    })
+}
+
 /// Run an external command. Use the anyhow framework to bubble up errors if they occur. Will print
 /// the full command to be executed when pacdef is in debug mode.
 ///
+/// If [`set_print_commands`] was enabled, the command is printed instead.
+///
 /// # Errors
 ///
 /// This function will return an error if the command cannot be run or if it returns a non-zero
 /// exit status. In case of an error the full command will be part of the error message.
-pub fn run_external_command(mut cmd: Command) -> Result<()> {
+pub fn run_external_command(cmd: Command) -> Result<()> {
+    if PRINT_COMMANDS.load(Ordering::Relaxed) {
+        println!("{}", format_command(&cmd));
+        return Ok(());
+    }
+
+    print_debug_info(&cmd);
+
+    get_runner().run(cmd)
+}
+
+/// Run a command that is not part of the package management, like a user-configured
+/// hook. Unlike [`run_external_command`], it is run even if [`set_print_commands`]
+/// was enabled.
+///
+/// # Errors
+///
+/// This function will return an error if the command cannot be run or if it returns a
+/// non-zero exit status.
+pub fn run_command(cmd: Command) -> Result<()> {
+    print_debug_info(&cmd);
+
+    get_runner().run(cmd)
+}
+
+/// Run an external command and return its stdout as a `String`. Like
+/// [`run_external_command`], the full command is printed when pacdef is in debug mode.
+///
+/// # Errors
+///
+/// This function will return an error if the command cannot be run, if it returns a
+/// non-zero exit status, or if its output is not valid UTF-8.
+pub fn run_command_for_stdout(cmd: Command) -> Result<String> {
+    print_debug_info(&cmd);
+
+    get_runner().run_for_stdout(cmd)
+}
+
+/// Run a read-only command and return its exit status and output, without
+/// treating a non-zero exit status as an error. Like [`run_command_for_stdout`], it
+/// is run even if [`set_print_commands`] was enabled.
+///
+/// # Errors
+///
+/// This function will return an error if the command cannot be run.
+pub fn run_command_for_output(cmd: Command) -> Result<CommandOutput> {
+    print_debug_info(&cmd);
+
+    get_runner().run_for_output(cmd, false)
+}
+
+/// Run an external command that changes the system and return its exit status and
+/// output, which are also shown to the user while it runs. A non-zero exit status is
+/// not an error, see [`CommandOutput::ensure_success`].
+///
+/// If [`set_print_commands`] was enabled, the command is printed instead, and a
+/// successful run without output is returned.
+///
+/// # Errors
+///
+/// This function will return an error if the command cannot be run.
+#[cfg_attr(not(feature = "arch"), allow(dead_code))]
+pub fn run_external_command_for_output(cmd: Command) -> Result<CommandOutput> {
+    if PRINT_COMMANDS.load(Ordering::Relaxed) {
+        let command = format_command(&cmd);
+        println!("{command}");
+        return Ok(CommandOutput {
+            command,
+            success: true,
+            code: Some(0),
+            ..CommandOutput::default()
+        });
+    }
+
+    print_debug_info(&cmd);
+
+    get_runner().run_for_output(cmd, true)
+}
+
+/// Print the full command line of `cmd` if pacdef is in debug mode.
+fn print_debug_info(cmd: &Command) {
     if should_print_debug_info() {
         println!("will run the following command");
-        dbg!(&cmd);
+        println!("{}", format_command(cmd));
+    }
+}
+
+/// Format the full command line the way it would be typed into a shell, prefixed by
+/// the environment variables set for the command. Arguments are quoted if necessary,
+/// but never shortened. Variables removed from the environment are left out.
+fn format_command(cmd: &Command) -> String {
+    let mut parts: Vec<String> = cmd
+        .get_envs()
+        .filter_map(|(key, value)| {
+            let value = value?.to_string_lossy();
+            Some(format!("{}={}", key.to_string_lossy(), quote(&value)))
+        })
+        .collect();
+    parts.push(quote(&cmd.get_program().to_string_lossy()));
+    parts.extend(cmd.get_args().map(|arg| quote(&arg.to_string_lossy())));
+    parts.join(" ")
+}
+
+fn quote(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg
+            .chars()
+            .any(|c| c.is_whitespace() || "'\"$`\\*?;&|<>()#~".contains(c));
+
+    if needs_quotes {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    } else {
+        arg.to_string()
     }
+}
 
-    let exit_status = cmd
-        .status()
-        .with_context(|| format!("running command [{cmd:?}]"))?;
+/// A [`CommandRunner`] for tests that records the full command line of every
+/// command and returns `stdout` for all of them. Commands whose command line
+/// contains one of the `failing` arguments fail. [`CommandRunner::run_for_output`]
+/// returns the `outputs` in order before falling back to the same.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeRunner {
+    pub stdout: String,
+    pub failing: Vec<String>,
+    pub outputs: RefCell<VecDeque<CommandOutput>>,
+    pub commands: RefCell<Vec<String>>,
+}
+
+#[cfg(test)]
+impl FakeRunner {
+    fn fails(&self, line: &str) -> bool {
+        self.failing
+            .iter()
+            .any(|arg| line.split(' ').any(|part| part == arg))
+    }
+}
+
+#[cfg(test)]
+impl CommandRunner for FakeRunner {
+    fn run(&self, cmd: Command) -> Result<()> {
+        let line = format_command(&cmd);
+        self.commands.borrow_mut().push(line.clone());
+        if self.fails(&line) {
+            bail!("{line} failed");
+        }
+        Ok(())
+    }
+
+    fn run_for_stdout(&self, cmd: Command) -> Result<String> {
+        self.commands.borrow_mut().push(format_command(&cmd));
+        Ok(self.stdout.clone())
+    }
+
+    fn run_for_output(&self, cmd: Command, _echo: bool) -> Result<CommandOutput> {
+        let line = format_command(&cmd);
+        self.commands.borrow_mut().push(line.clone());
 
-    let success = exit_status.success();
-    ensure!(
-        success,
-        "command [{cmd:?}] returned non-zero exit status {success}"
-    );
-    Ok(())
+        if let Some(output) = self.outputs.borrow_mut().pop_front() {
+            return Ok(CommandOutput {
+                command: line,
+                ..output
+            });
+        }
+
+        let success = !self.fails(&line);
+        Ok(CommandOutput {
+            success,
+            code: Some(if success { 0 } else { 1 }),
+            stdout: self.stdout.clone(),
+            command: line,
+            ..CommandOutput::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_is_formatted_for_the_shell() {
+        let mut cmd = Command::new("sudo");
+        cmd.args(["dnf", "install", "--setopt=x=y z", "it's", ""]);
+
+        assert_eq!(
+            format_command(&cmd),
+            r"sudo dnf install '--setopt=x=y z' 'it'\''s' ''"
+        );
+
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "install-tool"])
+            .env("PACDEF_PACKAGE", "zls")
+            .env("PACDEF_REV", "");
+        assert_eq!(
+            format_command(&cmd),
+            "PACDEF_PACKAGE=zls PACDEF_REV='' sh -c install-tool"
+        );
+    }
+
+    #[test]
+    fn command_times_out() {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("10");
+        let mut child = cmd.spawn().expect("sleep can be run");
+
+        let start = Instant::now();
+        let result = wait_with_timeout(&mut child, Duration::from_millis(100), &cmd);
+
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let mut cmd = Command::new("echo");
+        cmd.arg("done");
+        let (status, stdout) =
+            output_with_timeout(&mut cmd, Duration::from_secs(5)).expect("echo can be run");
+        assert!(status.success());
+        assert_eq!(stdout, b"done\n");
+    }
 }
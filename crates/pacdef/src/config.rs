@@ -1,15 +1,18 @@
-use std::fs::{create_dir_all, read_to_string, File};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{create_dir_all, read_dir, read_to_string, File};
 use std::io::{ErrorKind, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::grouping::options::PackageOptions;
 use crate::prelude::*;
 
 // Update the master README if fields change.
 /// Config for the program, as listed in `$XDG_CONFIG_HOME/pacdef/pacdef.toml`.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     /// The AUR helper to use for Arch Linux.
     #[serde(default = "aur_helper")]
@@ -23,12 +26,290 @@ pub struct Config {
     /// Warn the user when a group is not a symlink.
     #[serde(default = "yes")]
     pub warn_not_symlinks: bool,
+    /// Sort the packages and normalize the formatting of a group file whenever
+    /// pacdef writes it. Off by default, so that hand formatting is kept.
+    #[serde(default)]
+    pub normalize_on_write: bool,
+    /// Groups that are always synced, even if `package sync --group` selects other
+    /// groups. Each of them must exist.
+    #[serde(default)]
+    pub always_groups: Vec<String>,
+    /// The group that pacdef offers to create from all installed packages, like
+    /// `pacdef bootstrap`, if no group files exist yet.
+    #[serde(default)]
+    pub default_group: Option<String>,
     /// Backends the user does not want to use even though the binary exists.
     #[serde(default)]
     pub disabled_backends: Vec<String>,
+    /// The backends that the packages in the `[all]` section of group files are
+    /// declared for, as far as they are enabled.
+    #[serde(default = "all_section_backends")]
+    pub all_section_backends: Vec<String>,
     /// Choose whether to use pipx instead of pip for python package management
     #[serde(default = "pip")]
     pub pip_binary: String,
+    /// Use `pacman` instead of `aur_helper` for read-only queries on Arch Linux.
+    #[serde(default)]
+    pub query_with_pacman: bool,
+    /// Fail instead of skipping a backend with declared packages if its binary is
+    /// not installed.
+    #[serde(default)]
+    pub strict_backends: bool,
+    /// Keep installing the remaining packages if some of them fail, and report all
+    /// failures at the end.
+    #[serde(default)]
+    pub continue_on_error: bool,
+    /// The backends whose packages are installed first, in this order. All other
+    /// backends follow afterwards. Packages are removed in the reverse order.
+    #[serde(default = "backend_order")]
+    pub backend_order: Vec<String>,
+    /// Glob patterns for package names that are never installed or removed.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Remove dependencies that are no longer needed together with a package on
+    /// Arch Linux.
+    #[serde(default = "yes")]
+    pub arch_remove_orphans: bool,
+    /// Also remove the dependencies of those dependencies that are no longer
+    /// needed, and so on, on Arch Linux. If false, only direct dependencies of the
+    /// removed packages are removed with them.
+    #[serde(default = "yes")]
+    pub arch_orphan_recursive: bool,
+    /// Delete the build directory of the AUR helper when cleaning the cache on Arch
+    /// Linux. Off by default, as it also deletes builds that are in progress.
+    #[serde(default)]
+    pub arch_prune_build_dir: bool,
+    /// Globs of files, like `/usr/share/icons/*`, that pacman may overwrite if an
+    /// install on Arch Linux fails only because of such files existing in the
+    /// filesystem. Empty by default, as overwriting files can break other packages.
+    #[serde(default)]
+    pub arch_overwrite_conflicts: Vec<String>,
+    /// Treat the packages that are built from the same pkgbase on Arch Linux as one,
+    /// so that declaring one of them, or the pkgbase itself, also covers the others.
+    /// Off by default, as it hides installed packages from `package unmanaged`.
+    #[serde(default)]
+    pub arch_group_split_packages: bool,
+    /// Globs of packages that are never removed on Arch Linux, in addition to
+    /// `pacman`, `glibc`, `systemd`, `linux`, the AUR helper and `HoldPkg`.
+    #[serde(default)]
+    pub arch_protected_packages: Vec<String>,
+    /// Refresh the package databases once before installing packages on Arch
+    /// Linux, so that packages added to the repositories since the last refresh
+    /// are found. Off by default, as refreshing without upgrading risks a partial
+    /// upgrade.
+    #[serde(default)]
+    pub arch_refresh_before_install: bool,
+    /// Consider a cargo crate installed even if it was installed with other features
+    /// or from another git source. A different target still counts as drift.
+    #[serde(default)]
+    pub cargo_ignore_feature_drift: bool,
+    /// Shell command to run after `package sync`. `{installed}` and `{removed}` are
+    /// replaced by the number of installed and removed packages.
+    #[serde(default)]
+    pub notify_command: Option<String>,
+    /// Refuse to remove more than this many packages in `package clean` and `package
+    /// review`, unless `--force` is given. Unlimited if unset.
+    #[serde(default)]
+    pub max_removals: Option<usize>,
+    /// Only show the changes of `package sync` and `package clean`, unless `--apply`
+    /// is given.
+    #[serde(default)]
+    pub require_apply: bool,
+    /// The backend of the package manager of the distribution. If unset, it is
+    /// detected from `/etc/os-release`. The other distribution backends are disabled.
+    #[serde(default)]
+    pub native_backend: Option<String>,
+    /// The elisp that `emacs --batch` evaluates to list the installed packages. It
+    /// must print one line per package: its name, followed by the names of the
+    /// packages it requires.
+    #[serde(default = "emacs_list_snippet")]
+    pub emacs_list_snippet: String,
+    /// The directory vim plugins are cloned into. Defaults to
+    /// `$XDG_DATA_HOME/nvim/site/pack/pacdef/start`.
+    #[serde(default)]
+    pub vim_plugin_dir: Option<String>,
+    /// Shell command to run after vim plugins were installed or removed, to let the
+    /// plugin manager pick up the changes.
+    #[serde(default)]
+    pub vim_manager_command: Option<String>,
+    /// The directory tmux plugins are cloned into. Defaults to `~/.tmux/plugins`.
+    #[serde(default)]
+    pub tpm_plugin_dir: Option<String>,
+    /// The binary of the package manager of zig tools. Defaults to `zig`.
+    #[serde(default = "zig_manager")]
+    pub zig_manager: String,
+    /// Shell command that installs a zig tool into the bin dir. Defaults to
+    /// building its git repository with `zig build`.
+    #[serde(default)]
+    pub zig_install_command: Option<String>,
+    /// The directory zig tools are installed into. Defaults to
+    /// `$XDG_DATA_HOME/zig/bin`.
+    #[serde(default)]
+    pub zig_bin_dir: Option<String>,
+    /// The container runtime whose images are managed, `podman` or `docker`. If
+    /// unset, podman is used if it is installed, and docker otherwise.
+    #[serde(default)]
+    pub container_runtime: Option<String>,
+    /// The time in seconds the installed packages of a backend are cached in the
+    /// state dir. `0` disables the cache.
+    #[serde(default)]
+    pub query_cache_ttl: u64,
+    /// The time in seconds after which a command of a backend is killed and fails.
+    /// Interactive backends are exempt, unless they have a timeout in
+    /// `command_timeouts`. No timeout if unset.
+    #[serde(default)]
+    pub command_timeout: Option<u64>,
+    /// Check at most once per day after `package sync` whether a newer pacdef was
+    /// released.
+    #[serde(default)]
+    pub update_check: bool,
+    /// The URL to get the latest version of pacdef from, instead of crates.io. It
+    /// must return a crates.io API response, or just the version.
+    #[serde(default)]
+    pub update_check_url: Option<String>,
+    /// The package file or URL to install an Arch package from with `pacman -U`,
+    /// keyed by package name. Packages not listed here are installed from the
+    /// repositories.
+    #[serde(default)]
+    pub arch_package_files: BTreeMap<String, String>,
+    /// The optional dependencies to install as dependencies along with an Arch
+    /// package, keyed by package name.
+    #[serde(default)]
+    pub arch_optional_deps: BTreeMap<String, Vec<String>>,
+    /// Whether asdf sets a version as global version after installing it, keyed by
+    /// package, e.g. `nodejs/20.5.0`. Versions not listed here are not set globally.
+    #[serde(default)]
+    pub asdf_global: BTreeMap<String, bool>,
+    /// Whether mise uses a version globally after installing it, keyed by package,
+    /// e.g. `node@20.5.0`. Versions not listed here are not used globally.
+    #[serde(default)]
+    pub mise_global: BTreeMap<String, bool>,
+    /// The archive to install an Emacs package from, keyed by package name. Packages
+    /// not listed here are installed from any archive that has them.
+    #[serde(default)]
+    pub emacs_archives: BTreeMap<String, String>,
+    /// The git URL to clone a vim plugin from, keyed by plugin name. Plugins not
+    /// listed here must be written as `<owner>/<repo>` to clone them from GitHub.
+    #[serde(default)]
+    pub vim_plugin_urls: BTreeMap<String, String>,
+    /// The git branch to clone a vim plugin from, keyed by plugin name. Plugins not
+    /// listed here are cloned from the default branch.
+    #[serde(default)]
+    pub vim_plugin_branches: BTreeMap<String, String>,
+    /// The git URL to clone a tmux plugin from, keyed by plugin name. Plugins not
+    /// listed here must be written as `<owner>/<repo>` to clone them from GitHub.
+    #[serde(default)]
+    pub tpm_plugin_urls: BTreeMap<String, String>,
+    /// The URL to install a zig tool from, keyed by tool name. Tools not listed here
+    /// must be written as `<owner>/<repo>` to install them from GitHub.
+    #[serde(default)]
+    pub zig_urls: BTreeMap<String, String>,
+    /// The git ref to install a zig tool from, keyed by tool name. Tools not listed
+    /// here are installed from the default branch.
+    #[serde(default)]
+    pub zig_revs: BTreeMap<String, String>,
+    /// The path or URL of the zip file to install a GNOME Shell extension from, keyed
+    /// by UUID. Extensions not listed here are downloaded from extensions.gnome.org.
+    #[serde(default)]
+    pub gnome_extension_urls: BTreeMap<String, String>,
+    /// Whether to enable a GNOME Shell extension after installing it, keyed by UUID.
+    /// Extensions not listed here are not enabled.
+    #[serde(default)]
+    pub gnome_extension_enable: BTreeMap<String, bool>,
+    /// The cabal flags to build a Haskell package with, e.g. `+threaded -pedantic`,
+    /// keyed by package name.
+    #[serde(default)]
+    pub cabal_flags: BTreeMap<String, String>,
+    /// The version constraint of a Haskell package, e.g. `==3.8`, keyed by package
+    /// name. Packages not listed here are installed in the latest version.
+    #[serde(default)]
+    pub cabal_constraints: BTreeMap<String, String>,
+    /// The version to install a Perl module in, e.g. `1.0048` for exactly that
+    /// version or `>= 2.0` for a range, keyed by module name. Modules not listed here
+    /// are installed in the latest version.
+    #[serde(default)]
+    pub cpanm_versions: BTreeMap<String, String>,
+    /// Whether to install a Perl module without running its tests, keyed by module
+    /// name. Modules not listed here are tested.
+    #[serde(default)]
+    pub cpanm_notest: BTreeMap<String, bool>,
+    /// The target triple to install a cargo crate for, keyed by crate name. Crates
+    /// not listed here are installed for the host.
+    #[serde(default)]
+    pub cargo_targets: BTreeMap<String, String>,
+    /// The rustup toolchain to install a cargo crate with, keyed by crate name. Crates
+    /// not listed here are installed with the active toolchain.
+    #[serde(default)]
+    pub cargo_toolchains: BTreeMap<String, String>,
+    /// The git URL to install a cargo crate from, keyed by crate name. Crates not
+    /// listed here are installed from crates.io.
+    #[serde(default)]
+    pub cargo_git_urls: BTreeMap<String, String>,
+    /// The git branch to install a cargo crate from, keyed by crate name. Only used
+    /// for crates in `cargo_git_urls`.
+    #[serde(default)]
+    pub cargo_git_branches: BTreeMap<String, String>,
+    /// The git tag to install a cargo crate from, keyed by crate name. Only used for
+    /// crates in `cargo_git_urls`.
+    #[serde(default)]
+    pub cargo_git_tags: BTreeMap<String, String>,
+    /// The git commit to install a cargo crate from, keyed by crate name. Only used
+    /// for crates in `cargo_git_urls`.
+    #[serde(default)]
+    pub cargo_git_revs: BTreeMap<String, String>,
+    /// The features to install a cargo crate with, keyed by crate name.
+    #[serde(default)]
+    pub cargo_features: BTreeMap<String, Vec<String>>,
+    /// Whether to install a cargo crate with all of its features, keyed by crate
+    /// name.
+    #[serde(default)]
+    pub cargo_all_features: BTreeMap<String, bool>,
+    /// Whether to install a cargo crate without its default features, keyed by crate
+    /// name.
+    #[serde(default)]
+    pub cargo_no_default_features: BTreeMap<String, bool>,
+    /// The binaries of a cargo crate, keyed by crate name. Crates not listed here
+    /// provide a binary named like the crate. Only used to detect crates that were
+    /// installed with `cargo install --no-track`.
+    #[serde(default)]
+    pub cargo_binaries: BTreeMap<String, Vec<String>>,
+    /// The rustup profile to use when installing a toolchain, keyed by toolchain name.
+    /// Toolchains not listed here use the `default` profile.
+    #[serde(default)]
+    pub rustup_profiles: BTreeMap<String, String>,
+    /// The install priority of packages, keyed by backend and package name. Within a
+    /// backend, packages with a higher priority are installed first. Unlisted packages
+    /// have priority 0.
+    #[serde(default)]
+    pub priorities: BTreeMap<String, BTreeMap<String, i32>>,
+    /// Packages that are not available on every machine, keyed by backend. A failure
+    /// to install them only results in a warning.
+    #[serde(default)]
+    pub optional_packages: BTreeMap<String, BTreeSet<String>>,
+    /// The machine architecture, as `uname -m` prints it, that a package is only
+    /// installed on, keyed by backend and package name. On other machines, the
+    /// package is neither installed nor removed.
+    #[serde(default)]
+    pub package_architectures: BTreeMap<String, BTreeMap<String, String>>,
+    /// Whether a backend may prompt on the terminal while installing or removing
+    /// packages, keyed by backend. Overrides the default of the backend.
+    #[serde(default)]
+    pub interactive_backends: BTreeMap<String, bool>,
+    /// The time in seconds after which a command is killed, keyed by backend. Takes
+    /// precedence over `command_timeout`.
+    #[serde(default)]
+    pub command_timeouts: BTreeMap<String, u64>,
+    /// Additional arguments appended to every install command, keyed by backend.
+    #[serde(default)]
+    pub extra_args: BTreeMap<String, Vec<String>>,
+    /// Whether a failure of a backend stops the installation, keyed by backend.
+    /// Takes precedence over `continue_on_error`.
+    #[serde(default)]
+    pub stop_on_error: BTreeMap<String, bool>,
+    /// Whether a backend installs, upgrades and removes packages without asking for
+    /// confirmation, keyed by backend. `--no-confirm` applies to all backends.
+    #[serde(default)]
+    pub assume_yes: BTreeMap<String, bool>,
 }
 
 fn yes() -> bool {
@@ -39,17 +320,89 @@ fn aur_helper() -> String {
     "paru".into()
 }
 
+fn all_section_backends() -> Vec<String> {
+    NATIVE_BACKENDS.map(String::from).to_vec()
+}
+
+fn backend_order() -> Vec<String> {
+    vec!["rustup".into(), "rust".into()]
+}
+
+/// The name of the dir next to the config file that holds config fragments.
+const FRAGMENT_DIR_NAME: &str = "conf.d";
+
+/// All `*.toml` files in `dir`, in lexical order. Empty if `dir` does not exist.
+fn get_fragment_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut result: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    result.sort();
+    result
+}
+
+/// Merge the config fragment at `path` into `value`, unless the fragment cannot be
+/// parsed or the result is not a valid config.
+fn merge_fragment(value: &mut toml::Value, path: &Path) -> Result<()> {
+    let content = read_to_string(path).context("reading file")?;
+    let fragment: toml::Value = toml::from_str(&content).context("parsing toml")?;
+
+    let mut merged = value.clone();
+    deep_merge(&mut merged, fragment);
+    merged
+        .clone()
+        .try_into::<Config>()
+        .context("validating the merged config")?;
+
+    *value = merged;
+    Ok(())
+}
+
+/// Merge `other` into `base`. Tables are merged key by key, all other values of
+/// `other`, including arrays, replace those in `base`.
+fn deep_merge(base: &mut toml::Value, other: toml::Value) {
+    match (base, other) {
+        (toml::Value::Table(base), toml::Value::Table(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
 fn pip() -> String {
     "pip".into()
 }
 
+fn emacs_list_snippet() -> String {
+    crate::backend::actual::emacs::DEFAULT_LIST_SNIPPET.into()
+}
+
+fn zig_manager() -> String {
+    "zig".into()
+}
+
 impl Config {
-    /// Load the config from the associated file.
+    /// Load the config from the associated file, and merge all fragments from the
+    /// `conf.d` dir next to it. Fragments that cannot be read or would make the
+    /// config invalid are skipped with a warning.
     ///
     /// # Errors
     ///
     /// This function will return an error if the config file exists but cannot be
-    /// read, its contents are not UTF-8, or the file is malformed.
+    /// read, its contents are not UTF-8, or the file is malformed, e.g. because pacdef
+    /// was killed while writing it. Only a missing file is reported as
+    /// [`Error::ConfigFileNotFound`].
     pub fn load(config_file: &Path) -> Result<Self> {
         let from_file = read_to_string(config_file);
 
@@ -63,7 +416,503 @@ impl Config {
             }
         };
 
-        toml::from_str(&content).context("parsing toml config")
+        let malformed = || {
+            format!(
+                "config file {} is malformed, it may have been cut off while it was written; \
+                 fix it, or remove it to start over with the defaults",
+                config_file.to_string_lossy()
+            )
+        };
+
+        let config = toml::from_str(&content).with_context(malformed)?;
+
+        let fragments = get_fragment_paths(&config_file.with_file_name(FRAGMENT_DIR_NAME));
+        if fragments.is_empty() {
+            return Ok(config);
+        }
+
+        let mut value: toml::Value = toml::from_str(&content).with_context(malformed)?;
+        for fragment in fragments {
+            if let Err(error) = merge_fragment(&mut value, &fragment) {
+                let path = fragment.to_string_lossy();
+                log::warn!("ignoring config fragment {path}: {error:#}");
+            }
+        }
+
+        value.try_into().context("parsing merged toml config")
+    }
+
+    /// Disable the backends of all distributions except the `native_backend`. If it
+    /// is not set, it is detected from `/etc/os-release`. If that fails, all backends
+    /// stay enabled.
+    pub fn detect_backends(&mut self) {
+        let native = match &self.native_backend {
+            Some(native) => native.clone(),
+            None => {
+                let Ok(os_release) = read_to_string(OS_RELEASE) else {
+                    return;
+                };
+                let Some(native) = native_backend_from_os_release(&os_release) else {
+                    return;
+                };
+                log::info!("auto-selected native backend '{native}' from {OS_RELEASE}");
+                native.to_string()
+            }
+        };
+
+        for section in NATIVE_BACKENDS {
+            if section != native && !self.disabled_backends.iter().any(|b| b == section) {
+                self.disabled_backends.push(section.to_string());
+            }
+        }
+    }
+
+    /// Get the sections of the enabled backends that the `[all]` section of group
+    /// files applies to.
+    pub fn sections_for_all(&self) -> impl Iterator<Item = &str> {
+        self.all_section_backends
+            .iter()
+            .filter(|section| !self.disabled_backends.contains(section))
+            .map(String::as_str)
+    }
+
+    /// Merge the package options from the sidecar files of all `groups` into the
+    /// corresponding maps. If an option is also set here, the sidecar file wins.
+    pub fn merge_group_options(&mut self, groups: &Groups) {
+        for group in groups {
+            for (section, packages) in &group.options {
+                for (package, options) in packages {
+                    self.merge_package_options(&group.name, section, package, options);
+                }
+            }
+        }
+    }
+
+    fn merge_package_options(
+        &mut self,
+        group: &str,
+        section: &str,
+        package: &str,
+        options: &PackageOptions,
+    ) {
+        self.merge_flags(group, section, package, options);
+
+        // an Arch package is installed from either a file or a URL
+        let arch_source = options.file.clone().or_else(|| options.url.clone());
+
+        let supported = match section {
+            "arch" => vec![("file", &arch_source, &mut self.arch_package_files)],
+            "rust" => vec![
+                ("target", &options.target, &mut self.cargo_targets),
+                ("toolchain", &options.toolchain, &mut self.cargo_toolchains),
+                ("url", &options.url, &mut self.cargo_git_urls),
+                ("branch", &options.branch, &mut self.cargo_git_branches),
+                ("tag", &options.tag, &mut self.cargo_git_tags),
+                ("rev", &options.rev, &mut self.cargo_git_revs),
+            ],
+            "rustup" => vec![("profile", &options.profile, &mut self.rustup_profiles)],
+            "emacs" => vec![("archive", &options.archive, &mut self.emacs_archives)],
+            "vim" => vec![
+                ("url", &options.url, &mut self.vim_plugin_urls),
+                ("branch", &options.branch, &mut self.vim_plugin_branches),
+            ],
+            "tpm" => vec![("url", &options.url, &mut self.tpm_plugin_urls)],
+            "zig" => vec![
+                ("url", &options.url, &mut self.zig_urls),
+                ("rev", &options.rev, &mut self.zig_revs),
+            ],
+            "gnome" => vec![("url", &options.url, &mut self.gnome_extension_urls)],
+            "cabal" => vec![
+                ("flags", &options.flags, &mut self.cabal_flags),
+                (
+                    "constraint",
+                    &options.constraint,
+                    &mut self.cabal_constraints,
+                ),
+            ],
+            "cpanm" => vec![("version", &options.version, &mut self.cpanm_versions)],
+            _ => vec![],
+        };
+
+        let set = [
+            &options.target,
+            &options.toolchain,
+            &options.profile,
+            &options.archive,
+            &options.url,
+            &options.branch,
+            &options.tag,
+            &options.rev,
+            &options.flags,
+            &options.constraint,
+            &options.version,
+            &options.file,
+        ]
+        .into_iter()
+        .filter(|value| value.is_some())
+        .count();
+        let used = supported
+            .iter()
+            .filter(|(_, value, _)| value.is_some())
+            .count();
+
+        if set > used {
+            log::warn!(
+                "ignoring options of {package} [{section}] in group '{group}' that the backend does not support"
+            );
+        }
+
+        for (option, value, map) in supported {
+            let Some(value) = value else {
+                continue;
+            };
+
+            if let Some(old) = map.insert(package.to_string(), value.clone()) {
+                if &old != value {
+                    log::warn!(
+                        "{option} of {package} [{section}] is '{old}' in the config, but '{value}' in group '{group}', using '{value}'"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Merge the options that are not a plain string per package.
+    fn merge_flags(&mut self, group: &str, section: &str, package: &str, options: &PackageOptions) {
+        if let Some(priority) = options.priority {
+            self.merge_priority(group, section, package, priority);
+        }
+
+        if let Some(optional) = options.optional {
+            self.merge_optional(section, package, optional);
+        }
+
+        if let Some(enable) = options.enable {
+            self.merge_enable(group, section, package, enable);
+        }
+
+        if let Some(global) = options.global {
+            self.merge_global(group, section, package, global);
+        }
+
+        if let Some(notest) = options.notest {
+            self.merge_notest(group, section, package, notest);
+        }
+
+        if let Some(arch) = &options.arch {
+            self.merge_architecture(group, section, package, arch);
+        }
+
+        self.merge_features(group, section, package, options);
+
+        if let Some(optional_deps) = &options.optional_deps {
+            self.merge_optional_deps(group, section, package, optional_deps);
+        }
+    }
+
+    fn merge_priority(&mut self, group: &str, section: &str, package: &str, priority: i32) {
+        let old = self
+            .priorities
+            .entry(section.to_string())
+            .or_default()
+            .insert(package.to_string(), priority);
+
+        if let Some(old) = old.filter(|old| *old != priority) {
+            log::warn!(
+                "priority of {package} [{section}] is '{old}' in the config, but '{priority}' in group '{group}', using '{priority}'"
+            );
+        }
+    }
+
+    fn merge_architecture(&mut self, group: &str, section: &str, package: &str, arch: &str) {
+        let old = self
+            .package_architectures
+            .entry(section.to_string())
+            .or_default()
+            .insert(package.to_string(), arch.to_string());
+
+        if let Some(old) = old.filter(|old| old != arch) {
+            log::warn!(
+                "arch of {package} [{section}] is '{old}' in the config, but '{arch}' in group '{group}', using '{arch}'"
+            );
+        }
+    }
+
+    fn merge_enable(&mut self, group: &str, section: &str, package: &str, enable: bool) {
+        if section != "gnome" {
+            log::warn!(
+                "ignoring option 'enable' of {package} [{section}] in group '{group}', which only GNOME Shell extensions support"
+            );
+            return;
+        }
+
+        self.gnome_extension_enable
+            .insert(package.to_string(), enable);
+    }
+
+    fn merge_global(&mut self, group: &str, section: &str, package: &str, global: bool) {
+        let map = match section {
+            "asdf" => &mut self.asdf_global,
+            "mise" => &mut self.mise_global,
+            _ => {
+                log::warn!(
+                    "ignoring option 'global' of {package} [{section}] in group '{group}', which only asdf and mise support"
+                );
+                return;
+            }
+        };
+
+        map.insert(package.to_string(), global);
+    }
+
+    fn merge_features(
+        &mut self,
+        group: &str,
+        section: &str,
+        package: &str,
+        options: &PackageOptions,
+    ) {
+        let set = options.features.is_some()
+            || options.all_features.is_some()
+            || options.no_default_features.is_some();
+        if !set {
+            return;
+        }
+        if section != "rust" {
+            log::warn!(
+                "ignoring the features of {package} [{section}] in group '{group}', which only cargo crates support"
+            );
+            return;
+        }
+
+        if let Some(features) = &options.features {
+            self.cargo_features
+                .insert(package.to_string(), features.clone());
+        }
+        if let Some(all_features) = options.all_features {
+            self.cargo_all_features
+                .insert(package.to_string(), all_features);
+        }
+        if let Some(no_default_features) = options.no_default_features {
+            self.cargo_no_default_features
+                .insert(package.to_string(), no_default_features);
+        }
+    }
+
+    fn merge_optional_deps(
+        &mut self,
+        group: &str,
+        section: &str,
+        package: &str,
+        optional_deps: &[String],
+    ) {
+        if section != "arch" {
+            log::warn!(
+                "ignoring option 'optional_deps' of {package} [{section}] in group '{group}', which only Arch packages support"
+            );
+            return;
+        }
+
+        self.arch_optional_deps
+            .insert(package.to_string(), optional_deps.to_vec());
+    }
+
+    fn merge_notest(&mut self, group: &str, section: &str, package: &str, notest: bool) {
+        if section != "cpanm" {
+            log::warn!(
+                "ignoring option 'notest' of {package} [{section}] in group '{group}', which only Perl modules support"
+            );
+            return;
+        }
+
+        self.cpanm_notest.insert(package.to_string(), notest);
+    }
+
+    fn merge_optional(&mut self, section: &str, package: &str, optional: bool) {
+        let packages = self
+            .optional_packages
+            .entry(section.to_string())
+            .or_default();
+
+        if optional {
+            packages.insert(package.to_string());
+        } else {
+            packages.remove(package);
+        }
+    }
+
+    /// Get the position of the backend with the given `section` name in the
+    /// `backend_order`. Unlisted backends come after all listed ones.
+    pub fn backend_position(&self, section: &str) -> usize {
+        self.backend_order
+            .iter()
+            .position(|listed| listed == section)
+            .unwrap_or(self.backend_order.len())
+    }
+
+    /// Guard against a broken group dir or backend query making every package look
+    /// unmanaged, by refusing to remove more than `max_removals` packages without
+    /// `force`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if more than `max_removals` packages
+    /// would be removed and `force` is not set.
+    pub fn check_max_removals(&self, count: usize, force: bool) -> Result<()> {
+        let Some(max) = self.max_removals else {
+            return Ok(());
+        };
+
+        if count <= max {
+            return Ok(());
+        }
+
+        if force {
+            log::warn!(
+                "removing {count} packages, more than max_removals ({max}), because of --force"
+            );
+            return Ok(());
+        }
+
+        bail!(
+            "refusing to remove {count} packages, which is more than max_removals ({max}). Check your group files, or pass --force to remove them anyway"
+        );
+    }
+
+    /// Check whether installing the packages of the backend with the given `section`
+    /// name goes on after a failure, by `stop_on_error` or else by
+    /// `continue_on_error`.
+    pub fn continues_on_error(&self, section: &str) -> bool {
+        self.stop_on_error
+            .get(section)
+            .map_or(self.continue_on_error, |stop| !stop)
+    }
+
+    /// Replace the `backend_order` by the one in `file`, which lists one backend per
+    /// line. A backend with a `!` suffix stops the installation if it fails, the
+    /// others do not. Empty lines and comments starting with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the file cannot be read, or a backend is
+    /// unknown or listed twice.
+    pub fn load_backend_order_file(&mut self, file: &Path) -> Result<()> {
+        let content = read_to_string(file)
+            .with_context(|| format!("reading backend order file {}", file.to_string_lossy()))?;
+
+        let order = parse_backend_order(&content, self)
+            .with_context(|| format!("parsing backend order file {}", file.to_string_lossy()))?;
+
+        self.backend_order = order.iter().map(|(section, _)| section.clone()).collect();
+        self.stop_on_error.extend(order);
+        Ok(())
+    }
+
+    /// Make sure that `section` names a backend, either one that pacdef was built
+    /// with, an external backend in `PATH`, or the backend of a distribution.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error listing the known backends otherwise.
+    pub fn ensure_backend(&self, section: &str) -> Result<()> {
+        if NATIVE_BACKENDS.contains(&section) || AnyBackend::from_section(section, self).is_ok() {
+            return Ok(());
+        }
+
+        let mut known: Vec<&str> = AnyBackend::all(self)
+            .map(|backend| backend.backend_info().section)
+            .chain(NATIVE_BACKENDS)
+            .collect();
+        known.sort_unstable();
+        known.dedup();
+        bail!(
+            "unknown backend {section}, expected one of {}",
+            known.join(", ")
+        )
+    }
+
+    /// Make sure that the keys of `stop_on_error` and `assume_yes` name backends.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error for the first key that is not a backend.
+    pub fn ensure_backend_keys(&self) -> Result<()> {
+        for section in self.stop_on_error.keys() {
+            self.ensure_backend(section)
+                .context("checking stop_on_error")?;
+        }
+        for section in self.assume_yes.keys() {
+            self.ensure_backend(section)
+                .context("checking assume_yes and --assume-yes-for")?;
+        }
+        Ok(())
+    }
+
+    /// Check whether `backend` must not ask for confirmation, either because of
+    /// `no_confirm` for all backends or because of `assume_yes` for this one.
+    pub fn no_confirm_for(&self, backend: &AnyBackend, no_confirm: bool) -> bool {
+        no_confirm
+            || self
+                .assume_yes
+                .get(backend.backend_info().section)
+                .copied()
+                .unwrap_or_default()
+    }
+
+    /// Check whether `backend` may prompt on the terminal, by `interactive_backends`
+    /// or else by the default of the backend.
+    pub fn is_interactive(&self, backend: &AnyBackend) -> bool {
+        self.interactive_backends
+            .get(backend.backend_info().section)
+            .copied()
+            .unwrap_or_else(|| backend.is_interactive())
+    }
+
+    /// Get the timeout of the commands of `backend`, by `command_timeouts`, or else
+    /// by `command_timeout` if the backend is not interactive.
+    pub fn command_timeout_for(&self, backend: &AnyBackend) -> Option<Duration> {
+        let section = backend.backend_info().section;
+
+        let seconds = match self.command_timeouts.get(section) {
+            Some(seconds) => Some(*seconds),
+            None if self.is_interactive(backend) => None,
+            None => self.command_timeout,
+        };
+
+        seconds.map(Duration::from_secs)
+    }
+
+    /// Get the install priority of the package `name` of the backend with the given
+    /// `section` name.
+    pub fn priority_of(&self, section: &str, name: &str) -> i32 {
+        self.priorities
+            .get(section)
+            .and_then(|packages| packages.get(name))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Check whether the package `name` of the backend with the given `section` name
+    /// is optional.
+    pub fn is_optional(&self, section: &str, name: &str) -> bool {
+        self.optional_packages
+            .get(section)
+            .is_some_and(|packages| packages.contains(name))
+    }
+
+    /// Get the architecture that the package `name` of the backend with the given
+    /// `section` name is only installed on, if any.
+    pub fn architecture_of(&self, section: &str, name: &str) -> Option<&str> {
+        self.package_architectures
+            .get(section)
+            .and_then(|packages| packages.get(name))
+            .map(String::as_str)
+    }
+
+    /// Get the additional install arguments the user configured for the backend
+    /// with the given `section` name.
+    pub fn extra_args_for(&self, section: &str) -> Vec<String> {
+        self.extra_args.get(section).cloned().unwrap_or_default()
     }
 
     /// Save the instance of [`Config`] to disk.
@@ -87,6 +936,35 @@ impl Config {
     }
 }
 
+const OS_RELEASE: &str = "/etc/os-release";
+
+/// The backends of the package managers of distributions, of which only one is used.
+pub const NATIVE_BACKENDS: [&str; 4] = ["arch", "debian", "fedora", "void"];
+
+/// Get the native backend from the `ID` of the distribution in the content of
+/// `/etc/os-release`, or else from the distributions in `ID_LIKE`.
+fn native_backend_from_os_release(os_release: &str) -> Option<&'static str> {
+    let value_of = |key: &str| {
+        os_release.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim_matches('"').to_string())
+        })
+    };
+
+    let id = value_of("ID").unwrap_or_default();
+    let id_like = value_of("ID_LIKE").unwrap_or_default();
+
+    std::iter::once(id.as_str())
+        .chain(id_like.split_whitespace())
+        .find_map(|id| match id {
+            "arch" => Some("arch"),
+            "debian" | "ubuntu" => Some("debian"),
+            "fedora" => Some("fedora"),
+            "void" => Some("void"),
+            _ => None,
+        })
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -94,8 +972,274 @@ impl Default for Config {
             aur_rm_args: vec![],
             flatpak_systemwide: true,
             warn_not_symlinks: true,
+            normalize_on_write: false,
+            always_groups: vec![],
+            default_group: None,
             disabled_backends: vec![],
+            all_section_backends: all_section_backends(),
             pip_binary: "pip".into(),
+            query_with_pacman: false,
+            strict_backends: false,
+            continue_on_error: false,
+            backend_order: backend_order(),
+            exclude: vec![],
+            arch_remove_orphans: true,
+            arch_orphan_recursive: true,
+            arch_prune_build_dir: false,
+            arch_overwrite_conflicts: vec![],
+            arch_group_split_packages: false,
+            arch_protected_packages: vec![],
+            arch_refresh_before_install: false,
+            cargo_ignore_feature_drift: false,
+            notify_command: None,
+            max_removals: None,
+            require_apply: false,
+            native_backend: None,
+            emacs_list_snippet: emacs_list_snippet(),
+            vim_plugin_dir: None,
+            vim_manager_command: None,
+            tpm_plugin_dir: None,
+            zig_manager: zig_manager(),
+            zig_install_command: None,
+            zig_bin_dir: None,
+            container_runtime: None,
+            query_cache_ttl: 0,
+            command_timeout: None,
+            update_check: false,
+            update_check_url: None,
+            arch_package_files: BTreeMap::new(),
+            arch_optional_deps: BTreeMap::new(),
+            asdf_global: BTreeMap::new(),
+            mise_global: BTreeMap::new(),
+            emacs_archives: BTreeMap::new(),
+            vim_plugin_urls: BTreeMap::new(),
+            vim_plugin_branches: BTreeMap::new(),
+            tpm_plugin_urls: BTreeMap::new(),
+            zig_urls: BTreeMap::new(),
+            zig_revs: BTreeMap::new(),
+            gnome_extension_urls: BTreeMap::new(),
+            gnome_extension_enable: BTreeMap::new(),
+            cabal_flags: BTreeMap::new(),
+            cabal_constraints: BTreeMap::new(),
+            cpanm_versions: BTreeMap::new(),
+            cpanm_notest: BTreeMap::new(),
+            cargo_targets: BTreeMap::new(),
+            cargo_toolchains: BTreeMap::new(),
+            cargo_git_urls: BTreeMap::new(),
+            cargo_git_branches: BTreeMap::new(),
+            cargo_git_tags: BTreeMap::new(),
+            cargo_git_revs: BTreeMap::new(),
+            cargo_features: BTreeMap::new(),
+            cargo_all_features: BTreeMap::new(),
+            cargo_no_default_features: BTreeMap::new(),
+            cargo_binaries: BTreeMap::new(),
+            rustup_profiles: BTreeMap::new(),
+            priorities: BTreeMap::new(),
+            optional_packages: BTreeMap::new(),
+            package_architectures: BTreeMap::new(),
+            interactive_backends: BTreeMap::new(),
+            command_timeouts: BTreeMap::new(),
+            extra_args: BTreeMap::new(),
+            stop_on_error: BTreeMap::new(),
+            assume_yes: BTreeMap::new(),
         }
     }
 }
+
+/// Parse the lines of a backend order file into the backends and whether they stop
+/// on error, in order. Every backend must be known to the `config`.
+fn parse_backend_order(content: &str, config: &Config) -> Result<Vec<(String, bool)>> {
+    let mut order: Vec<(String, bool)> = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (section, stop) = line
+            .strip_suffix('!')
+            .map_or((line, false), |section| (section.trim_end(), true));
+        if let Err(error) = config.ensure_backend(section) {
+            bail!("line {}: {error}", number + 1);
+        }
+        ensure!(
+            !order.iter().any(|(listed, _)| listed == section),
+            "line {}: backend {section} is listed twice",
+            number + 1
+        );
+        order.push((section.to_string(), stop));
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_order_file() {
+        let config = Config::default();
+        let content = "# native packages first\narch !\nrustup!\n\nrust # then crates\npython\n";
+        let order = parse_backend_order(content, &config).expect("file is valid");
+        assert_eq!(
+            order,
+            [
+                ("arch".to_string(), true),
+                ("rustup".to_string(), true),
+                ("rust".to_string(), false),
+                ("python".to_string(), false),
+            ]
+        );
+
+        let mut config = Config {
+            continue_on_error: false,
+            stop_on_error: order.into_iter().collect(),
+            ..Config::default()
+        };
+        assert!(!config.continues_on_error("rustup"));
+        assert!(config.continues_on_error("rust"));
+        assert!(!config.continues_on_error("flatpak"));
+        config.continue_on_error = true;
+        assert!(config.continues_on_error("flatpak"));
+
+        assert!(parse_backend_order("rust\nrust!\n", &config).is_err());
+        let error = parse_backend_order("rustup!\ncargo\n", &config).expect_err("cargo is unknown");
+        assert!(error
+            .to_string()
+            .starts_with("line 2: unknown backend cargo"));
+    }
+
+    #[test]
+    fn assume_yes_per_backend() {
+        let config = Config {
+            assume_yes: [("rust".to_string(), true), ("void".to_string(), false)].into(),
+            ..Config::default()
+        };
+        let rust = AnyBackend::from_section("rust", &config).expect("rust is built in");
+        let void = AnyBackend::from_section("void", &config).expect("void is built in");
+
+        assert!(config.no_confirm_for(&rust, false));
+        assert!(!config.no_confirm_for(&void, false));
+        assert!(config.no_confirm_for(&void, true));
+        assert!(config.ensure_backend_keys().is_ok());
+
+        let config = Config {
+            assume_yes: [("cargo".to_string(), true)].into(),
+            ..Config::default()
+        };
+        assert!(config.ensure_backend_keys().is_err());
+    }
+
+    #[test]
+    fn native_backend_detection() {
+        let os_release = "NAME=\"EndeavourOS\"\nID=\"endeavouros\"\nID_LIKE=\"arch\"\n";
+        assert_eq!(native_backend_from_os_release(os_release), Some("arch"));
+
+        let os_release = "NAME=\"Linux Mint\"\nID=linuxmint\nID_LIKE=\"ubuntu debian\"\n";
+        assert_eq!(native_backend_from_os_release(os_release), Some("debian"));
+
+        assert_eq!(native_backend_from_os_release("ID=nixos\n"), None);
+    }
+
+    #[test]
+    fn max_removals() {
+        let mut config = Config::default();
+        assert!(config.check_max_removals(100, false).is_ok());
+
+        config.max_removals = Some(2);
+        assert!(config.check_max_removals(2, false).is_ok());
+        assert!(config.check_max_removals(3, false).is_err());
+        assert!(config.check_max_removals(3, true).is_ok());
+    }
+
+    #[test]
+    fn architecture_from_sidecar() {
+        let options = PackageOptions {
+            arch: Some("x86_64".into()),
+            ..PackageOptions::default()
+        };
+        let mut config = Config::default();
+        config.merge_package_options("base", "arch", "lib32-glibc", &options);
+
+        assert_eq!(
+            config.architecture_of("arch", "lib32-glibc"),
+            Some("x86_64")
+        );
+        assert_eq!(config.architecture_of("arch", "glibc"), None);
+        assert_eq!(config.architecture_of("debian", "lib32-glibc"), None);
+    }
+
+    #[test]
+    fn config_can_be_dumped() {
+        let mut config = Config::default();
+        config
+            .arch_package_files
+            .insert("foo".into(), "/tmp/foo-1.0-1-x86_64.pkg.tar.zst".into());
+
+        let content = toml::to_string(&config).expect("values come before tables");
+        let dumped: Config = toml::from_str(&content).expect("dump is a valid config");
+        assert_eq!(dumped.arch_package_files, config.arch_package_files);
+    }
+
+    #[test]
+    fn truncated_config_is_not_missing() {
+        let path = std::env::temp_dir().join(format!(
+            "pacdef-truncated-config-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, "aur_helper = \"paru\"\ndisabled_backends = [\"py")
+            .expect("temp dir is writable");
+
+        let result = Config::load(&path);
+        std::fs::remove_file(&path).expect("temp file can be removed");
+        let error = result.expect_err("config is cut off");
+
+        assert!(error.downcast_ref::<Error>().is_none());
+        let message = format!("{error:#}");
+        assert!(message.contains("is malformed"));
+        assert!(message.contains("at line 2"));
+    }
+
+    #[test]
+    fn fragments_are_merged_in_order() {
+        let dir = std::env::temp_dir().join(format!(
+            "pacdef-fragments-are-merged-in-order-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let fragment_dir = dir.join(FRAGMENT_DIR_NAME);
+        create_dir_all(&fragment_dir).expect("temp dir is writable");
+
+        let write = |path: PathBuf, content: &str| {
+            std::fs::write(path, content).expect("temp dir is writable");
+        };
+        write(
+            dir.join("pacdef.toml"),
+            "warn_not_symlinks = false\n[cargo_targets]\na = \"x\"\n",
+        );
+        write(
+            fragment_dir.join("10-targets.toml"),
+            "[cargo_targets]\nb = \"y\"\n",
+        );
+        write(
+            fragment_dir.join("20-invalid.toml"),
+            "max_removals = \"ten\"\n",
+        );
+        write(
+            fragment_dir.join("30-host.toml"),
+            "warn_not_symlinks = true\n",
+        );
+        write(fragment_dir.join("40-ignored.txt"), "max_removals = 1\n");
+
+        let result = Config::load(&dir.join("pacdef.toml"));
+        std::fs::remove_dir_all(&dir).expect("temp dir can be removed");
+        let config = result.expect("base config is valid");
+
+        assert!(config.warn_not_symlinks);
+        assert_eq!(config.max_removals, None);
+        assert_eq!(
+            config.cargo_targets,
+            [("a".into(), "x".into()), ("b".into(), "y".into())].into()
+        );
+    }
+}
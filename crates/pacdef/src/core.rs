@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env::current_dir;
-use std::fs::{copy, create_dir_all, remove_file, rename, File};
+use std::fs::{copy, create_dir_all, read_to_string, remove_file, rename, write, File};
+use std::io::{stdin, stdout, IsTerminal};
 use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -8,14 +9,31 @@ use std::process::Command;
 use anyhow::{bail, ensure, Context, Result};
 use const_format::formatcp;
 
-use crate::cmd::{run_edit_command, run_external_command};
-use crate::env::{get_editor, should_print_debug_info};
-use crate::grouping::group::groups_to_backend_packages;
-use crate::path::{binary_in_path, get_absolutized_file_paths, get_group_dir};
+use crate::backend::moved::warn_about_moved_packages;
+use crate::cache::{invalidate, set_ttl};
+use crate::cmd::{
+    prints_commands, run_command, run_edit_command, run_external_command, set_print_commands,
+    with_timeout_of,
+};
+use crate::env::{get_editor, get_machine_architecture, should_print_debug_info};
+use crate::glob::matches_any;
+use crate::graph::groups_to_dot;
+use crate::grouping::document::{normalizes_on_write, set_normalize_on_write, GroupDocument};
+use crate::grouping::group::{
+    find_dangling_symlinks, groups_to_backend_packages, native_duplicates, BackendPackages,
+};
+use crate::grouping::options::{get_sidecar_path, save_sidecar, GroupOptions};
+use crate::history::{boot_time, now, parse_duration};
+use crate::instance::lock_instance;
+use crate::lock::Lockfile;
+use crate::parallel::{query_backends, set_jobs};
+use crate::path::{binary_in_path, get_absolutized_file_paths, get_relative_path};
 use crate::prelude::*;
-use crate::review::review;
+use crate::review::{review, review_interactive};
 use crate::search::search_packages;
-use crate::ui::get_user_confirmation;
+use crate::trace::{is_traced, set_traced};
+use crate::ui::{get_user_confirmation, get_user_confirmation_default_no, Color};
+use crate::update::check_for_update;
 
 impl MainArguments {
     /// Run the action that was provided by the user as first argument.
@@ -26,15 +44,479 @@ impl MainArguments {
     /// # Errors
     ///
     /// This function propagates errors from the underlying functions.
-    pub fn run(self, groups: &Groups, config: &Config) -> Result<()> {
+    pub fn run(
+        self,
+        groups: &Groups,
+        config: &Config,
+        group_dir: &Path,
+        lock_file: &Path,
+    ) -> Result<()> {
+        set_print_commands(self.print_commands);
+        set_jobs(self.jobs);
+        set_traced(self.trace.clone());
+        set_normalize_on_write(config.normalize_on_write);
+        set_ttl(if self.no_cache {
+            0
+        } else {
+            config.query_cache_ttl
+        });
+
+        if lists_unmanaged_packages(&self.subcommand)
+            && offer_default_group(groups, config, group_dir)?
+        {
+            return Ok(());
+        }
+
+        if changes_packages(&self.subcommand) {
+            lock_instance()?;
+        }
+
         match self.subcommand {
-            MainSubcommand::Group(group) => group.run(groups),
-            MainSubcommand::Package(package) => package.run(groups, config),
+            MainSubcommand::Bootstrap(bootstrap) => bootstrap.run(groups, config, group_dir),
+            MainSubcommand::Config(config_arguments) => config_arguments.run(config),
+            MainSubcommand::Deps(deps) => deps.run(config),
+            MainSubcommand::Fmt(fmt) => fmt.run(groups),
+            MainSubcommand::Group(group) => group.run(groups, group_dir),
+            MainSubcommand::Log(log) => log.run(groups, config),
+            MainSubcommand::Package(package) => package.run(groups, config, lock_file),
+            MainSubcommand::Repair(repair) => repair.run(groups, config),
+            MainSubcommand::Restore(restore) => restore.run(config),
+            MainSubcommand::Validate(validate) => validate.run(config, group_dir),
+            MainSubcommand::Verify(verify) => verify.run(config),
             MainSubcommand::Version(version) => version.run(config),
         }
     }
 }
 
+/// Whether `subcommand` shows or removes the unmanaged packages, which are all
+/// installed packages as long as there are no groups.
+fn lists_unmanaged_packages(subcommand: &MainSubcommand) -> bool {
+    match subcommand {
+        MainSubcommand::Package(package) => matches!(
+            package.package_action,
+            PackageAction::Clean(_) | PackageAction::Review(_) | PackageAction::Unmanaged(_)
+        ),
+        _ => false,
+    }
+}
+
+/// If there are no groups besides the machine-local one and `default_group` is set
+/// in the config, ask the user whether to create that group from all installed
+/// packages instead of treating them as unmanaged. Returns whether the group was
+/// offered and accepted, in which case the command must be run again.
+fn offer_default_group(groups: &Groups, config: &Config, group_dir: &Path) -> Result<bool> {
+    let Some(group) = &config.default_group else {
+        return Ok(false);
+    };
+    if groups.iter().any(|group| !group.local) || !stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    println!(
+        "There are no group files yet, so every installed package counts as unmanaged.
+Create the group '{group}' from all installed packages first, like `pacdef bootstrap {group}`?"
+    );
+    if !get_user_confirmation_default_no()? {
+        return Ok(false);
+    }
+
+    let bootstrap = BootstrapArguments {
+        group: group.clone(),
+        no_confirm: false,
+    };
+    bootstrap.run(groups, config, group_dir)?;
+    println!("run the command again to use the new group");
+    Ok(true)
+}
+
+/// Whether `subcommand` installs or removes packages, and must therefore not run
+/// concurrently with another pacdef. `package review` only takes the lock once the
+/// changes are confirmed.
+fn changes_packages(subcommand: &MainSubcommand) -> bool {
+    match subcommand {
+        MainSubcommand::Repair(_) | MainSubcommand::Restore(_) => true,
+        MainSubcommand::Package(package) => matches!(
+            package.package_action,
+            PackageAction::Clean(_)
+                | PackageAction::CleanCache(_)
+                | PackageAction::Prefetch(_)
+                | PackageAction::Sync(_)
+        ),
+        _ => false,
+    }
+}
+
+impl BootstrapArguments {
+    /// Write all unmanaged packages into a new group, together with the install
+    /// options the backends can recover, which go into the sidecar file.
+    fn run(self, groups: &Groups, config: &Config, group_dir: &Path) -> Result<()> {
+        let path = new_group_path(group_dir, &self.group)?;
+
+        // also query the backends that no group has a section for yet
+        let mut backend_packages: BackendPackages = AnyBackend::all(config)
+            .map(|backend| (backend, Packages::new()))
+            .collect();
+        backend_packages.extend(groups_to_backend_packages(groups, config)?);
+
+        let unmanaged = query_unmanaged_packages(backend_packages, config)?;
+
+        if unmanaged.nothing_to_do_for_all_backends() {
+            println!("nothing to do");
+            return Ok(());
+        }
+
+        println!(
+            "Would write {} packages to the new group '{}':\n",
+            unmanaged.count(),
+            self.group
+        );
+        unmanaged.show_changes('+', Color::Green);
+
+        println!();
+        if self.no_confirm {
+            println!("proceeding without confirmation");
+        } else if !get_user_confirmation()? {
+            return Ok(());
+        }
+
+        write_packages_as_group(&path, &unmanaged)?;
+        println!("created group {}", path.to_string_lossy());
+        Ok(())
+    }
+}
+
+/// Write the `packages` to a new group file at `path`, and the install options the
+/// backends can recover to its sidecar file.
+fn write_packages_as_group(path: &Path, packages: &ToDoPerBackend) -> Result<()> {
+    let mut document = GroupDocument::parse("");
+    let mut options = GroupOptions::new();
+
+    for (backend, packages) in packages.iter() {
+        if packages.is_empty() {
+            continue;
+        }
+
+        let section = backend.backend_info().section;
+        document.add_packages(section, packages);
+
+        match backend.get_install_options(packages) {
+            Ok(recovered) if !recovered.is_empty() => {
+                options.insert(section.to_string(), recovered);
+            }
+            Ok(_) => (),
+            Err(error) => log::warn!(
+                backend = section;
+                "could not recover install options for {backend}: {error}"
+            ),
+        }
+    }
+
+    if normalizes_on_write() {
+        document.normalize();
+    }
+
+    write(path, document.to_string())
+        .with_context(|| format!("writing group file {}", path.to_string_lossy()))?;
+    save_sidecar(path, &options)
+}
+
+impl FmtArguments {
+    /// Normalize every group file, or with `check` only list the ones that are not
+    /// normalized.
+    fn run(self, groups: &Groups) -> Result<()> {
+        let mut unformatted = vec![];
+
+        for group in groups {
+            let content = read_to_string(&group.path)
+                .with_context(|| format!("reading group file {}", group.path.to_string_lossy()))?;
+
+            let mut document = GroupDocument::parse(&content);
+            document.normalize();
+            let normalized = document.to_string();
+
+            if normalized == content {
+                continue;
+            }
+
+            if self.check {
+                println!("{}", group.path.to_string_lossy());
+                unformatted.push(group.name.clone());
+                continue;
+            }
+
+            write(&group.path, normalized)
+                .with_context(|| format!("writing group file {}", group.path.to_string_lossy()))?;
+            println!("formatted {}", group.path.to_string_lossy());
+        }
+
+        ensure!(
+            unformatted.is_empty(),
+            "{} group files are not normalized, run `pacdef fmt` to fix them",
+            unformatted.len()
+        );
+
+        Ok(())
+    }
+}
+
+impl LogArguments {
+    /// Show the history of all backends that have one, and whether each package is
+    /// managed by a group.
+    fn run(self, groups: &Groups, config: &Config) -> Result<()> {
+        let since = match &self.since {
+            Some(duration) => now()? - parse_duration(duration)?,
+            None => boot_time()?,
+        };
+
+        let backend_packages = groups_to_backend_packages(groups, config)?;
+        let backends = enabled_backends(AnyBackend::all(config).map(|b| (b, ())), config)?;
+
+        let results = query_backends(backends, config, |any_backend, ()| {
+            any_backend.get_history(since)
+        });
+
+        let mut found_changes = false;
+
+        for (any_backend, result) in results {
+            let entries = match result {
+                Ok(entries) if entries.is_empty() => continue,
+                Ok(entries) => entries,
+                Err(error) => {
+                    show_backend_query_error(&error, &any_backend);
+                    continue;
+                }
+            };
+
+            let managed = backend_packages.get(&any_backend);
+
+            if found_changes {
+                println!();
+            }
+            found_changes = true;
+
+            println!("[{any_backend}]");
+            for entry in entries {
+                let kind = if managed.is_some_and(|packages| packages.contains(&entry.package)) {
+                    "managed"
+                } else {
+                    "manual"
+                };
+                println!(
+                    "{} {} {} ({kind})",
+                    entry.timestamp, entry.action, entry.package
+                );
+            }
+        }
+
+        if !found_changes {
+            println!("no changes found");
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigArguments {
+    fn run(self, config: &Config) -> Result<()> {
+        if self.dump {
+            let content = toml::to_string(config).context("serializing config")?;
+            print!("{content}");
+        }
+
+        Ok(())
+    }
+}
+
+impl DepsArguments {
+    /// Print the dependency tree of the package, as far as the backend can tell.
+    fn run(self, config: &Config) -> Result<()> {
+        let backend = AnyBackend::from_section(&self.backend, config)?;
+        let package = Package::from(self.package.as_str());
+
+        let tree = backend
+            .dependency_tree(&package)
+            .with_context(|| format!("getting the dependencies of {package}"))?;
+        print!("{tree}");
+        Ok(())
+    }
+}
+
+impl RepairArguments {
+    /// Reinstall the managed packages that are installed, of the selected backends
+    /// or of all of them.
+    fn run(self, groups: &Groups, config: &Config) -> Result<()> {
+        ensure!(
+            self.force,
+            "repair reinstalls every installed managed package, pass --force to do so"
+        );
+
+        for section in &self.backend {
+            AnyBackend::from_section(section, config)?;
+        }
+
+        let backend_packages: BackendPackages = groups_to_backend_packages(groups, config)?
+            .into_iter()
+            .filter(|(backend, _)| {
+                self.backend.is_empty()
+                    || self
+                        .backend
+                        .iter()
+                        .any(|section| section == backend.backend_info().section)
+            })
+            .collect();
+        ensure_backend_binaries(&backend_packages, config)?;
+        let backends = enabled_backends(backend_packages, config)?;
+
+        let mut to_reinstall = ToDoPerBackend::new();
+
+        let results = query_backends(backends, config, |any_backend, packages| {
+            let managed_backend = ManagedBackend {
+                packages: packages.clone(),
+                any_backend: any_backend.clone(),
+            };
+            managed_backend.get_installed_packages_sorted()
+        });
+
+        for (any_backend, result) in results {
+            match result {
+                Ok(installed) => {
+                    to_reinstall.push((any_backend, without_excluded(installed, config)));
+                }
+                Err(error) => show_backend_query_error(&error, &any_backend),
+            };
+        }
+
+        if to_reinstall.nothing_to_do_for_all_backends() {
+            println!("nothing to do");
+            return Ok(());
+        }
+
+        println!("Would reinstall the following packages:\n");
+        to_reinstall.show_changes('~', Color::Yellow);
+
+        println!();
+        if self.no_confirm {
+            println!("proceeding without confirmation");
+        } else if !get_user_confirmation()? {
+            return Ok(());
+        }
+
+        to_reinstall.reinstall_packages(self.no_confirm, config)
+    }
+}
+
+impl RestoreArguments {
+    /// Install all packages from the restore file that are not installed, with the
+    /// install options from its sidecar file.
+    fn run(self, config: &Config) -> Result<()> {
+        let group = Group::load_file(&self.file)
+            .with_context(|| format!("reading restore file {}", self.file.to_string_lossy()))?;
+
+        let mut config = config.clone();
+        let groups: Groups = [group].into();
+        config.merge_group_options(&groups);
+
+        let to_install = get_missing_packages(&groups, &config)?;
+
+        if to_install.nothing_to_do_for_all_backends() {
+            println!("nothing to do");
+            return Ok(());
+        }
+
+        println!("Would install the following packages:\n");
+        to_install.show_changes('+', Color::Green);
+
+        println!();
+        if self.no_confirm {
+            println!("proceeding without confirmation");
+        } else if !get_user_confirmation()? {
+            return Ok(());
+        }
+
+        to_install.install_missing_packages(self.no_confirm, &config)?;
+        Ok(())
+    }
+}
+
+impl ValidateArguments {
+    /// Print every problem in the group files, and fail if there are any. Packages
+    /// that are declared for several native backends are only checked once the group
+    /// files themselves are valid.
+    fn run(self, config: &Config, group_dir: &Path) -> Result<()> {
+        let mut problems = Group::validate(group_dir, config)?;
+
+        if problems.is_empty() {
+            let groups = Group::load(group_dir, false)?;
+            let backend_packages = groups_to_backend_packages(&groups, config)?;
+            problems.extend(native_duplicates(&backend_packages, &groups, config));
+        }
+
+        for problem in &problems {
+            println!("{problem}");
+        }
+
+        ensure!(
+            problems.is_empty(),
+            "found {} problems in the group files",
+            problems.len()
+        );
+
+        println!("all group files are valid");
+        Ok(())
+    }
+}
+
+impl VerifyArguments {
+    /// Show the issues of all backends that can verify packages, grouped by package.
+    fn run(self, config: &Config) -> Result<()> {
+        let backends = enabled_backends(AnyBackend::all(config).map(|b| (b, ())), config)?;
+
+        let results = query_backends(backends, config, |any_backend, ()| {
+            any_backend.verify_packages()
+        });
+
+        let mut found_issues = false;
+
+        for (any_backend, result) in results {
+            let issues = match result {
+                Ok(issues) if issues.is_empty() => continue,
+                Ok(issues) => issues,
+                Err(error) => {
+                    show_backend_query_error(&error, &any_backend);
+                    continue;
+                }
+            };
+
+            let mut by_package: BTreeMap<Package, Vec<String>> = BTreeMap::new();
+            for issue in issues {
+                by_package
+                    .entry(issue.package)
+                    .or_default()
+                    .push(issue.description);
+            }
+
+            if found_issues {
+                println!();
+            }
+            found_issues = true;
+
+            println!("[{any_backend}]");
+            for (package, descriptions) in by_package {
+                println!("{package}");
+                for description in descriptions {
+                    println!("  {description}");
+                }
+            }
+        }
+
+        if !found_issues {
+            println!("no issues found");
+        }
+
+        Ok(())
+    }
+}
+
 impl VersionArguments {
     /// If the crate was compiled from git, return `pacdef, <version> (<hash>)`.
     /// Otherwise return `pacdef, <version>`.
@@ -54,13 +536,15 @@ impl VersionArguments {
 }
 
 impl GroupArguments {
-    fn run(self, groups: &Groups) -> Result<()> {
+    fn run(self, groups: &Groups, group_dir: &Path) -> Result<()> {
         match self.group_action {
             GroupAction::Edit(edit) => edit.run(groups),
             GroupAction::Export(export) => export.run(groups),
-            GroupAction::Import(import) => import.run(),
+            GroupAction::Graph(graph) => graph.run(groups),
+            GroupAction::Import(import) => import.run(group_dir),
             GroupAction::List(list) => list.run(groups),
-            GroupAction::New(new) => new.run(),
+            GroupAction::New(new) => new.run(group_dir),
+            GroupAction::Prune(prune) => prune.run(groups, group_dir),
             GroupAction::Remove(remove) => remove.run(groups),
             GroupAction::Show(show) => show.run(groups),
         }
@@ -105,6 +589,7 @@ impl ExportGroupAction {
     ///
     /// This function will return an error if
     /// - the group file is a symlink (in which case exporting makes no sense),
+    /// - the group is the machine-local group,
     /// - the output file exists and `force` is not `true`, or
     /// - the user does not have permission to write to the output dir.
     ///
@@ -126,6 +611,11 @@ impl ExportGroupAction {
 
         for group in &groups {
             ensure!(!&group.path.is_symlink(), "cannot export symlinks");
+            ensure!(
+                !group.local,
+                "cannot export the machine-local group {}",
+                group.name
+            );
 
             let mut exported_path = output_dir.clone();
             exported_path.push(PathBuf::from(&group.name));
@@ -145,10 +635,19 @@ impl ExportGroupAction {
     }
 }
 
+impl GraphGroupAction {
+    fn run(self, groups: &Groups) -> Result<()> {
+        match self.format {
+            GraphFormat::Dot => print!("{}", groups_to_dot(groups)),
+        }
+
+        Ok(())
+    }
+}
+
 impl ImportGroupAction {
-    fn run(self) -> Result<()> {
+    fn run(self, group_dir: &Path) -> Result<()> {
         let files = get_absolutized_file_paths(&self.import_groups)?;
-        let groups_dir = get_group_dir()?;
 
         for target in files {
             let target_name = target
@@ -162,7 +661,7 @@ impl ImportGroupAction {
                 continue;
             }
 
-            let mut link = groups_dir.clone();
+            let mut link = group_dir.to_path_buf();
             link.push(target_name);
 
             if link.exists() {
@@ -205,37 +704,95 @@ impl NewGroupAction {
     /// - a group with the same name already exists,
     /// - the editor cannot be run, or
     /// - if we do not have permission to write to the group dir.
-    fn run(&self) -> Result<()> {
-        let group_path = get_group_dir()?;
+    fn run(&self, group_dir: &Path) -> Result<()> {
+        let paths = self
+            .new_groups
+            .iter()
+            .map(|name| new_group_path(group_dir, name))
+            .collect::<Result<Vec<_>>>()?;
 
-        // prevent group names that resolve to directories
-        for new_group in &self.new_groups {
-            ensure!(
-                new_group != "." && new_group != "..",
-                Error::InvalidGroupName(new_group.clone())
-            );
+        for file in &paths {
+            File::create(file)?;
         }
 
-        let paths: Vec<_> = self
-            .new_groups
+        if self.edit {
+            run_edit_command(&paths).context("running editor")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the path of a new group called `name`.
+///
+/// # Errors
+///
+/// This function will return an error if the name is `.` or `..`, or if a group with
+/// the same name already exists.
+fn new_group_path(group_dir: &Path, name: &str) -> Result<PathBuf> {
+    // prevent group names that resolve to directories
+    ensure!(
+        name != "." && name != "..",
+        Error::InvalidGroupName(name.to_string())
+    );
+
+    let mut path = group_dir.to_path_buf();
+    path.push(name);
+
+    ensure!(!path.exists(), Error::GroupAlreadyExists(path.clone()));
+    Ok(path)
+}
+
+impl PruneGroupAction {
+    /// Remove every group that declares no packages, together with its sidecar
+    /// file, and every dangling symlink in the group dir. Symlinked groups are
+    /// removed by removing the symlink, never its target. The machine-local group
+    /// is kept, as it is not in the group dir.
+    fn run(self, groups: &Groups, group_dir: &Path) -> Result<()> {
+        let empty: Vec<_> = groups
             .iter()
-            .map(|name| {
-                let mut base = group_path.clone();
-                base.push(name);
-                base
-            })
+            .filter(|group| !group.local && group.sections.is_empty())
             .collect();
+        let dangling = find_dangling_symlinks(group_dir)?;
 
-        for file in &paths {
-            ensure!(!file.exists(), Error::GroupAlreadyExists(file.clone()));
+        if empty.is_empty() && dangling.is_empty() {
+            println!("nothing to do");
+            return Ok(());
         }
 
-        for file in &paths {
-            File::create(file)?;
+        println!("Would remove the following group files:\n");
+        for group in &empty {
+            println!("{}", group.name);
         }
+        for path in &dangling {
+            let name = get_relative_path(path.as_path(), group_dir)
+                .to_string_lossy()
+                .to_string();
+            println!("{name} (dangling symlink)");
+        }
+        println!();
 
-        if self.edit {
-            run_edit_command(&paths).context("running editor")?;
+        if self.dry_run {
+            return Ok(());
+        }
+
+        if !self.no_confirm && !get_user_confirmation_default_no()? {
+            return Ok(());
+        }
+
+        for group in empty {
+            remove_file(&group.path)
+                .with_context(|| format!("removing {}", group.path.to_string_lossy()))?;
+
+            let sidecar = get_sidecar_path(&group.path);
+            if sidecar.exists() {
+                remove_file(&sidecar)
+                    .with_context(|| format!("removing {}", sidecar.to_string_lossy()))?;
+            }
+        }
+
+        for path in dangling {
+            remove_file(&path).with_context(|| format!("removing {}", path.to_string_lossy()))?;
         }
 
         Ok(())
@@ -299,12 +856,21 @@ impl ShowGroupAction {
 }
 
 impl PackageArguments {
-    fn run(self, groups: &Groups, config: &Config) -> Result<()> {
+    fn run(self, groups: &Groups, config: &Config, lock_file: &Path) -> Result<()> {
         match self.package_action {
             PackageAction::Clean(clean) => clean.run(groups, config),
+            PackageAction::CleanCache(clean_cache) => clean_cache.run(config),
+            PackageAction::Lock(lock) => lock.run(groups, config, lock_file),
+            PackageAction::Orphans(orphans) => orphans.run(config),
+            PackageAction::Prefetch(prefetch) => prefetch.run(groups, config),
             PackageAction::Review(review) => review.run(groups, config),
             PackageAction::Search(search) => search.run(groups),
-            PackageAction::Sync(sync) => sync.run(groups, config),
+            PackageAction::Sync(sync) => {
+                let installed = sync.run(groups, config, lock_file)?;
+                notify(config, installed, 0);
+                check_for_update(config);
+                Ok(())
+            }
             PackageAction::Unmanaged(unmanaged) => unmanaged.run(groups, config),
         }
     }
@@ -320,25 +886,216 @@ impl CleanPackageAction {
         }
 
         println!("Would remove the following packages:\n");
-        to_remove.show().context("printing things to do")?;
+        to_remove.show_changes('-', Color::Red);
+
+        let cascades = to_remove.removal_cascades();
+        if !cascades.nothing_to_do_for_all_backends() {
+            println!("\nand these dependencies, which are not needed anymore:\n");
+            cascades.show_changes('-', Color::Yellow);
+        }
 
         println!();
+        if is_preview(self.apply, config) {
+            println!("{PREVIEW_MESSAGE}");
+            return Ok(());
+        }
+
+        config.check_max_removals(to_remove.count() + cascades.count(), self.force)?;
+
+        if let Some(file) = &self.save_removed {
+            ensure!(
+                !file.exists(),
+                "restore file {} already exists",
+                file.to_string_lossy()
+            );
+        }
+
         if self.no_confirm {
             println!("proceeding without confirmation");
-        } else if !get_user_confirmation()? {
+        } else if !get_user_confirmation_default_no()? {
+            return Ok(());
+        }
+
+        if let Some(file) = &self.save_removed {
+            create_parent(file)?;
+            write_packages_as_group(file, &to_remove)?;
+            println!("saved the packages to {}", file.to_string_lossy());
+        }
+
+        to_remove.remove_unmanaged_packages(self.no_confirm, config)
+    }
+}
+
+impl CleanCachePackageAction {
+    fn run(self, config: &Config) -> Result<()> {
+        let backends = enabled_backends(AnyBackend::all(config).map(|b| (b, ())), config)?;
+
+        for (any_backend, ()) in backends {
+            let no_confirm = config.no_confirm_for(&any_backend, self.no_confirm);
+            with_timeout_of(&any_backend, config, || any_backend.clean_cache(no_confirm))
+                .with_context(|| format!("cleaning cache for {any_backend}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl LockPackageAction {
+    /// Record the installed version of every managed package in the lock file.
+    /// Packages of backends that cannot report versions are not recorded.
+    fn run(self, groups: &Groups, config: &Config, lock_file: &Path) -> Result<()> {
+        let backend_packages = groups_to_backend_packages(groups, config)?;
+        ensure_backend_binaries(&backend_packages, config)?;
+        let backends = enabled_backends(backend_packages, config)?;
+
+        let mut lockfile = Lockfile::default();
+
+        let results = query_backends(backends, config, |any_backend, packages| {
+            any_backend
+                .get_installed_versions()
+                .map(|installed| (packages.clone(), installed))
+        });
+
+        for (any_backend, result) in results {
+            match result {
+                Ok((packages, installed)) => {
+                    lockfile.record(any_backend.backend_info().section, &packages, &installed);
+                }
+                Err(error) => show_backend_query_error(&error, &any_backend),
+            }
+        }
+
+        lockfile.save(lock_file)?;
+        println!("wrote {}", lock_file.to_string_lossy());
+
+        Ok(())
+    }
+}
+
+impl OrphansPackageAction {
+    fn run(self, config: &Config) -> Result<()> {
+        let backends = enabled_backends(AnyBackend::all(config).map(|b| (b, ())), config)?;
+
+        let mut orphans = ToDoPerBackend::new();
+
+        let results = query_backends(backends, config, |any_backend, ()| {
+            any_backend.list_orphans()
+        });
+
+        for (any_backend, result) in results {
+            match result {
+                Ok(packages) => orphans.push((any_backend, packages)),
+                Err(error) => show_backend_query_error(&error, &any_backend),
+            }
+        }
+
+        if orphans.nothing_to_do_for_all_backends() {
+            println!("no orphans found");
             return Ok(());
         }
 
-        to_remove.remove_unmanaged_packages(self.no_confirm)
+        orphans.show().context("printing orphans")
     }
 }
 
 impl ReviewPackageAction {
     fn run(self, groups: &Groups, config: &Config) -> Result<()> {
-        review(get_unmanaged_packages(groups, config)?, groups)
+        let unmanaged = get_unmanaged_packages(groups, config)?;
+        warn_about_moved_packages(&groups_to_backend_packages(groups, config)?, &unmanaged);
+
+        let yanked = if self.check_yanked {
+            get_yanked_packages(&unmanaged)
+        } else {
+            vec![]
+        };
+
+        if self.interactive {
+            let missing = get_missing_packages(groups, config)?;
+            review_interactive(
+                missing,
+                unmanaged,
+                groups,
+                config,
+                self.output_format(),
+                self.group_by,
+                self.force,
+            )?;
+        } else {
+            review(
+                unmanaged,
+                groups,
+                config,
+                self.output_format(),
+                self.group_by,
+                self.force,
+            )?;
+        }
+
+        show_yanked_packages(&yanked);
+        Ok(())
+    }
+
+    /// The format from `--output`, or else plain output on a terminal and terse
+    /// output when piped, unless `--[no-]interactive-output` says otherwise.
+    fn output_format(&self) -> ReviewOutput {
+        if let Some(output) = self.output {
+            return output;
+        }
+
+        let interactive = if self.interactive_output {
+            true
+        } else if self.no_interactive_output {
+            false
+        } else {
+            stdout().is_terminal()
+        };
+
+        if interactive {
+            ReviewOutput::Plain
+        } else {
+            ReviewOutput::Terse
+        }
     }
 }
 
+/// Get the installed packages of the `backends` whose installed version was yanked,
+/// as backend section, name and version. A backend that cannot be checked, e.g.
+/// because the machine is offline, is skipped with a warning.
+fn get_yanked_packages(backends: &ToDoPerBackend) -> Vec<(&'static str, String, String)> {
+    let mut result = vec![];
+
+    for (backend, _) in backends.iter() {
+        let section = backend.backend_info().section;
+        match backend.get_yanked_packages() {
+            Ok(yanked) => result.extend(
+                yanked
+                    .into_iter()
+                    .map(|(name, version)| (section, name, version)),
+            ),
+            Err(error) => {
+                log::warn!("cannot check for yanked packages of [{section}]: {error:#}");
+            }
+        }
+    }
+
+    result
+}
+
+/// List the `yanked` packages after a review, with a suggestion to update them.
+fn show_yanked_packages(yanked: &[(&str, String, String)]) {
+    if yanked.is_empty() {
+        return;
+    }
+
+    println!("\nthe installed version of these packages was yanked:");
+    for (section, name, version) in yanked {
+        println!("  [{section}] {name} {version}");
+    }
+    println!(
+        "reinstall them to update to the latest version, e.g. with `cargo install --force <crate>`"
+    );
+}
+
 impl SearchPackageAction {
     fn run(self, groups: &Groups) -> Result<()> {
         search_packages(&self.regex, groups)
@@ -346,31 +1103,117 @@ impl SearchPackageAction {
 }
 
 impl SyncPackageAction {
-    fn run(self, groups: &Groups, config: &Config) -> Result<()> {
-        let to_install = get_missing_packages(groups, config)?;
+    /// Install all missing packages and return the number of packages that were
+    /// installed.
+    fn run(self, groups: &Groups, config: &Config, lock_file: &Path) -> Result<usize> {
+        let groups = &select_groups(groups, &self.groups, &self.exclude_groups, config)?;
+        let mut to_install = get_missing_packages(groups, config)?;
+
+        let lockfile = if self.locked {
+            Some(Lockfile::load(lock_file)?)
+        } else {
+            None
+        };
+
+        if let Some(lockfile) = &lockfile {
+            to_install = pin_to_lockfile(to_install, lockfile);
+        }
+
+        let preview = is_preview(self.apply, config);
+
+        if self.sysupgrade && !preview {
+            upgrade_systems(&to_install, lockfile.as_ref(), self.no_confirm, config)?;
+        }
 
         if to_install.nothing_to_do_for_all_backends() {
             println!("nothing to do");
-            return Ok(());
+            return Ok(0);
         }
 
         println!("Would install the following packages:\n");
-        to_install.show().context("printing things to do")?;
+        to_install.show_changes('+', Color::Green);
 
         println!();
+        if preview {
+            println!("{PREVIEW_MESSAGE}");
+            return Ok(0);
+        }
+
         if self.no_confirm {
             println!("proceeding without confirmation");
         } else if !get_user_confirmation()? {
+            return Ok(0);
+        }
+
+        let mut config = config.clone();
+        config.continue_on_error |= self.continue_on_error;
+        to_install.install_missing_packages(self.no_confirm, &config)
+    }
+}
+
+/// Get the groups called `names`, together with the `always_groups` from the
+/// `config`, but without the groups called `excluded`. All groups are selected if
+/// no `names` are given. The packages of unselected groups are still managed, so
+/// they are not removed by `package clean`.
+fn select_groups(
+    groups: &Groups,
+    names: &[String],
+    excluded: &[String],
+    config: &Config,
+) -> Result<Groups> {
+    let missing: Vec<String> = names
+        .iter()
+        .chain(excluded)
+        .filter(|name| !groups.iter().any(|group| group.name == **name))
+        .cloned()
+        .collect();
+    ensure!(missing.is_empty(), Error::MultipleGroupsNotFound(missing));
+
+    if let Some(name) = excluded
+        .iter()
+        .find(|name| config.always_groups.contains(name))
+    {
+        bail!("group {name} is in always_groups and cannot be excluded");
+    }
+
+    Ok(groups
+        .iter()
+        .filter(|group| {
+            names.is_empty()
+                || names.contains(&group.name)
+                || config.always_groups.contains(&group.name)
+        })
+        .filter(|group| !excluded.contains(&group.name))
+        .cloned()
+        .collect())
+}
+
+impl PrefetchPackageAction {
+    /// Download all missing packages into the caches of their package managers, so
+    /// that a later sync can install them without network access.
+    fn run(self, groups: &Groups, config: &Config) -> Result<()> {
+        let to_download = get_missing_packages(groups, config)?;
+
+        if to_download.nothing_to_do_for_all_backends() {
+            println!("nothing to do");
             return Ok(());
         }
 
-        to_install.install_missing_packages(self.no_confirm)
+        println!("Would download the following packages:\n");
+        to_download.show_changes('+', Color::Green);
+        println!();
+
+        to_download.download_missing_packages(self.no_confirm, config)
     }
 }
 
 impl UnmanagedPackageAction {
     fn run(self, groups: &Groups, config: &Config) -> Result<()> {
         let unmanaged_per_backend = &get_unmanaged_packages(groups, config)?;
+        warn_about_moved_packages(
+            &groups_to_backend_packages(groups, config)?,
+            unmanaged_per_backend,
+        );
 
         if unmanaged_per_backend.nothing_to_do_for_all_backends() {
             return Ok(());
@@ -384,37 +1227,92 @@ impl UnmanagedPackageAction {
 
 fn get_missing_packages(groups: &Groups, config: &Config) -> Result<ToDoPerBackend> {
     let backend_packages = groups_to_backend_packages(groups, config)?;
+    for duplicate in native_duplicates(&backend_packages, groups, config) {
+        log::warn!("{duplicate}");
+    }
+    ensure_backend_binaries(&backend_packages, config)?;
+    let backends = enabled_backends(backend_packages, config)?;
 
     let mut to_install = ToDoPerBackend::new();
 
-    for (any_backend, packages) in &backend_packages {
-        let backend_info = any_backend.backend_info();
-
-        if config
-            .disabled_backends
-            .contains(&backend_info.section.to_string())
-        {
-            continue;
-        }
-
-        if !binary_in_path(&backend_info.binary)? {
-            continue;
-        }
-
+    let results = query_backends(backends, config, |any_backend, packages| {
         let managed_backend = ManagedBackend {
             packages: packages.clone(),
             any_backend: any_backend.clone(),
         };
-
-        match managed_backend.get_missing_packages_sorted() {
-            Ok(diff) => to_install.push((any_backend.clone(), diff)),
-            Err(error) => show_backend_query_error(&error, any_backend),
+        managed_backend.get_missing_packages_sorted()
+    });
+
+    for (any_backend, result) in results {
+        match result {
+            Ok(diff) => {
+                let diff = without_other_architectures(diff, &any_backend, config);
+                to_install.push((any_backend, without_excluded(diff, config)));
+            }
+            Err(error) => show_backend_query_error(&error, &any_backend),
         };
     }
 
     Ok(to_install)
 }
 
+/// Upgrade all installed packages of every backend, before managed packages are
+/// installed. With a lock file, its packages are held in their installed version.
+fn upgrade_systems(
+    backends: &ToDoPerBackend,
+    lockfile: Option<&Lockfile>,
+    noconfirm: bool,
+    config: &Config,
+) -> Result<()> {
+    for (backend, _) in backends.iter() {
+        let noconfirm = config.no_confirm_for(backend, noconfirm);
+        let held = lockfile
+            .map(|lockfile| lockfile.packages(backend.backend_info().section))
+            .unwrap_or_default();
+
+        invalidate(backend);
+        with_timeout_of(backend, config, || backend.upgrade_system(noconfirm, &held))
+            .with_context(|| format!("upgrading all packages for {backend}"))?;
+    }
+
+    Ok(())
+}
+
+/// Replace every package by the exact version recorded in the lock file, if the
+/// backend supports installing specific versions. Packages that cannot be pinned are
+/// installed in their latest version.
+fn pin_to_lockfile(to_install: ToDoPerBackend, lockfile: &Lockfile) -> ToDoPerBackend {
+    let mut result = ToDoPerBackend::new();
+
+    for (backend, packages) in to_install {
+        let section = backend.backend_info().section;
+
+        let pinned = packages
+            .into_iter()
+            .map(|package| {
+                let Some(version) = lockfile.version(section, &package) else {
+                    log::warn!(
+                        backend = section, package:% = package;
+                        "{package} [{section}] is not locked, installing latest version");
+                    return package;
+                };
+
+                backend.pin_version(&package, version).unwrap_or_else(|| {
+                    log::warn!(
+                        backend = section, package:% = package;
+                        "[{section}] cannot install exact versions, installing latest {package}"
+                    );
+                    package
+                })
+            })
+            .collect();
+
+        result.push((backend, pinned));
+    }
+
+    result
+}
+
 /// Get a list of unmanaged packages per backend.
 ///
 /// This method loops through all enabled `Backend`s whose binary is in `PATH`.
@@ -424,11 +1322,122 @@ fn get_missing_packages(groups: &Groups, config: &Config) -> Result<ToDoPerBacke
 /// This function will propagate errors from the individual backends.
 fn get_unmanaged_packages(groups: &Groups, config: &Config) -> Result<ToDoPerBackend> {
     let backend_packages = groups_to_backend_packages(groups, config)?;
+    ensure_backend_binaries(&backend_packages, config)?;
+    query_unmanaged_packages(backend_packages, config)
+}
+
+/// Get the explicitly installed packages of each of the enabled backends that are
+/// not among its managed packages.
+fn query_unmanaged_packages(
+    backend_packages: BackendPackages,
+    config: &Config,
+) -> Result<ToDoPerBackend> {
+    let backends = enabled_backends(backend_packages, config)?;
 
     let mut todo_unmanaged = ToDoPerBackend::new();
 
-    for (any_backend, packages) in &backend_packages {
+    let results = query_backends(backends, config, |any_backend, packages| {
+        let managed_backend = ManagedBackend {
+            packages: packages.clone(),
+            any_backend: any_backend.clone(),
+        };
+        managed_backend.get_unmanaged_packages_sorted()
+    });
+
+    for (any_backend, result) in results {
+        match result {
+            Ok(unmanaged) => {
+                todo_unmanaged.push((any_backend, without_excluded(unmanaged, config)));
+            }
+            Err(error) => show_backend_query_error(&error, &any_backend),
+        };
+    }
+
+    Ok(todo_unmanaged)
+}
+
+const PREVIEW_MESSAGE: &str =
+    "not changing anything because of require_apply, pass --apply to do so";
+
+/// Check whether changes must only be shown, because `require_apply` is set in the
+/// config but `--apply` was not given.
+fn is_preview(apply: bool, config: &Config) -> bool {
+    config.require_apply && !apply
+}
+
+/// Run the `notify_command` from the config after a sync. A failing command only
+/// results in a warning. With `--print-commands`, nothing was installed, so the
+/// command is not run.
+fn notify(config: &Config, installed: usize, removed: usize) {
+    let Some(command) = &config.notify_command else {
+        return;
+    };
+
+    if prints_commands() {
+        return;
+    }
+
+    let command = command
+        .replace("{installed}", &installed.to_string())
+        .replace("{removed}", &removed.to_string());
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+
+    if let Err(error) = run_command(cmd) {
+        log::warn!("notify command failed: {error}");
+    }
+}
+
+/// If `strict_backends` is set, make sure that the binary of every enabled backend
+/// with declared packages is in `PATH`.
+///
+/// # Errors
+///
+/// This function returns an error naming the backend and its binary if the binary is
+/// missing, or if `$PATH` is not set.
+fn ensure_backend_binaries(backend_packages: &BackendPackages, config: &Config) -> Result<()> {
+    if !config.strict_backends {
+        return Ok(());
+    }
+
+    for (any_backend, packages) in backend_packages {
+        let backend_info = any_backend.backend_info();
+
+        if packages.is_empty()
+            || config
+                .disabled_backends
+                .contains(&backend_info.section.to_string())
+        {
+            continue;
+        }
+
+        ensure!(
+            binary_in_path(&backend_info.binary)?,
+            "backend '{}' has declared packages, but its binary '{}' is not installed",
+            backend_info.section,
+            backend_info.binary
+        );
+    }
+
+    Ok(())
+}
+
+/// Keep only the backends that are not disabled in the config and whose binary is
+/// in `PATH`, together with their associated data.
+///
+/// # Errors
+///
+/// This function returns an error if `$PATH` is not set.
+fn enabled_backends<D>(
+    backends: impl IntoIterator<Item = (AnyBackend, D)>,
+    config: &Config,
+) -> Result<Vec<(AnyBackend, D)>> {
+    let mut result = vec![];
+
+    for (any_backend, data) in backends {
         let backend_info = any_backend.backend_info();
+
         if config
             .disabled_backends
             .contains(&backend_info.section.to_string())
@@ -440,18 +1449,45 @@ fn get_unmanaged_packages(groups: &Groups, config: &Config) -> Result<ToDoPerBac
             continue;
         }
 
-        let managed_backend = ManagedBackend {
-            packages: packages.clone(),
-            any_backend: any_backend.clone(),
-        };
-
-        match managed_backend.get_unmanaged_packages_sorted() {
-            Ok(unmanaged) => todo_unmanaged.push((any_backend.clone(), unmanaged)),
-            Err(error) => show_backend_query_error(&error, any_backend),
-        };
+        result.push((any_backend, data));
     }
 
-    Ok(todo_unmanaged)
+    Ok(result)
+}
+
+/// Remove all packages whose name matches any of the `exclude` globs from the config.
+fn without_excluded(mut packages: Packages, config: &Config) -> Packages {
+    packages.retain(|package| {
+        let excluded = matches_any(&config.exclude, &package.name);
+        if excluded && is_traced(package) {
+            log::info!(package:% = package; "trace {package}: excluded by the exclude globs");
+        }
+        !excluded
+    });
+    packages
+}
+
+/// Remove all packages that are only installed on another machine architecture
+/// than this one, by their `arch` option.
+fn without_other_architectures(
+    mut packages: Packages,
+    backend: &AnyBackend,
+    config: &Config,
+) -> Packages {
+    let section = backend.backend_info().section;
+    let machine = get_machine_architecture();
+
+    packages.retain(|package| match config.architecture_of(section, &package.name) {
+        Some(arch) if arch != machine => {
+            log::info!(
+                backend = section, package:% = package;
+                "skipping {package} [{section}], which is only installed on {arch}, not {machine}"
+            );
+            false
+        }
+        _ => true,
+    });
+    packages
 }
 
 /// Create the parent directory of the `path` if that directory does not exist.
@@ -534,11 +1570,12 @@ fn find_groups_by_name<'a>(names: &[String], groups: &'a Groups) -> Result<Vec<&
 fn show_backend_query_error(error: &anyhow::Error, backend: &AnyBackend) {
     if should_print_debug_info() {
         log::warn!(
+            backend:% = backend;
             "skipping backend '{backend}': {}",
             error.chain().map(|x| x.to_string()).collect::<String>()
         );
     } else {
-        log::warn!("skipping backend '{backend}': {error}");
+        log::warn!(backend:% = backend; "skipping backend '{backend}': {error}");
     }
 }
 
@@ -1,4 +1,6 @@
-use std::env::var;
+use std::env::{consts::ARCH, var};
+use std::process::Command;
+use std::sync::OnceLock;
 
 use anyhow::{anyhow, Result};
 
@@ -10,6 +12,24 @@ fn check_vars_in_order(vars: &[&str]) -> Option<String> {
     vars.iter().find_map(|v| var(v).ok())
 }
 
+/// Get the architecture of the machine as `uname -m` prints it, e.g. `x86_64` or
+/// `aarch64`. If `uname` cannot be run, the architecture pacdef was built for is
+/// used instead.
+pub fn get_machine_architecture() -> &'static str {
+    static MACHINE: OnceLock<String> = OnceLock::new();
+
+    MACHINE.get_or_init(|| {
+        Command::new("uname")
+            .arg("-m")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|machine| !machine.is_empty())
+            .unwrap_or_else(|| ARCH.to_string())
+    })
+}
+
 fn get_single_var(variable: &str) -> Option<String> {
     var(variable).ok()
 }
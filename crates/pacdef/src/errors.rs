@@ -18,6 +18,8 @@ pub enum Error {
     InvalidGroupName(String),
     /// Multiple groups not found.
     MultipleGroupsNotFound(Vec<String>),
+    /// Another pacdef that changes packages is running, with its pid if known.
+    AnotherInstanceRunning(Option<u32>),
 }
 
 impl Display for Error {
@@ -37,6 +39,10 @@ impl Display for Error {
                     vec.join(", ")
                 )
             }
+            Self::AnotherInstanceRunning(Some(pid)) => {
+                write!(f, "another pacdef instance is running (pid {pid})")
+            }
+            Self::AnotherInstanceRunning(None) => write!(f, "another pacdef instance is running"),
         }
     }
 }
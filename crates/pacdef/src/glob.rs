@@ -0,0 +1,59 @@
+//! A minimal glob matcher for package names.
+
+/// Check if `name` matches the glob `pattern`. `*` matches any sequence of
+/// characters, including an empty one, and `?` matches exactly one character. All
+/// other characters must match literally.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern, and the name position it was tried at
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` consume one more character
+                Some((star, tried)) => {
+                    p = star + 1;
+                    n = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Check if `name` matches any of the glob `patterns`.
+pub fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| glob_matches(pattern, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_matches;
+
+    #[test]
+    fn globs() {
+        assert!(glob_matches("lib32-*", "lib32-glibc"));
+        assert!(!glob_matches("lib32-*", "glibc"));
+        assert!(glob_matches("*-git", "paru-git"));
+        assert!(glob_matches("python?-*", "python3-black"));
+        assert!(glob_matches("*a*b*", "xxaxxbxx"));
+        assert!(glob_matches("ripgrep", "ripgrep"));
+        assert!(!glob_matches("ripgrep", "ripgrep-all"));
+        assert!(glob_matches("*", ""));
+    }
+}
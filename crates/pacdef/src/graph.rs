@@ -0,0 +1,90 @@
+//! Render the declared packages of all groups as a graph.
+
+use std::collections::BTreeSet;
+
+use crate::prelude::*;
+
+/// Render the groups as a Graphviz DOT graph. Every group has an edge to each of
+/// the packages it declares, and every package has an edge to its backend.
+/// Packages declared by several groups are drawn only once per backend. The
+/// machine-local group is left out, as it is not shared.
+pub fn groups_to_dot(groups: &Groups) -> String {
+    let mut backends = BTreeSet::new();
+    let mut packages = BTreeSet::new();
+    let mut output = String::from("digraph pacdef {\n    rankdir=LR;\n");
+
+    for group in groups.iter().filter(|group| !group.local) {
+        let group_id = quote(&format!("group:{}", group.name));
+        output.push_str(&format!(
+            "    {group_id} [label={}, shape=box];\n",
+            quote(&group.name)
+        ));
+
+        for section in &group.sections {
+            backends.insert(section.name.as_str());
+
+            for package in &section.packages {
+                let package_id = format!("{}/{package}", section.name);
+                output.push_str(&format!("    {group_id} -> {};\n", quote(&package_id)));
+                packages.insert((package_id, section.name.as_str(), package.to_string()));
+            }
+        }
+    }
+
+    for (package_id, backend, label) in &packages {
+        let package_id = quote(package_id);
+        output.push_str(&format!("    {package_id} [label={}];\n", quote(label)));
+        let backend_id = quote(&format!("backend:{backend}"));
+        output.push_str(&format!("    {package_id} -> {backend_id};\n"));
+    }
+
+    for backend in backends {
+        let backend_id = quote(&format!("backend:{backend}"));
+        output.push_str(&format!(
+            "    {backend_id} [label={}, shape=ellipse];\n",
+            quote(backend)
+        ));
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+/// Quote an identifier for DOT.
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::grouping::options::GroupOptions;
+
+    #[test]
+    fn dot_graph() {
+        let group = Group {
+            name: "base".into(),
+            sections: [Section::new("arch".into(), [Package::from("zsh")].into())].into(),
+            path: PathBuf::from("/groups/base"),
+            warn_symlink: false,
+            options: GroupOptions::new(),
+            local: false,
+        };
+        let local = Group {
+            name: "local".into(),
+            path: PathBuf::from("/state/local"),
+            local: true,
+            ..group.clone()
+        };
+
+        let dot = groups_to_dot(&[group, local].into());
+
+        assert!(dot.starts_with("digraph pacdef {"));
+        assert!(dot.contains("\"group:base\" -> \"arch/zsh\";"));
+        assert!(!dot.contains("group:local"));
+        assert!(dot.contains("\"arch/zsh\" -> \"backend:arch\";"));
+        assert!(dot.contains("\"backend:arch\" [label=\"arch\", shape=ellipse];"));
+    }
+}
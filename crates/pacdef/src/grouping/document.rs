@@ -0,0 +1,325 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::grouping::options::split_inline_options;
+use crate::prelude::*;
+
+/// Whether group files are normalized whenever pacdef writes them, as set by
+/// [`set_normalize_on_write`].
+static NORMALIZE_ON_WRITE: AtomicBool = AtomicBool::new(false);
+
+/// Set whether group files are normalized whenever pacdef writes them, see
+/// [`GroupDocument::normalize`].
+pub fn set_normalize_on_write(value: bool) {
+    NORMALIZE_ON_WRITE.store(value, Ordering::Relaxed);
+}
+
+/// Whether group files are normalized whenever pacdef writes them.
+pub fn normalizes_on_write() -> bool {
+    NORMALIZE_ON_WRITE.load(Ordering::Relaxed)
+}
+
+/// The content of a group file, line by line, so that packages can be added without
+/// touching comments, blank lines or the order of the sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDocument {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// A section header like `[arch]`, with the name of the section.
+    Header { name: String, raw: String },
+    /// A line that declares a package, possibly followed by a comment.
+    Package { package: Package, raw: String },
+    /// Blank lines, comments, and lines before the first header.
+    Other(String),
+}
+
+impl Line {
+    fn parse(raw: &str, in_section: bool) -> Self {
+        if raw.starts_with('[') {
+            let name = raw
+                .trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string();
+            return Self::Header {
+                name,
+                raw: raw.to_string(),
+            };
+        }
+
+        match Package::try_from(raw) {
+            Some(package) if in_section => Self::Package {
+                package,
+                raw: raw.to_string(),
+            },
+            _ => Self::Other(raw.to_string()),
+        }
+    }
+
+    fn raw(&self) -> &str {
+        match self {
+            Self::Header { raw, .. } | Self::Package { raw, .. } | Self::Other(raw) => raw,
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.raw().trim().is_empty()
+    }
+
+    /// Write the line in its canonical form: headers without whitespace, packages
+    /// followed by two spaces before their comment, and no trailing whitespace.
+    fn normalize(&mut self) {
+        match self {
+            Self::Header { name, raw } => *raw = format!("[{name}]"),
+            Self::Package { package, raw } => {
+                let (declaration, comment) = match split_inline_options(raw) {
+                    Some((_, options, rest)) => (
+                        format!("{package} {options}"),
+                        rest.trim_start().trim_start_matches('#'),
+                    ),
+                    None => (
+                        package.to_string(),
+                        raw.split_once('#').map_or("", |(_, comment)| comment),
+                    ),
+                };
+                let comment = comment.trim();
+                *raw = if comment.is_empty() {
+                    declaration
+                } else {
+                    format!("{declaration}  # {comment}")
+                };
+            }
+            Self::Other(raw) => *raw = raw.trim_end().to_string(),
+        }
+    }
+}
+
+impl GroupDocument {
+    /// Split the content of a group file into its lines.
+    pub fn parse(content: &str) -> Self {
+        let mut in_section = false;
+        let lines = content
+            .lines()
+            .map(|raw| {
+                let line = Line::parse(raw, in_section);
+                in_section |= matches!(line, Line::Header { .. });
+                line
+            })
+            .collect();
+
+        Self { lines }
+    }
+
+    /// Add the `packages` to the first section called `section`, after the last
+    /// package that is already declared there. Packages that the section already
+    /// declares are skipped. If there is no such section, it is appended to the end,
+    /// separated by a blank line.
+    pub fn add_packages(&mut self, section: &str, packages: &Packages) {
+        let Some(header) = self.find_header(section) else {
+            self.append_section(section, packages);
+            return;
+        };
+
+        let end = self.lines[header + 1..]
+            .iter()
+            .position(|line| matches!(line, Line::Header { .. }))
+            .map_or(self.lines.len(), |offset| header + 1 + offset);
+
+        let declared: Packages = self.lines[header..end]
+            .iter()
+            .filter_map(|line| match line {
+                Line::Package { package, .. } => Some(package.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let insert_at = self.lines[header..end]
+            .iter()
+            .rposition(|line| matches!(line, Line::Package { .. } | Line::Header { .. }))
+            .map_or(end, |offset| header + offset + 1);
+
+        let new_lines = packages
+            .iter()
+            .filter(|package| !declared.contains(package))
+            .map(|package| Line::Package {
+                package: package.clone(),
+                raw: package.to_string(),
+            });
+
+        self.lines.splice(insert_at..insert_at, new_lines);
+    }
+
+    /// Bring the document into its canonical form. Packages are sorted by name
+    /// within each block of consecutive package lines, so comments and blank lines
+    /// keep hand-made groupings and sorting never crosses a section. Sections are
+    /// separated by a single blank line, other runs of blank lines are collapsed,
+    /// and every line is normalized, see [`Line::normalize`].
+    pub fn normalize(&mut self) {
+        for line in &mut self.lines {
+            line.normalize();
+        }
+
+        for block in self
+            .lines
+            .split_mut(|line| !matches!(line, Line::Package { .. }))
+        {
+            block.sort_by(|a, b| match (a, b) {
+                (Line::Package { package: a, .. }, Line::Package { package: b, .. }) => a.cmp(b),
+                _ => std::cmp::Ordering::Equal,
+            });
+        }
+
+        let mut lines: Vec<Line> = Vec::with_capacity(self.lines.len());
+        for line in self.lines.drain(..) {
+            let after_content = lines.last().is_some_and(|previous| !previous.is_blank());
+
+            if line.is_blank() && !after_content {
+                continue;
+            }
+            if matches!(line, Line::Header { .. }) && after_content {
+                lines.push(Line::Other(String::new()));
+            }
+
+            lines.push(line);
+        }
+
+        while lines.last().is_some_and(Line::is_blank) {
+            lines.pop();
+        }
+
+        self.lines = lines;
+    }
+
+    fn find_header(&self, section: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .position(|line| matches!(line, Line::Header { name, .. } if name == section))
+    }
+
+    fn append_section(&mut self, section: &str, packages: &Packages) {
+        let needs_blank = self
+            .lines
+            .last()
+            .is_some_and(|line| !line.raw().trim().is_empty());
+
+        if needs_blank {
+            self.lines.push(Line::Other(String::new()));
+        }
+
+        self.lines.push(Line::Header {
+            name: section.to_string(),
+            raw: format!("[{section}]"),
+        });
+        self.lines
+            .extend(packages.iter().map(|package| Line::Package {
+                package: package.clone(),
+                raw: package.to_string(),
+            }));
+    }
+}
+
+/// Every line is terminated by a newline, including the last one.
+impl Display for GroupDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line.raw())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "\
+# my tools, see also [rust] in dev
+[arch]
+# editors
+vim  # the one true editor
+helix
+
+# comment at the end of [arch]
+
+[rust]
+ripgrep
+";
+
+    #[test]
+    fn adding_packages_keeps_comments() {
+        let mut document = GroupDocument::parse(CONTENT);
+        let packages: Packages = [Package::from("neovim"), Package::from("vim")].into();
+        document.add_packages("arch", &packages);
+
+        let expected = "\
+# my tools, see also [rust] in dev
+[arch]
+# editors
+vim  # the one true editor
+helix
+neovim
+
+# comment at the end of [arch]
+
+[rust]
+ripgrep
+";
+        assert_eq!(document.to_string(), expected);
+    }
+
+    #[test]
+    fn normalizing_sorts_within_blocks() {
+        let content = "\
+[arch]   \n\
+# editors
+vim#the one true editor
+helix
+\n\n\
+neovim   # \n\
+extra/bat
+[rust]
+ripgrep {target = \"x86_64-unknown-linux-musl\"}   #static
+fd-find
+[vim]
+vim-fugitive { url = \"https://host/fugitive.git#main\" }
+\n";
+        let mut document = GroupDocument::parse(content);
+        document.normalize();
+
+        let expected = "\
+[arch]
+# editors
+helix
+vim  # the one true editor
+
+extra/bat
+neovim
+
+[rust]
+fd-find
+ripgrep {target = \"x86_64-unknown-linux-musl\"}  # static
+
+[vim]
+vim-fugitive { url = \"https://host/fugitive.git#main\" }
+";
+        assert_eq!(document.to_string(), expected);
+
+        document.normalize();
+        assert_eq!(document.to_string(), expected);
+    }
+
+    #[test]
+    fn adding_packages_to_new_section() {
+        let mut document = GroupDocument::parse(CONTENT);
+        document.add_packages("python", &[Package::from("black")].into());
+
+        assert_eq!(
+            document.to_string(),
+            format!("{CONTENT}\n[python]\nblack\n")
+        );
+        assert_eq!(GroupDocument::parse(CONTENT).to_string(), CONTENT);
+    }
+}
@@ -1,27 +1,66 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
-use std::fs::{create_dir, read_to_string, File};
+use std::fs::{read_to_string, File};
 use std::hash::Hash;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use path_absolutize::Absolutize;
 use walkdir::WalkDir;
 
+use crate::config::NATIVE_BACKENDS;
+use crate::grouping::document::{normalizes_on_write, GroupDocument};
+use crate::grouping::options::{
+    is_sidecar, load_inline_options, load_sidecar, merge_inline_options, parse_inline_options,
+    GroupOptions,
+};
 use crate::path::get_relative_path;
 
 use crate::prelude::*;
+use crate::trace::{trace, traced};
 
 /// A set of groups
 pub type Groups = BTreeSet<Group>;
 
+/// The pseudo-section of group files whose packages are declared for every backend
+/// in `all_section_backends` of the config.
+pub const ALL_SECTION: &str = "all";
+
 pub type BackendPackages = BTreeMap<AnyBackend, Packages>;
+
+/// Get the packages of all `groups` per backend. The packages of the `[all]` section
+/// are added to every enabled backend in `all_section_backends` that pacdef was
+/// built with.
 pub fn groups_to_backend_packages(groups: &Groups, config: &Config) -> Result<BackendPackages> {
     let mut backend_packages = BackendPackages::new();
 
     for group in groups {
         for section in &group.sections {
+            for package in traced(&section.packages) {
+                trace(&section.name, package, || {
+                    format!("declared in group '{}'", group.name)
+                });
+            }
+
+            if section.name == ALL_SECTION {
+                for backend in config
+                    .sections_for_all()
+                    .filter_map(|name| AnyBackend::from_section(name, config).ok())
+                {
+                    for package in traced(&section.packages) {
+                        trace(backend.backend_info().section, package, || {
+                            format!("declared for [{backend}] by the [{ALL_SECTION}] section")
+                        });
+                    }
+                    backend_packages
+                        .entry(backend)
+                        .or_default()
+                        .extend(section.packages.iter().cloned());
+                }
+                continue;
+            }
+
             backend_packages
                 .entry(AnyBackend::from_section(&section.name, config)?)
                 .or_default()
@@ -32,6 +71,69 @@ pub fn groups_to_backend_packages(groups: &Groups, config: &Config) -> Result<Ba
     Ok(backend_packages)
 }
 
+/// Get the names of the `groups` that declare `package` for the backend with the
+/// given `section`, either in that section or in the `[all]` section.
+pub fn declaring_groups<'a>(groups: &'a Groups, section: &str, package: &Package) -> Vec<&'a str> {
+    groups
+        .iter()
+        .filter(|group| {
+            group.sections.iter().any(|declared| {
+                (declared.name == section || declared.name == ALL_SECTION)
+                    && declared.packages.contains(package)
+            })
+        })
+        .map(|group| group.name.as_str())
+        .collect()
+}
+
+/// Describe every package that is declared for more than one enabled native backend,
+/// together with the groups that declare it. Only one native backend is used on a
+/// machine, so such a package would be installed by each of them. This happens e.g.
+/// if the native backend cannot be detected and the `[all]` section applies to
+/// several of them.
+pub fn native_duplicates(
+    backend_packages: &BackendPackages,
+    groups: &Groups,
+    config: &Config,
+) -> Vec<String> {
+    let mut sections_of: BTreeMap<&Package, Vec<&str>> = BTreeMap::new();
+    for (backend, packages) in backend_packages {
+        let section = backend.backend_info().section;
+        if !NATIVE_BACKENDS.contains(&section)
+            || config
+                .disabled_backends
+                .iter()
+                .any(|disabled| disabled == section)
+        {
+            continue;
+        }
+
+        for package in packages {
+            sections_of.entry(package).or_default().push(section);
+        }
+    }
+
+    sections_of
+        .into_iter()
+        .filter(|(_, sections)| sections.len() > 1)
+        .map(|(package, sections)| {
+            let mut declaring: Vec<&str> = sections
+                .iter()
+                .flat_map(|section| declaring_groups(groups, section, package))
+                .collect();
+            declaring.sort_unstable();
+            declaring.dedup();
+
+            format!(
+                "{package} is declared for the native backends {} by the groups {}, \
+                 set `native_backend` or move it into the section of one of them",
+                sections.join(", "),
+                declaring.join(", ")
+            )
+        })
+        .collect()
+}
+
 /// Representation of a group file.
 #[derive(Debug, Clone)]
 pub struct Group {
@@ -44,31 +146,47 @@ pub struct Group {
     pub path: PathBuf,
     /// Whether the main program should warn this group being loaded from a symlink.
     pub warn_symlink: bool,
+    /// The package options from the sidecar file of the group.
+    pub options: GroupOptions,
+    /// Whether this is the machine-local group, which is never exported, pruned or
+    /// drawn in the graph.
+    pub local: bool,
 }
 
 impl Group {
     /// Load all group files from the pacdef group dir by traversing through the group dir.
     ///
     /// This method will print a warning if `warn_not_symlinks` is true and a group
-    /// file is not a symlink or does not reside under a symlink dir.
+    /// file is not a symlink or does not reside under a symlink dir. Symlinks whose
+    /// target does not exist are skipped with a warning. Sidecar files are loaded
+    /// together with their group file.
     ///
     /// # Errors
     ///
-    /// This function will return an error if any of the files under `group_dir` cannot
-    /// be accessed.
+    /// This function will return an error if `group_dir` is not a directory, or if any
+    /// of the files under `group_dir` cannot be accessed.
     pub fn load(group_dir: &Path, warn_not_symlinks: bool) -> Result<Groups> {
         let mut result = Groups::new();
 
-        if !group_dir.is_dir() {
-            // we only need to create the innermost dir. The rest was already created from when
-            // we loaded the config
-            create_dir(group_dir).context("group dir does not exist, creating")?;
-        }
+        ensure!(
+            group_dir.is_dir(),
+            "group dir {} is not a directory",
+            group_dir.to_string_lossy()
+        );
 
         let mut symlink_dirs = Vec::new();
 
         for entry in WalkDir::new(group_dir).follow_links(true).min_depth(1) {
-            let file = entry?;
+            let file = match entry {
+                Ok(file) => file,
+                Err(error) => match error.path().filter(|path| is_dangling_symlink(path)) {
+                    Some(path) => {
+                        warn_dangling_symlink(path);
+                        continue;
+                    }
+                    None => return Err(error.into()),
+                },
+            };
             let path = file.path().absolutize_from(group_dir)?.to_path_buf();
 
             if path.is_dir() {
@@ -78,6 +196,10 @@ impl Group {
                 continue;
             }
 
+            if is_sidecar(&path) {
+                continue;
+            }
+
             let should_warn_about_symlinks = warn_not_symlinks
                 && !path.is_symlink()
                 && !is_child_of_any_dir(&path, &symlink_dirs);
@@ -90,6 +212,131 @@ impl Group {
 
         Ok(result)
     }
+
+    /// Check every group file under `group_dir` and its sidecar file, and return all
+    /// problems found, each prefixed with the file and line. Unlike [`Group::load`],
+    /// broken sections are reported instead of skipped with a warning. No backend is
+    /// queried.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `group_dir` is not a directory, or if
+    /// it cannot be traversed.
+    pub fn validate(group_dir: &Path, config: &Config) -> Result<Vec<String>> {
+        ensure!(
+            group_dir.is_dir(),
+            "group dir {} is not a directory",
+            group_dir.to_string_lossy()
+        );
+
+        let mut problems = Vec::new();
+
+        for entry in WalkDir::new(group_dir)
+            .follow_links(true)
+            .min_depth(1)
+            .sort_by_file_name()
+        {
+            let file = entry?;
+            let path = file.path();
+
+            if path.is_dir() || is_sidecar(path) {
+                continue;
+            }
+
+            let location = path.to_string_lossy();
+
+            match read_to_string(path) {
+                Ok(content) => problems.extend(
+                    check_group_content(&content, config)
+                        .into_iter()
+                        .map(|(line, message)| format!("{location}:{line}: {message}")),
+                ),
+                Err(error) => problems.push(format!("{location}: {error}")),
+            }
+
+            if let Err(error) = load_sidecar(path) {
+                problems.push(format!("{error:#}"));
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+/// Find all symlinks under `group_dir` whose target does not exist.
+///
+/// # Errors
+///
+/// This function will return an error if `group_dir` cannot be traversed.
+pub fn find_dangling_symlinks(group_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut result = vec![];
+
+    for entry in WalkDir::new(group_dir).min_depth(1) {
+        let path = entry?.into_path();
+        if is_dangling_symlink(&path) {
+            result.push(path);
+        }
+    }
+
+    Ok(result)
+}
+
+fn is_dangling_symlink(path: &Path) -> bool {
+    path.is_symlink() && !path.exists()
+}
+
+/// Tell the user which group file is skipped because its target is gone, e.g.
+/// after the dotfiles repository it points into was reorganized.
+fn warn_dangling_symlink(link: &Path) {
+    let target = link.read_link().unwrap_or_default();
+    log::warn!(
+        "skipping group file {}, it is a symlink to {}, which does not exist",
+        link.to_string_lossy(),
+        target.to_string_lossy()
+    );
+}
+
+/// Find the problems in the `content` of a group file, with their line numbers
+/// starting at 1: malformed headers, headers without a matching backend, packages
+/// outside of a section, invalid inline options, and empty sections.
+fn check_group_content(content: &str, config: &Config) -> Vec<(usize, String)> {
+    let mut problems = Vec::new();
+    // the line and name of the current section, and whether it declares any package
+    let mut section: Option<(usize, String, bool)> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let number = index + 1;
+
+        if line.starts_with('[') {
+            if let Some((start, name, false)) = section.take() {
+                problems.push((start, format!("[{name}] is empty")));
+            }
+
+            let header = line.trim();
+            let name = header.trim_start_matches('[').trim_end_matches(']');
+            if !header.ends_with(']') || name.is_empty() {
+                problems.push((number, format!("malformed section header '{header}'")));
+            } else if name != ALL_SECTION && AnyBackend::from_section(name, config).is_err() {
+                problems.push((number, format!("no matching backend for [{name}]")));
+            }
+
+            section = Some((number, name.to_string(), false));
+        } else if Package::try_from(line).is_some() {
+            match &mut section {
+                Some((_, _, has_packages)) => *has_packages = true,
+                None => problems.push((number, "package outside of a section".to_string())),
+            }
+            if let Some(Err(error)) = parse_inline_options(line) {
+                problems.push((number, format!("{error:#}")));
+            }
+        }
+    }
+
+    if let Some((start, name, false)) = section {
+        problems.push((start, format!("[{name}] is empty")));
+    }
+
+    problems
 }
 
 /// Check if `path` is a child of any of the [`PathBuf`] in `dirs`. All paths should be
@@ -99,14 +346,12 @@ fn is_child_of_any_dir(path: &Path, dirs: &[PathBuf]) -> bool {
         // pair `path` with every item from `symlink_dirs`
         .zip([path].iter().cycle())
         // for every pair, test if all path elements of the dir are present in the file path
-        .map(|(dir, file)| {
+        // it suffices if that holds for any of the generated pairs
+        .any(|(dir, file)| {
             dir.iter()
                 .zip(file.iter())
-                .map(|(dir_elem, file_elem)| dir_elem == file_elem)
-                .all(|path_element_equal| path_element_equal)
+                .all(|(dir_elem, file_elem)| dir_elem == file_elem)
         })
-        // it suffices if that holds for any of the generated pairs
-        .any(|is_child| is_child)
 }
 
 impl PartialOrd for Group {
@@ -133,11 +378,40 @@ impl PartialEq for Group {
     }
 }
 
-impl Eq for Group {
-    fn assert_receiver_is_total_eq(&self) {}
-}
+impl Eq for Group {}
 
 impl Group {
+    /// Load a single group file from anywhere, outside of the group dir. The name of
+    /// the group is the file name. Its sidecar file is loaded as well.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file or its sidecar file cannot be
+    /// read.
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let path = path.absolutize()?;
+        let dir = path.parent().context("getting parent dir")?;
+        Self::try_from(path.as_ref(), dir, false)
+    }
+
+    /// Load the machine-local group from `path`, if the file exists. It is loaded in
+    /// addition to the group dir, but not part of it, so it is not shared with other
+    /// machines.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file or its sidecar file cannot be
+    /// read.
+    pub fn load_local(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut group = Self::load_file(path)?;
+        group.local = true;
+        Ok(Some(group))
+    }
+
     /// Load the group from `path`. Determine the name from the path relative to the
     /// `group_dir`.
     ///
@@ -178,6 +452,10 @@ impl Group {
             log::warn!("no sections found in group '{name}'");
         }
 
+        let mut options = load_sidecar(path).context("loading package options")?;
+        let inline = load_inline_options(&content).context("loading inline package options")?;
+        merge_inline_options(&mut options, inline).context("merging inline package options")?;
+
         let path = path.into();
 
         Ok(Self {
@@ -185,32 +463,33 @@ impl Group {
             sections,
             path,
             warn_symlink,
+            options,
+            local: false,
         })
     }
 
-    /// Add the new `packages` to the group file under the section `section_header`. If
-    /// the section header does not yet exist, it is created. The packages are written
-    /// in the provided order immediately after the header.
+    /// Add the new `packages` to the group file under the section `section`. If the
+    /// section does not yet exist, it is created. Everything else in the file is kept
+    /// as it is, see [`GroupDocument::add_packages`].
     ///
     /// # Errors
     ///
     /// This function returns an error if the group file cannot be read, or if the
     /// file cannot be written to.
-    pub fn save_packages(&self, section_header: &str, packages: &Packages) -> Result<()> {
-        let mut content = read_to_string(&self.path)
+    pub fn save_packages(&self, section: &str, packages: &Packages) -> Result<()> {
+        let content = read_to_string(&self.path)
             .with_context(|| format!("reading existing file contents from {:?}", &self.path))?;
 
-        if content.contains(section_header) {
-            write_packages_to_existing_section(&mut content, section_header, packages)
-                .context("existing section")?;
-        } else {
-            add_new_section_with_packages(&mut content, section_header, packages);
+        let mut document = GroupDocument::parse(&content);
+        document.add_packages(section, packages);
+        if normalizes_on_write() {
+            document.normalize();
         }
 
         let mut file = File::create(&self.path)
             .with_context(|| format!("creating descriptor to output file {:?}", &self.path))?;
 
-        write!(file, "{content}").with_context(|| format!("writing file {:?}", &self.path))
+        write!(file, "{document}").with_context(|| format!("writing file {:?}", &self.path))
     }
 }
 
@@ -255,70 +534,172 @@ impl Display for Group {
     }
 }
 
-/// Add some packages to an existing section in the content of a group file.
-///
-/// # Errors
-///
-/// This function will return an error if the header cannot be found in
-/// the file content.
-fn write_packages_to_existing_section(
-    group_file_content: &mut String,
-    section_header: &str,
-    packages: &Packages,
-) -> Result<()> {
-    let idx_of_first_package_line_in_section =
-        find_first_package_line_in_section(group_file_content, section_header)?;
-
-    let after = group_file_content.split_off(idx_of_first_package_line_in_section);
-
-    for p in packages {
-        group_file_content.push_str(&format!("{p}\n"));
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn group(name: &str, sections: Vec<Section>) -> Group {
+        Group {
+            name: name.into(),
+            sections: sections.into_iter().collect(),
+            path: PathBuf::from("/groups").join(name),
+            warn_symlink: false,
+            options: GroupOptions::new(),
+            local: false,
+        }
     }
 
-    group_file_content.push_str(&after);
-    Ok(())
-}
+    #[test]
+    fn check_group_content() {
+        let content = "\
+# my tools
+ripgrep
+[rust]
+fd-find  # comment
+
+[nonsense]
+foo
+[arch
+vim
+[python]
+# nothing here
+";
+        let result = super::check_group_content(content, &Config::default());
+        assert_eq!(
+            result,
+            [
+                (2, "package outside of a section".to_string()),
+                (6, "no matching backend for [nonsense]".to_string()),
+                (8, "malformed section header '[arch'".to_string()),
+                (10, "[python] is empty".to_string()),
+            ]
+        );
+    }
 
-/// Find the index to the first line in `group_file_content` after the
-/// given `section_header`.
-///
-/// # Errors
-///
-/// This function will return an error if the `section_header` does not
-/// exist in `group_file_content`, or if the line containing the
-/// `section_header` is not newline-terminated.
-fn find_first_package_line_in_section(
-    group_file_content: &str,
-    section_header: &str,
-) -> Result<usize> {
-    let section_start = group_file_content
-        .find(section_header)
-        .context("finding first package after section header")?;
-
-    let distance_to_next_newline = group_file_content[section_start..]
-        .find('\n')
-        .context("getting next newline")?;
-
-    Ok(section_start + distance_to_next_newline + 1) // + 1 to be after the newline
-}
+    #[test]
+    fn all_section_is_expanded() {
+        let config = Config {
+            all_section_backends: vec!["fedora".into(), "void".into(), "nonsense".into()],
+            disabled_backends: vec!["void".into()],
+            ..Config::default()
+        };
+        let group = group(
+            "base",
+            vec![
+                Section::new(ALL_SECTION.into(), [Package::from("git")].into()),
+                Section::new("fedora".into(), [Package::from("vim")].into()),
+            ],
+        );
+
+        let result = groups_to_backend_packages(&[group].into(), &config)
+            .expect("all sections have a backend");
+
+        let result: Vec<_> = result
+            .iter()
+            .map(|(backend, packages)| (backend.backend_info().section, packages.clone()))
+            .collect();
+        assert_eq!(
+            result,
+            [(
+                "fedora",
+                [Package::from("git"), Package::from("vim")].into()
+            )]
+        );
+    }
 
-/// Append a new section with some packages to the content of a group file.
-fn add_new_section_with_packages(
-    group_file_content: &mut String,
-    section_header: &str,
-    packages: &Packages,
-) {
-    group_file_content.push('\n');
-    group_file_content.push_str(section_header);
-    group_file_content.push('\n');
-    for p in packages {
-        group_file_content.push_str(&format!("{p}\n"));
+    #[test]
+    fn packages_of_several_native_backends() {
+        let config = Config {
+            all_section_backends: vec!["fedora".into(), "void".into()],
+            ..Config::default()
+        };
+        let groups = [
+            group(
+                "base",
+                vec![Section::new(
+                    ALL_SECTION.into(),
+                    [Package::from("git")].into(),
+                )],
+            ),
+            group(
+                "work",
+                vec![
+                    Section::new("fedora".into(), [Package::from("vim")].into()),
+                    Section::new("rust".into(), [Package::from("git")].into()),
+                ],
+            ),
+        ]
+        .into();
+
+        let backend_packages =
+            groups_to_backend_packages(&groups, &config).expect("all sections have a backend");
+        assert_eq!(
+            native_duplicates(&backend_packages, &groups, &config),
+            [
+                "git is declared for the native backends fedora, void by the groups base, \
+              set `native_backend` or move it into the section of one of them"
+            ]
+        );
+
+        let config = Config {
+            disabled_backends: vec!["void".into()],
+            ..config
+        };
+        assert!(native_duplicates(&backend_packages, &groups, &config).is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::path::PathBuf;
+    #[test]
+    fn declaring_groups_of_package() {
+        let groups: Groups = [
+            group(
+                "base",
+                vec![Section::new(
+                    ALL_SECTION.into(),
+                    [Package::from("git")].into(),
+                )],
+            ),
+            group(
+                "dev",
+                vec![Section::new(
+                    "arch".into(),
+                    [Package::from("extra/git")].into(),
+                )],
+            ),
+            group(
+                "rust",
+                vec![Section::new("rust".into(), [Package::from("git")].into())],
+            ),
+        ]
+        .into();
+
+        assert_eq!(
+            declaring_groups(&groups, "arch", &Package::from("git")),
+            ["base", "dev"]
+        );
+        assert!(declaring_groups(&groups, "arch", &Package::from("vim")).is_empty());
+    }
+
+    #[test]
+    fn dangling_symlink_is_skipped() {
+        let dir = std::env::temp_dir().join(format!("pacdef-dangling-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("temp dir is writable");
+        std::fs::write(dir.join("base"), "[rust]\nripgrep\n").expect("temp dir is writable");
+        std::os::unix::fs::symlink(dir.join("moved/work"), dir.join("work"))
+            .expect("temp dir is writable");
+
+        let result = Group::load(&dir, false);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let names: Vec<_> = result
+            .expect("dangling symlink is skipped")
+            .into_iter()
+            .map(|group| group.name)
+            .collect();
+        assert_eq!(names, ["base"]);
+    }
 
     #[test]
     fn extract_group_name() {
@@ -7,8 +7,16 @@ more [`Section`]s, which relate to individual backends. Each section contains
 one (strictly spoken zero) or more [`Package`]s. On start-up `pacdef` will load
 all groups using [`Group::load`], which in turn will get all packages from all
 sections.
+
+Options for individual packages that do not fit into a plain package list are
+read from an optional sidecar file `<group>.toml` next to the group file.
+
+Group files are changed through a [`document::GroupDocument`], which keeps comments,
+blank lines and the order of sections intact.
 */
 
+pub mod document;
 pub mod group;
+pub mod options;
 pub mod package;
 pub mod section;
@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+/// The options for individual packages of a group, keyed by backend section and
+/// package name.
+pub type GroupOptions = BTreeMap<String, BTreeMap<String, PackageOptions>>;
+
+/// Options for installing a single package, as declared in the sidecar file of a
+/// group. Each option is only supported by some backends.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageOptions {
+    /// The target triple to install a cargo crate for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The rustup toolchain to install a cargo crate with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// The rustup profile to install a toolchain with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Whether asdf or mise use the version globally after installing it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global: Option<bool>,
+    /// The archive to install an Emacs package from, e.g. `melpa` or `gnu`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+    /// The git URL to clone a vim plugin from, or to install a cargo crate from, or
+    /// the URL of the file to install an Arch package from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The package file to install an Arch package from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// The git branch to clone a vim plugin from, or to install a cargo crate from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// The git tag to install a cargo crate from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// The git commit to install a cargo crate from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// The cabal flags to build a Haskell package with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<String>,
+    /// The version constraint of a Haskell package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
+    /// The version to install a Perl module in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Packages with a higher priority are installed before the other packages of
+    /// the same backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// A failure to install an optional package only results in a warning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<bool>,
+    /// Whether to enable a GNOME Shell extension after installing it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable: Option<bool>,
+    /// Whether to install a Perl module without running its tests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notest: Option<bool>,
+    /// The machine architecture, as `uname -m` prints it, that the package is only
+    /// installed on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// The features to install a cargo crate with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features: Option<Vec<String>>,
+    /// Whether to install a cargo crate with all of its features.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub all_features: Option<bool>,
+    /// Whether to install a cargo crate without its default features.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_default_features: Option<bool>,
+    /// The optional dependencies to install as dependencies along with an Arch
+    /// package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional_deps: Option<Vec<String>>,
+}
+
+/// The options written inline after a package in a group file.
+#[derive(Deserialize)]
+struct InlineOptions {
+    options: PackageOptions,
+}
+
+/// Split a `line` of a group file into the part before the inline options, the
+/// inline table from `{` to its matching `}`, and the rest of the line, which may
+/// hold a comment. A `#` within the table, e.g. in a URL, belongs to the table.
+/// Returns `None` if the line has no inline options, i.e. no `{` before any `#`.
+pub fn split_inline_options(line: &str) -> Option<(&str, &str, &str)> {
+    let start = line.find('{')?;
+    if line[..start].contains('#') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in line[start..].char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{' | '[') => depth += 1,
+            (None, '}' | ']') => {
+                depth -= 1;
+                if depth == 0 {
+                    let end = start + index + 1;
+                    return Some((&line[..start], &line[start..end], &line[end..]));
+                }
+            }
+            (None, _) => {}
+        }
+        escaped = false;
+    }
+
+    // unbalanced, which the TOML parser reports
+    Some((&line[..start], &line[start..], ""))
+}
+
+/// Parse the options written inline after the package on a `line` of a group file,
+/// as a TOML inline table like `ripgrep { target = "x86_64-unknown-linux-musl" }`,
+/// optionally followed by a comment. Returns `None` if the line has no inline
+/// options.
+pub fn parse_inline_options(line: &str) -> Option<Result<PackageOptions>> {
+    let (_, table, rest) = split_inline_options(line)?;
+
+    let result = toml::from_str::<InlineOptions>(&format!("options = {table}{rest}"))
+        .map(|inline| inline.options)
+        .with_context(|| format!("parsing inline options {}", table.trim_end()));
+    Some(result)
+}
+
+/// Get the inline options of all packages in the `content` of a group file, keyed by
+/// section and package name.
+///
+/// # Errors
+///
+/// This function will return an error if the inline options of any package cannot be
+/// parsed.
+pub fn load_inline_options(content: &str) -> Result<GroupOptions> {
+    let mut result = GroupOptions::new();
+    let mut section = None;
+
+    for (index, line) in content.lines().enumerate() {
+        if line.starts_with('[') {
+            section = Some(line.trim().trim_start_matches('[').trim_end_matches(']'));
+            continue;
+        }
+
+        let (Some(section), Some(package)) = (section, Package::try_from(line)) else {
+            continue;
+        };
+        if let Some(options) = parse_inline_options(line) {
+            let options = options.with_context(|| format!("line {}", index + 1))?;
+            result
+                .entry(section.to_string())
+                .or_default()
+                .insert(package.name, options);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Merge the `inline` options of a group file into the `options` from its sidecar
+/// file. If a package has the same option in both, the sidecar option wins and a
+/// warning is logged.
+///
+/// # Errors
+///
+/// This function will return an error if the options cannot be converted for
+/// merging, which does not happen for valid options.
+pub fn merge_inline_options(options: &mut GroupOptions, inline: GroupOptions) -> Result<()> {
+    for (backend, packages) in inline {
+        let section = options.entry(backend.clone()).or_default();
+
+        for (package, inline) in packages {
+            let merged = match section.remove(&package) {
+                None => inline,
+                Some(sidecar) => {
+                    let mut merged = toml::Value::try_from(sidecar)?;
+                    if let (Some(table), toml::Value::Table(inline)) =
+                        (merged.as_table_mut(), toml::Value::try_from(inline)?)
+                    {
+                        for (key, value) in inline {
+                            match table.get(&key) {
+                                None => {
+                                    table.insert(key, value);
+                                }
+                                Some(sidecar) if *sidecar != value => log::warn!(
+                                    "[{backend}] {package}: option {key} is set both inline \
+                                     and in the sidecar file, using the sidecar value"
+                                ),
+                                Some(_) => (),
+                            }
+                        }
+                    }
+                    merged.try_into()?
+                }
+            };
+            section.insert(package, merged);
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the path of the sidecar file of the group file at `group_file`, which is the
+/// path with `.toml` appended.
+pub fn get_sidecar_path(group_file: &Path) -> PathBuf {
+    let mut path = OsString::from(group_file);
+    path.push(".toml");
+    path.into()
+}
+
+/// Check whether `path` is the sidecar file of another group file.
+pub fn is_sidecar(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml") && path.with_extension("").is_file()
+}
+
+/// Load the options from the sidecar file of the group file at `group_file`. If there
+/// is no sidecar file, no options are returned.
+///
+/// # Errors
+///
+/// This function will return an error if the sidecar file exists, but cannot be read
+/// or parsed.
+pub fn load_sidecar(group_file: &Path) -> Result<GroupOptions> {
+    let path = get_sidecar_path(group_file);
+
+    if !path.exists() {
+        return Ok(GroupOptions::new());
+    }
+
+    let content = read_to_string(&path)
+        .with_context(|| format!("reading sidecar file {}", path.to_string_lossy()))?;
+
+    toml::from_str(&content)
+        .with_context(|| format!("parsing sidecar file {}", path.to_string_lossy()))
+}
+
+/// Save `options` to the sidecar file of the group file at `group_file`. Nothing is
+/// written if there are no options.
+///
+/// # Errors
+///
+/// This function will return an error if the sidecar file cannot be written.
+pub fn save_sidecar(group_file: &Path, options: &GroupOptions) -> Result<()> {
+    if options.is_empty() {
+        return Ok(());
+    }
+
+    let path = get_sidecar_path(group_file);
+    let content = toml::to_string(options).context("converting package options to toml")?;
+
+    write(&path, content)
+        .with_context(|| format!("writing sidecar file {}", path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sidecar() {
+        let content = r#"
+            [rust.ripgrep]
+            target = "x86_64-unknown-linux-musl"
+
+            [rustup.nightly]
+            profile = "minimal"
+        "#;
+
+        let options: GroupOptions = toml::from_str(content).expect("valid sidecar");
+
+        assert_eq!(
+            options["rust"]["ripgrep"].target.as_deref(),
+            Some("x86_64-unknown-linux-musl")
+        );
+        assert_eq!(
+            options["rustup"]["nightly"].profile.as_deref(),
+            Some("minimal")
+        );
+        assert!(toml::from_str::<GroupOptions>("[rust.bat]\ncolor = true").is_err());
+    }
+
+    #[test]
+    fn inline_options() {
+        let content = r#"
+[rust]
+ripgrep { target = "x86_64-unknown-linux-musl", toolchain = "nightly" }  # static
+bat
+
+[arch]
+extra/neovim {optional=true}
+"#;
+
+        let inline = load_inline_options(content).expect("valid inline options");
+        assert_eq!(
+            inline["rust"]["ripgrep"].target.as_deref(),
+            Some("x86_64-unknown-linux-musl")
+        );
+        assert!(!inline["rust"].contains_key("bat"));
+        assert_eq!(inline["arch"]["neovim"].optional, Some(true));
+
+        let mut options: GroupOptions =
+            toml::from_str("[rust.ripgrep]\ntoolchain = \"stable\"\npriority = 1").expect("valid");
+        merge_inline_options(&mut options, inline).expect("valid options merge");
+        assert_eq!(
+            options["rust"]["ripgrep"].target.as_deref(),
+            Some("x86_64-unknown-linux-musl")
+        );
+        assert_eq!(options["rust"]["ripgrep"].priority, Some(1));
+
+        assert!(load_inline_options("[rust]\nbat { color = true }").is_err());
+        assert!(load_inline_options("[rust]\nbat { optional = true } garbage").is_err());
+        assert!(load_inline_options("[rust]\nbat { target = }").is_err());
+    }
+
+    #[test]
+    fn sidecar_wins_over_inline_options() {
+        let inline = load_inline_options("[rust]\nripgrep { toolchain = \"nightly\" }")
+            .expect("valid inline options");
+        let mut options: GroupOptions =
+            toml::from_str("[rust.ripgrep]\ntoolchain = \"stable\"").expect("valid sidecar");
+
+        merge_inline_options(&mut options, inline).expect("valid options merge");
+        assert_eq!(
+            options["rust"]["ripgrep"].toolchain.as_deref(),
+            Some("stable")
+        );
+    }
+
+    #[test]
+    fn inline_features_and_optional_deps() {
+        let content = r#"
+[rust]
+ripgrep { features = ["pcre2"], no_default_features = true }
+
+[arch]
+mpv { optional_deps = ["yt-dlp"] }
+"#;
+
+        let inline = load_inline_options(content).expect("valid inline options");
+        let ripgrep = &inline["rust"]["ripgrep"];
+        assert_eq!(ripgrep.features, Some(vec!["pcre2".into()]));
+        assert_eq!(ripgrep.no_default_features, Some(true));
+        assert_eq!(ripgrep.all_features, None);
+        assert_eq!(
+            inline["arch"]["mpv"].optional_deps,
+            Some(vec!["yt-dlp".into()])
+        );
+    }
+
+    #[test]
+    fn hash_within_inline_options() {
+        let line = r##"zsh-theme { url = "https://host/repo#branch", branch = "a\"#}" }  # theme"##;
+        assert_eq!(
+            split_inline_options(line),
+            Some((
+                "zsh-theme ",
+                r##"{ url = "https://host/repo#branch", branch = "a\"#}" }"##,
+                "  # theme"
+            ))
+        );
+        let inline = load_inline_options(&format!("[vim]\n{line}")).expect("# in a string");
+        assert_eq!(
+            inline["vim"]["zsh-theme"].url.as_deref(),
+            Some("https://host/repo#branch")
+        );
+        assert_eq!(split_inline_options("bat  # {not options}"), None);
+    }
+}
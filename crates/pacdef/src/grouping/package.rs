@@ -16,6 +16,9 @@ pub struct Package {
 
 fn remove_comment_and_trim_whitespace(s: &str) -> &str {
     s.split('#') // remove comment
+        .next()
+        .expect("line contains something")
+        .split('{') // remove inline options
         .next()
         .expect("line contains something")
         .trim() // remove whitespace
@@ -54,7 +57,8 @@ impl Package {
     }
 
     /// Try to parse a string (from a line in a group file) and return a package.
-    /// From the string, any possible comment is removed and whitespace is trimmed.package
+    /// From the string, any possible comment and inline options are removed and
+    /// whitespace is trimmed.
     /// Returns `None` if there is nothing left after trimming.
     pub fn try_from<S>(s: S) -> Option<Self>
     where
@@ -124,11 +128,24 @@ mod tests {
         assert_eq!(repo, None);
     }
 
+    #[test]
+    fn repo_is_ignored_for_installed_packages() {
+        let declared = Package::from("extra/neovim");
+        assert_eq!(declared, Package::from("neovim"));
+        assert_ne!(declared, Package::from("testing/neovim"));
+        assert_eq!(declared.to_string(), "extra/neovim");
+    }
+
     #[test]
     fn from() {
         let x = "myrepo/somepackage  #  ".to_string();
         let p = Package::try_from(x).expect("this should be a valid package line");
         assert_eq!(p.name, "somepackage");
         assert_eq!(p.repo, Some("myrepo".to_string()));
+
+        let p = Package::try_from(r#"ripgrep { target = "x86_64-unknown-linux-musl" }"#)
+            .expect("this should be a valid package line");
+        assert_eq!(p.name, "ripgrep");
+        assert_eq!(p.repo, None);
     }
 }
@@ -81,9 +81,7 @@ impl PartialEq for Section {
     }
 }
 
-impl Eq for Section {
-    fn assert_receiver_is_total_eq(&self) {}
-}
+impl Eq for Section {}
 
 impl PartialOrd for Section {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
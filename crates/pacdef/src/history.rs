@@ -0,0 +1,123 @@
+/*!
+Time handling for reading the history of package managers.
+
+All points in time are seconds since the Unix epoch.
+*/
+
+use std::fs::read_to_string;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+/// Get the current time.
+///
+/// # Errors
+///
+/// This function will return an error if the system clock is set before the epoch.
+pub fn now() -> Result<i64> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("system clock is set before the epoch")?;
+    i64::try_from(since_epoch.as_secs()).context("converting current time")
+}
+
+/// Get the time the system was booted from `/proc/stat`.
+///
+/// # Errors
+///
+/// This function will return an error if `/proc/stat` cannot be read or does not
+/// contain the boot time.
+pub fn boot_time() -> Result<i64> {
+    let stat = read_to_string("/proc/stat").context("reading /proc/stat")?;
+
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .context("finding boot time in /proc/stat")?
+        .trim()
+        .parse()
+        .context("parsing boot time")
+}
+
+/// Parse a duration like `30s`, `15m`, `2h` or `7d` into seconds.
+///
+/// # Errors
+///
+/// This function will return an error if the number or the unit are invalid.
+pub fn parse_duration(duration: &str) -> Result<i64> {
+    let split = duration.len() - duration.trim_start_matches(char::is_numeric).len();
+    let (number, unit) = duration.split_at(split);
+
+    let number: i64 = number
+        .parse()
+        .with_context(|| format!("invalid duration '{duration}'"))?;
+
+    let factor = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("invalid unit in duration '{duration}', expected one of s, m, h, d"),
+    };
+
+    Ok(number * factor)
+}
+
+/// Parse a timestamp of the form `2024-04-13T10:15:32+0200`. Returns `None` if the
+/// timestamp has a different form.
+// only the Arch backend has a history
+#[cfg_attr(not(feature = "arch"), allow(dead_code))]
+pub fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let (date, time) = timestamp.split_once('T')?;
+
+    let mut date = date.split('-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+    let offset_start = time.find(['+', '-'])?;
+    let (time, offset) = time.split_at(offset_start);
+
+    let mut time = time.split(':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    let sign = if offset.starts_with('-') { -1 } else { 1 };
+    let offset: i64 = offset.get(1..)?.parse().ok()?;
+    let offset = sign * ((offset / 100) * 3600 + (offset % 100) * 60);
+
+    let days = days_from_civil(year, month, day);
+    Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// The number of days since 1970-01-01 for a date in the proleptic Gregorian
+/// calendar.
+#[cfg_attr(not(feature = "arch"), allow(dead_code))]
+const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00+0000"), Some(0));
+        assert_eq!(
+            parse_timestamp("2024-04-13T10:15:32+0200"),
+            Some(1_712_996_132)
+        );
+        assert_eq!(parse_timestamp("2024-04-13 10:15"), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s").expect("valid duration"), 90);
+        assert_eq!(parse_duration("2h").expect("valid duration"), 7200);
+        assert!(parse_duration("2w").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+}
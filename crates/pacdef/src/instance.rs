@@ -0,0 +1,65 @@
+/*!
+Prevent concurrent runs of pacdef that change packages.
+
+Two installs that run at the same time, e.g. `package sync` from two shells, can
+collide in the package manager. Every action that changes packages therefore takes
+an exclusive lock on a file in the state dir first. The lock is held until pacdef
+exits, and the kernel releases it even if pacdef is killed by a signal. Read-only
+actions do not take the lock.
+*/
+
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+
+use crate::prelude::*;
+
+const LOCK_FILE_NAME: &str = "instance.lock";
+
+/// The locked file. It is never closed, so the lock is held until pacdef exits.
+static LOCK: OnceLock<File> = OnceLock::new();
+
+/// Take the lock for changing packages. Taking it again is a no-op.
+///
+/// # Errors
+///
+/// This function returns [`Error::AnotherInstanceRunning`] if another pacdef holds
+/// the lock, or an error if the lock file cannot be opened.
+pub fn lock_instance() -> Result<()> {
+    if LOCK.get().is_some() {
+        return Ok(());
+    }
+
+    let mut path = get_pacdef_state_dir()?;
+    create_dir_all(&path).context("creating state dir")?;
+    path.push(LOCK_FILE_NAME);
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("opening lock file {}", path.to_string_lossy()))?;
+
+    let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if result != 0 {
+        let mut pid = String::new();
+        let pid = file
+            .read_to_string(&mut pid)
+            .ok()
+            .and_then(|_| pid.trim().parse().ok());
+        return Err(Error::AnotherInstanceRunning(pid).into());
+    }
+
+    file.set_len(0)
+        .and_then(|()| file.rewind())
+        .and_then(|()| write!(file, "{}", std::process::id()))
+        .context("writing pid to lock file")?;
+
+    let _ = LOCK.set(file);
+    Ok(())
+}
@@ -24,20 +24,34 @@ pub(crate) mod backend;
 #[allow(missing_docs)]
 pub mod cli;
 
+#[cfg(test)]
+mod bench;
+mod cache;
 mod cmd;
 mod config;
 #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
 mod core;
 mod env;
 mod errors;
+mod glob;
+mod graph;
 mod grouping;
+mod history;
+mod instance;
+mod lock;
+mod logger;
+mod parallel;
 mod review;
 mod search;
+mod trace;
 mod ui;
+mod update;
 
 #[allow(unused_imports)]
 mod prelude;
 
 pub mod path;
 
-pub use prelude::{Config, Error, Group};
+pub use logger::{init_logger, set_log_format};
+pub use prelude::{Config, Error, Group, Groups};
+pub use ui::{colored_stderr, set_color, Color};
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+/// The exact versions of all managed packages, as written by `pacdef package lock`.
+/// Versions are stored per backend section, keyed by the package as it is written in
+/// the group files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Lockfile {
+    sections: BTreeMap<String, Versions>,
+}
+
+impl Lockfile {
+    /// Load the lock file from disk.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or is malformed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = read_to_string(path)
+            .with_context(|| format!("reading lock file {}", path.to_string_lossy()))?;
+        toml::from_str(&content).context("parsing lock file")
+    }
+
+    /// Save the lock file to disk, replacing any previous content.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = toml::to_string(&self).context("converting lock file to toml")?;
+        write(path, content)
+            .with_context(|| format!("writing lock file {}", path.to_string_lossy()))
+    }
+
+    /// Record the versions of the managed `packages` of a backend. Packages without
+    /// a known version are skipped, and so are backends without any versions.
+    pub fn record(&mut self, section: &str, packages: &Packages, installed: &Versions) {
+        let locked: Versions = packages
+            .iter()
+            .filter_map(|p| Some((p.to_string(), installed.get(&p.name)?.clone())))
+            .collect();
+
+        if !locked.is_empty() {
+            self.sections.insert(section.to_string(), locked);
+        }
+    }
+
+    /// Get the locked version of a package.
+    pub fn version(&self, section: &str, package: &Package) -> Option<&str> {
+        self.sections
+            .get(section)?
+            .get(&package.to_string())
+            .map(String::as_str)
+    }
+
+    /// Get all locked packages of a backend.
+    pub fn packages(&self, section: &str) -> Packages {
+        self.sections
+            .get(section)
+            .into_iter()
+            .flat_map(Versions::keys)
+            .map(|key| Package::from(key.as_str()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_roundtrip() {
+        let packages: Packages = [Package::from("ripgrep"), Package::from("fd")].into();
+        let installed: Versions = [("ripgrep".to_string(), "14.1.0".to_string())].into();
+
+        let mut lockfile = Lockfile::default();
+        lockfile.record("rust", &packages, &installed);
+        lockfile.record("python", &packages, &Versions::new());
+
+        let content = toml::to_string(&lockfile).expect("lock file serializes");
+        let lockfile: Lockfile = toml::from_str(&content).expect("lock file parses");
+
+        assert_eq!(
+            lockfile.version("rust", &Package::from("ripgrep")),
+            Some("14.1.0")
+        );
+        assert_eq!(lockfile.version("rust", &Package::from("fd")), None);
+        assert_eq!(lockfile.version("python", &Package::from("ripgrep")), None);
+    }
+}
@@ -0,0 +1,117 @@
+//! The logger that prints warnings and errors to stderr, either for humans or as
+//! JSON lines.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::kv::{Error as KvError, Key, Value, VisitSource};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::cli::LogFormat;
+use crate::ui::{colored_stderr, Color};
+
+/// Whether records are printed as JSON lines, as chosen by [`set_log_format`].
+static JSON: AtomicBool = AtomicBool::new(false);
+
+struct PacdefLogger;
+
+impl log::Log for PacdefLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if JSON.load(Ordering::Relaxed) {
+            eprintln!("{}", to_json_line(record));
+            return;
+        }
+
+        let level = record.level();
+        let level = match level {
+            log::Level::Error => colored_stderr(level.as_str(), Color::Red),
+            log::Level::Warn => colored_stderr(level.as_str(), Color::Yellow),
+            _ => level.to_string(),
+        };
+        eprintln!("{level} - {}", record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the logger for all records from level `info` on.
+///
+/// # Panics
+///
+/// Panics if another logger was installed before.
+pub fn init_logger() {
+    log::set_boxed_logger(Box::new(PacdefLogger))
+        .map(|()| log::set_max_level(log::LevelFilter::Info))
+        .expect("no other loggers should have been set");
+}
+
+/// Set how records are printed.
+pub fn set_log_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Collects the key-values of a record, e.g. the backend and package a warning
+/// concerns, as JSON strings.
+struct JsonFields<'a>(&'a mut Map<String, JsonValue>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), KvError> {
+        self.0
+            .insert(key.to_string(), JsonValue::String(value.to_string()));
+        Ok(())
+    }
+}
+
+/// Format `record` as a single line of JSON with its level, target, message and
+/// key-values.
+fn to_json_line(record: &log::Record) -> String {
+    let mut fields = Map::new();
+    fields.insert("level".into(), record.level().as_str().into());
+    fields.insert("target".into(), record.target().into());
+    fields.insert("message".into(), record.args().to_string().into());
+
+    // the visitor never fails, so all fields are collected
+    let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+
+    JsonValue::Object(fields).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_line_has_context() {
+        let fields: &[(&str, Value)] = &[
+            ("backend", Value::from("arch")),
+            ("package", Value::from("eza")),
+        ];
+        let record = log::Record::builder()
+            .level(log::Level::Warn)
+            .target("pacdef::backend")
+            .args(format_args!("exa is replaced by eza"))
+            .key_values(&fields)
+            .build();
+
+        let line: JsonValue =
+            serde_json::from_str(&to_json_line(&record)).expect("line is valid JSON");
+
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "level": "WARN",
+                "target": "pacdef::backend",
+                "message": "exa is replaced by eza",
+                "backend": "arch",
+                "package": "eza",
+            })
+        );
+    }
+}
@@ -20,9 +20,12 @@ use std::process::{ExitCode, Termination};
 use anyhow::{bail, Context, Result};
 
 use clap::Parser;
-use pacdef::cli::MainArguments;
-use pacdef::path::{get_config_path, get_config_path_old_version, get_group_dir};
-use pacdef::{Config, Error as PacdefError, Group};
+use pacdef::cli::{MainArguments, MainSubcommand};
+use pacdef::path::{
+    get_config_path_old_version, get_local_group_path, get_lock_file_path, resolve_config_path,
+    resolve_group_dir,
+};
+use pacdef::{init_logger, set_color, set_log_format, Config, Error as PacdefError, Group, Groups};
 
 const MAJOR_UPDATE_MESSAGE: &str = "VERSION UPGRADE
 You seem to have used version 1.x of pacdef before.
@@ -31,26 +34,14 @@ Check out https://github.com/steven-omaha/pacdef/blob/main/README.md#configurati
 This message will not appear again.
 ------";
 
-struct PacdefLogger;
-
-impl log::Log for PacdefLogger {
-    fn enabled(&self, _: &log::Metadata) -> bool {
-        true
-    }
-
-    fn log(&self, record: &log::Record) {
-        if self.enabled(record.metadata()) {
-            eprintln!("{} - {}", record.level(), record.args());
-        }
-    }
-
-    fn flush(&self) {}
-}
+const FIRST_RUN_MESSAGE: &str = "Create your first group with `pacdef group new <name>`,
+fill a new group with all installed packages with `pacdef bootstrap <name>`,
+or import existing group files with `pacdef group import <file>...`.
+Imported groups are symlinks, so the files can stay in your own repository.
+------";
 
 fn main() -> ExitCode {
-    log::set_boxed_logger(Box::new(PacdefLogger))
-        .map(|()| log::set_max_level(log::LevelFilter::Info))
-        .expect("no other loggers should have been set");
+    init_logger();
 
     handle_final_result(main_inner())
 }
@@ -74,10 +65,13 @@ fn handle_final_result(result: Result<()>) -> ExitCode {
 
 fn main_inner() -> Result<()> {
     let main_arguments = MainArguments::parse();
+    set_color(main_arguments.color);
+    set_log_format(main_arguments.log_format);
 
-    let config_file = get_config_path().context("getting config file")?;
+    let config_file = resolve_config_path(main_arguments.config_path.as_deref())
+        .context("getting config file")?;
 
-    let config = match Config::load(&config_file).context("loading config file") {
+    let mut config = match Config::load(&config_file).context("loading config file") {
         Ok(config) => config,
         Err(e) => {
             if let Some(crate_error) = e.downcast_ref::<PacdefError>() {
@@ -86,16 +80,71 @@ fn main_inner() -> Result<()> {
                     _ => bail!("unexpected error: {crate_error}"),
                 }
             } else {
-                bail!("unexpected error: {e:?}");
+                return Err(e);
             }
         }
     };
 
-    let group_dir = get_group_dir().context("resolving group dir")?;
-    let groups = Group::load(&group_dir, config.warn_not_symlinks)
+    config
+        .exclude
+        .extend(main_arguments.exclude.iter().cloned());
+    config.strict_backends |= main_arguments.strict_backends;
+    config.assume_yes.extend(
+        main_arguments
+            .assume_yes_for
+            .iter()
+            .map(|section| (section.clone(), true)),
+    );
+    if let Some(file) = &main_arguments.backend_order_file {
+        config.load_backend_order_file(file)?;
+    }
+    config.ensure_backend_keys()?;
+    config.detect_backends();
+
+    let group_dir =
+        resolve_group_dir(main_arguments.group_dir.as_deref()).context("resolving group dir")?;
+
+    let lock_file = get_lock_file_path(&config_file);
+
+    // validating must work on broken group files, and without creating the group dir
+    if matches!(main_arguments.subcommand, MainSubcommand::Validate(_)) {
+        return main_arguments.run(&Groups::new(), &config, &group_dir, &lock_file);
+    }
+
+    let first_run = !group_dir.exists();
+    if first_run {
+        create_group_dir(&group_dir)?;
+    }
+
+    let mut groups = Group::load(&group_dir, config.warn_not_symlinks)
         .with_context(|| format!("loading groups under {}", group_dir.to_string_lossy()))?;
 
-    if groups.is_empty() {
+    let local_group = get_local_group_path().context("getting local group path")?;
+    if let Some(local) = Group::load_local(&local_group)
+        .with_context(|| format!("loading local group {}", local_group.to_string_lossy()))?
+    {
+        if groups.contains(&local) {
+            bail!(
+                "the group '{}' in the group dir has the name of the machine-local group {}",
+                local.name,
+                local_group.to_string_lossy()
+            );
+        }
+        groups.insert(local);
+    }
+
+    // groups must be manageable while always_groups is wrong, e.g. to create the group
+    if !matches!(
+        main_arguments.subcommand,
+        MainSubcommand::Group(_) | MainSubcommand::Config(_) | MainSubcommand::Fmt(_)
+    ) {
+        check_always_groups(&config, &groups)?;
+    }
+    config.merge_group_options(&groups);
+
+    if first_run {
+        println!("{FIRST_RUN_MESSAGE}");
+    } else if groups.is_empty() {
         log::warn!("no group files found");
     }
 
@@ -108,7 +157,27 @@ fn main_inner() -> Result<()> {
         }
     }
 
-    main_arguments.run(&groups, &config)
+    main_arguments.run(&groups, &config, &group_dir, &lock_file)
+}
+
+/// Make sure that every group in `always_groups` exists, so that its packages are
+/// not silently left out.
+fn check_always_groups(config: &Config, groups: &Groups) -> Result<()> {
+    let missing: Vec<&str> = config
+        .always_groups
+        .iter()
+        .filter(|name| !groups.iter().any(|group| group.name == **name))
+        .map(String::as_str)
+        .collect();
+
+    if !missing.is_empty() {
+        bail!(
+            "always_groups in the config lists groups that do not exist: {}",
+            missing.join(", ")
+        );
+    }
+
+    Ok(())
 }
 
 fn load_default_config(config_file: &Path) -> Result<Config> {
@@ -123,6 +192,22 @@ fn load_default_config(config_file: &Path) -> Result<Config> {
     Ok(Config::default())
 }
 
+/// Create the group dir on first run. A dangling symlink is most likely a group
+/// repository that is not checked out, so it is not replaced by an empty dir.
+fn create_group_dir(group_dir: &Path) -> Result<()> {
+    if group_dir.is_symlink() {
+        bail!(
+            "group dir {} is a symlink to a path that does not exist",
+            group_dir.to_string_lossy()
+        );
+    }
+
+    std::fs::create_dir_all(group_dir)
+        .with_context(|| format!("creating group dir {}", group_dir.to_string_lossy()))?;
+    println!("created group dir {}", group_dir.to_string_lossy());
+    Ok(())
+}
+
 fn create_empty_config_file(config_file: &Path) -> Result<()> {
     let config_dir = &config_file.parent().context("getting parent dir")?;
     std::fs::create_dir_all(config_dir).context("creating parent dir")?;
@@ -0,0 +1,113 @@
+/*!
+Query several backends at the same time.
+
+Every backend is queried on its own thread, but at most [`set_jobs`] backends at once.
+Nothing is printed while the queries run. The results are returned in a stable
+order, so all output derived from them stays readable and attributed to the correct
+backend. Interactive backends are queried one after another on the calling thread,
+after all other backends, so that they can always use the terminal.
+*/
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::Result;
+
+use crate::cmd::with_timeout_of;
+use crate::prelude::*;
+
+/// Maximum number of backends that are queried at the same time. `0` means one per
+/// available CPU.
+static JOBS: AtomicUsize = AtomicUsize::new(0);
+
+/// Limit the number of backends that are queried at the same time. If `jobs` is
+/// `None`, one backend per available CPU is queried.
+pub fn set_jobs(jobs: Option<NonZeroUsize>) {
+    JOBS.store(jobs.map_or(0, NonZeroUsize::get), Ordering::Relaxed);
+}
+
+fn get_jobs() -> usize {
+    match JOBS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        jobs => jobs,
+    }
+}
+
+/// Call `query` for every backend and its associated `data`, with up to the
+/// configured number of backends at once. The results are returned sorted
+/// alphabetically by backend section, independent of the order in which the
+/// queries finish.
+pub fn query_backends<D, T, F>(
+    items: Vec<(AnyBackend, D)>,
+    config: &Config,
+    query: F,
+) -> Vec<(AnyBackend, Result<T>)>
+where
+    D: Sync,
+    T: Send,
+    F: Fn(&AnyBackend, &D) -> Result<T> + Sync,
+{
+    let (interactive, items): (Vec<_>, Vec<_>) = items
+        .into_iter()
+        .partition(|(backend, _)| config.is_interactive(backend));
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    let workers = get_jobs().min(items.len());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some((backend, data)) = items.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = with_timeout_of(backend, config, || query(backend, data));
+                    results
+                        .lock()
+                        .expect("no query panics while holding the lock")
+                        .push((backend.clone(), result));
+                }
+            });
+        }
+    });
+
+    let mut results = results
+        .into_inner()
+        .expect("no query panics while holding the lock");
+
+    for (backend, data) in interactive {
+        let result = with_timeout_of(&backend, config, || query(&backend, &data));
+        results.push((backend, result));
+    }
+
+    results.sort_by(|(a, _), (b, _)| a.backend_info().section.cmp(b.backend_info().section));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::{query_backends, set_jobs};
+    use crate::prelude::*;
+
+    #[test]
+    fn results_are_sorted_by_section() {
+        let config = Config::default();
+        let mut items: Vec<_> = AnyBackend::all(&config).map(|b| (b, ())).collect();
+        items.reverse();
+
+        set_jobs(NonZeroUsize::new(2));
+        let results = query_backends(items, &config, |backend, ()| {
+            Ok(backend.backend_info().section)
+        });
+
+        let sections: Vec<_> = results
+            .into_iter()
+            .map(|(_, result)| result.expect("query does not fail"))
+            .collect();
+        let mut sorted = sections.clone();
+        sorted.sort_unstable();
+        assert_eq!(sections, sorted);
+    }
+}
@@ -2,14 +2,27 @@
 All functions related to `pacdef`'s internal paths.
 */
 
+use std::collections::BTreeSet;
+use std::ffi::{CString, OsString};
+use std::fs::remove_dir_all;
+use std::io::Error as IoError;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::PathBuf;
+use std::sync::Mutex;
 use std::{env, path::Path};
 
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use path_absolutize::Absolutize;
 
 const CONFIG_FILE_NAME: &str = "pacdef.toml";
 const CONFIG_FILE_NAME_OLD: &str = "pacdef.yaml";
+const LOCK_FILE_NAME: &str = "pacdef.lock";
+
+/// The binaries that were found by [`binary_in_path`] in this run. Binaries like the
+/// AUR helper are looked up for every backend query and command, so they are only
+/// searched for once. Missing binaries are not remembered, as an earlier backend of a
+/// sync may install them.
+static FOUND_BINARIES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
 
 /// Get the group directory where all group files are located. This is
 /// `$XDG_CONFIG_HOME/pacdef/groups`, which defaults to `$HOME/.config/pacdef/groups`.
@@ -23,6 +36,22 @@ pub fn get_group_dir() -> Result<PathBuf> {
     Ok(result)
 }
 
+/// Get the group directory, which is `override_dir` if provided, or the result of
+/// [`get_group_dir`] otherwise.
+///
+/// # Errors
+///
+/// This function returns an error if no override is provided and both
+/// `$XDG_CONFIG_HOME` and `$HOME` are undefined.
+pub fn resolve_group_dir(override_dir: Option<&Path>) -> Result<PathBuf> {
+    override_dir.map_or_else(get_group_dir, |dir| Ok(dir.to_path_buf()))
+}
+
+/// Get the path to the lock file, which is located next to the config file.
+pub fn get_lock_file_path(config_file: &Path) -> PathBuf {
+    config_file.with_file_name(LOCK_FILE_NAME)
+}
+
 /// Get the base directory for `pacdef`'s config files.
 ///
 /// # Errors
@@ -34,6 +63,51 @@ pub fn get_pacdef_base_dir() -> Result<PathBuf> {
     Ok(dir)
 }
 
+/// Get the directory for `pacdef`'s machine-local state files, which should not be
+/// shared between machines. This is `$XDG_STATE_HOME/pacdef`, which defaults to
+/// `$HOME/.local/state/pacdef`.
+///
+/// # Errors
+///
+/// This function will return an error if `$XDG_STATE_HOME` cannot be determined.
+pub fn get_pacdef_state_dir() -> Result<PathBuf> {
+    let mut dir = get_xdg_state_home().context("getting XDG_STATE_HOME")?;
+    dir.push("pacdef");
+    Ok(dir)
+}
+
+/// Get the path of the machine-local group file, `$XDG_STATE_HOME/pacdef/local`,
+/// which is loaded in addition to the group dir.
+///
+/// # Errors
+///
+/// This function will return an error if `$XDG_STATE_HOME` cannot be determined.
+pub fn get_local_group_path() -> Result<PathBuf> {
+    let mut path = get_pacdef_state_dir()?;
+    path.push("local");
+    Ok(path)
+}
+
+/// Get the path to the XDG cache directory, which defaults to `$HOME/.cache`.
+///
+/// # Errors
+///
+/// This function will return an error if neither the `$XDG_CACHE_HOME` nor
+/// the `$HOME` environment variables are set.
+pub fn get_xdg_cache_home() -> Result<PathBuf> {
+    get_xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Get the path to the XDG data directory, which defaults to `$HOME/.local/share`.
+///
+/// # Errors
+///
+/// This function will return an error if neither the `$XDG_DATA_HOME` nor
+/// the `$HOME` environment variables are set.
+pub fn get_xdg_data_home() -> Result<PathBuf> {
+    get_xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
 /// Get the path to the cargo home directory.
 ///
 /// # Errors
@@ -57,12 +131,36 @@ pub fn get_cargo_home() -> Result<PathBuf> {
 /// This function will return an error if neither the `$XDG_CONFIG_HOME` nor
 /// the `$HOME` environment variables are set.
 fn get_xdg_config_home() -> Result<PathBuf> {
-    if let Ok(config) = env::var("XDG_CONFIG_HOME") {
-        Ok(config.into())
-    } else {
-        let mut config = get_home_dir().context("falling back to $HOME/.config")?;
-        config.push(".config");
-        Ok(config)
+    get_xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Get the path to the XDG state directory.
+///
+/// # Errors
+///
+/// This function will return an error if neither the `$XDG_STATE_HOME` nor
+/// the `$HOME` environment variables are set.
+fn get_xdg_state_home() -> Result<PathBuf> {
+    get_xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// Get the XDG base directory from the environment variable `var`, or fall back to
+/// `fallback` relative to `$HOME`. As required by the XDG base directory
+/// specification, empty and relative values of `var` are ignored.
+///
+/// # Errors
+///
+/// This function will return an error if `var` is not usable and `$HOME` is not
+/// set.
+fn get_xdg_dir(var: &str, fallback: &str) -> Result<PathBuf> {
+    match env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Ok(dir),
+        _ => {
+            let mut dir =
+                get_home_dir().with_context(|| format!("falling back to $HOME/{fallback}"))?;
+            dir.push(fallback);
+            Ok(dir)
+        }
     }
 }
 
@@ -88,6 +186,17 @@ pub fn get_config_path() -> Result<PathBuf> {
     Ok(file)
 }
 
+/// Get the path to the pacdef config file, which is `override_path` if provided, or
+/// the result of [`get_config_path`] otherwise.
+///
+/// # Errors
+///
+/// This function returns an error if no override is provided and both
+/// `$XDG_CONFIG_HOME` and `$HOME` are undefined.
+pub fn resolve_config_path(override_path: Option<&Path>) -> Result<PathBuf> {
+    override_path.map_or_else(get_config_path, |path| Ok(path.to_path_buf()))
+}
+
 /// Get the path to the pacdef config file from version 0.x. This is
 /// `$XDG_CONFIG_HOME/pacdef/pacdef.conf`.
 ///
@@ -101,12 +210,75 @@ pub fn get_config_path_old_version() -> Result<PathBuf> {
     Ok(file)
 }
 
-/// Determine if a program `name` exists in the folders in the `$PATH` variable.
+/// A directory for temporary files that only the current user can access. It is
+/// removed together with its content when dropped.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a directory with a unique name that starts with `prefix` in the
+    /// temporary directory of the system. Unlike a predictable path, it cannot be
+    /// prepared by other users, e.g. with a symlink.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the directory cannot be created.
+    pub fn new(prefix: &str) -> Result<Self> {
+        let template = env::temp_dir().join(format!("{prefix}-XXXXXX"));
+        let mut template = CString::new(template.as_os_str().as_bytes())
+            .context("temporary directory contains a NUL byte")?
+            .into_bytes_with_nul();
+
+        let created = unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) };
+        ensure!(
+            !created.is_null(),
+            "creating temporary directory: {}",
+            IoError::last_os_error()
+        );
+
+        template.pop();
+        Ok(Self(OsString::from_vec(template).into()))
+    }
+
+    /// The path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(error) = remove_dir_all(&self.0) {
+            log::warn!(
+                "cannot remove temporary directory {}: {error}",
+                self.0.to_string_lossy()
+            );
+        }
+    }
+}
+
+/// Determine if a program `name` exists in the folders in the `$PATH` variable. Once
+/// a program was found, it is not searched for again in this run.
 ///
 /// # Errors
 ///
 /// This function returns an error if `$PATH` is not set.
 pub fn binary_in_path(name: &str) -> Result<bool> {
+    let mut found = FOUND_BINARIES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if found.contains(name) {
+        return Ok(true);
+    }
+
+    let in_path = search_path(name)?;
+    if in_path {
+        found.insert(name.to_string());
+    }
+    Ok(in_path)
+}
+
+fn search_path(name: &str) -> Result<bool> {
     let paths = env::var_os("PATH").context("getting $PATH")?;
     for dir in env::split_paths(&paths) {
         let full_path = dir.join(name);
@@ -159,9 +331,14 @@ pub fn get_absolutized_file_paths(arg_match: &[String]) -> Result<Vec<PathBuf>>
 
 #[cfg(test)]
 mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, remove_file, write};
     use std::path::PathBuf;
 
-    use super::get_relative_path;
+    use super::{
+        binary_in_path, get_config_path, get_config_path_old_version, get_group_dir,
+        get_pacdef_state_dir, get_relative_path, resolve_config_path, resolve_group_dir,
+    };
+    use crate::prelude::*;
 
     #[test]
     fn relative_path() {
@@ -178,4 +355,81 @@ mod tests {
         let base = PathBuf::from("/a/b/c");
         get_relative_path(full, base);
     }
+
+    #[test]
+    fn binary_is_searched_once() {
+        let dir = std::env::temp_dir().join(format!("pacdef-binary-test-{}", std::process::id()));
+        create_dir_all(&dir).expect("temp dir is writable");
+        let helper = dir.join("paru");
+        let name = helper.to_string_lossy();
+
+        assert!(!binary_in_path(&name).expect("PATH is set"));
+        write(&helper, "").expect("temp dir is writable");
+        assert!(binary_in_path(&name).expect("PATH is set"));
+
+        // not searched for again, so it is still found
+        remove_file(&helper).expect("file was written");
+        assert!(binary_in_path(&name).expect("PATH is set"));
+
+        remove_dir_all(dir).expect("temp dir is removable");
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let config = PathBuf::from("/some/where/pacdef.toml");
+        let groups = PathBuf::from("/some/where/groups");
+
+        let resolved = resolve_config_path(Some(&config)).expect("override is used as is");
+        assert_eq!(resolved, config);
+        let resolved = resolve_group_dir(Some(&groups)).expect("override is used as is");
+        assert_eq!(resolved, groups);
+    }
+
+    #[test]
+    fn group_dir_override_loads_fixture() {
+        let base = std::env::temp_dir().join(format!("pacdef-test-{}", std::process::id()));
+        let fixture = base.join("groups");
+        create_dir_all(&fixture).expect("temp dir is writable");
+        write(fixture.join("base"), "[rust]\nripgrep\n").expect("temp dir is writable");
+
+        let config_path =
+            resolve_config_path(Some(&base.join("pacdef.toml"))).expect("override is used as is");
+        let config = Config::load(&config_path);
+        assert!(config.is_err(), "fixture has no config file");
+
+        let group_dir = resolve_group_dir(Some(&fixture)).expect("override is used as is");
+        let groups = Group::load(&group_dir, false).expect("fixture is valid");
+        remove_dir_all(&base).expect("temp dir is writable");
+
+        let group = groups.first().expect("fixture contains one group");
+        assert_eq!(group.name, "base");
+        assert_eq!(group.path, fixture.join("base"));
+    }
+
+    #[test]
+    fn xdg_base_dirs() {
+        let base = std::env::temp_dir().join(format!("pacdef-xdg-{}", std::process::id()));
+        let config_home = base.join("config");
+        let state_home = base.join("state");
+
+        std::env::set_var("XDG_CONFIG_HOME", &config_home);
+        std::env::set_var("XDG_STATE_HOME", &state_home);
+        let config = get_config_path().expect("XDG_CONFIG_HOME is set");
+        let old_config = get_config_path_old_version().expect("XDG_CONFIG_HOME is set");
+        let groups = get_group_dir().expect("XDG_CONFIG_HOME is set");
+        let state = get_pacdef_state_dir().expect("XDG_STATE_HOME is set");
+
+        std::env::set_var("HOME", &base);
+        std::env::set_var("XDG_CONFIG_HOME", "");
+        std::env::set_var("XDG_STATE_HOME", "relative");
+        let fallback_config = get_config_path().expect("HOME is set");
+        let fallback_state = get_pacdef_state_dir().expect("HOME is set");
+
+        assert_eq!(config, config_home.join("pacdef/pacdef.toml"));
+        assert_eq!(old_config, config_home.join("pacdef/pacdef.yaml"));
+        assert_eq!(groups, config_home.join("pacdef/groups"));
+        assert_eq!(state, state_home.join("pacdef"));
+        assert_eq!(fallback_config, base.join(".config/pacdef/pacdef.toml"));
+        assert_eq!(fallback_state, base.join(".local/state/pacdef"));
+    }
 }
@@ -3,30 +3,54 @@ pub use crate::backend::actual::arch::Arch;
 #[cfg(feature = "debian")]
 pub use crate::backend::actual::debian::Debian;
 pub use crate::backend::actual::{
-    fedora::Fedora, flatpak::Flatpak, python::Python, rust::Rust, rustup::Rustup, void::Void,
+    asdf::Asdf, cabal::Cabal, container::Container, cpanm::Cpanm, emacs::Emacs, external::External,
+    fedora::Fedora, flatpak::Flatpak, gnome::GnomeExtensions, home_manager::HomeManager,
+    mise::Mise, python::Python, rust::Rust, rustup::Rustup, tpm::Tpm, vim::VimPlugins, void::Void,
+    zig::Zig,
+};
+pub use crate::backend::backend_trait::{
+    Backend, BackendInfo, HistoryAction, HistoryEntry, InstallOptions, Switches, Text, VerifyIssue,
+    Versions,
 };
-pub use crate::backend::backend_trait::{Backend, BackendInfo, Switches, Text};
 pub use crate::backend::todo_per_backend::ToDoPerBackend;
 pub use crate::backend::AnyBackend;
 pub use crate::backend::ManagedBackend;
+pub use crate::cli::BootstrapArguments;
+pub use crate::cli::CleanCachePackageAction;
 pub use crate::cli::CleanPackageAction;
+pub use crate::cli::ConfigArguments;
+pub use crate::cli::DepsArguments;
 pub use crate::cli::EditGroupAction;
 pub use crate::cli::ExportGroupAction;
+pub use crate::cli::FmtArguments;
+pub use crate::cli::GraphFormat;
+pub use crate::cli::GraphGroupAction;
 pub use crate::cli::GroupAction;
 pub use crate::cli::GroupArguments;
+pub use crate::cli::GroupBy;
 pub use crate::cli::ImportGroupAction;
 pub use crate::cli::ListGroupAction;
+pub use crate::cli::LockPackageAction;
+pub use crate::cli::LogArguments;
 pub use crate::cli::MainArguments;
 pub use crate::cli::MainSubcommand;
 pub use crate::cli::NewGroupAction;
+pub use crate::cli::OrphansPackageAction;
 pub use crate::cli::PackageAction;
 pub use crate::cli::PackageArguments;
+pub use crate::cli::PrefetchPackageAction;
+pub use crate::cli::PruneGroupAction;
 pub use crate::cli::RemoveGroupAction;
+pub use crate::cli::RepairArguments;
+pub use crate::cli::RestoreArguments;
+pub use crate::cli::ReviewOutput;
 pub use crate::cli::ReviewPackageAction;
 pub use crate::cli::SearchPackageAction;
 pub use crate::cli::ShowGroupAction;
 pub use crate::cli::SyncPackageAction;
 pub use crate::cli::UnmanagedPackageAction;
+pub use crate::cli::ValidateArguments;
+pub use crate::cli::VerifyArguments;
 pub use crate::cli::VersionArguments;
 pub use crate::config::Config;
 pub use crate::errors::Error;
@@ -42,5 +66,9 @@ pub use crate::path::get_config_path;
 pub use crate::path::get_config_path_old_version;
 pub use crate::path::get_group_dir;
 pub use crate::path::get_home_dir;
+pub use crate::path::get_lock_file_path;
 pub use crate::path::get_pacdef_base_dir;
+pub use crate::path::get_pacdef_state_dir;
 pub use crate::path::get_relative_path;
+pub use crate::path::resolve_config_path;
+pub use crate::path::resolve_group_dir;
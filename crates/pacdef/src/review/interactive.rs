@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::{stdin, stdout, Write};
+
+use anyhow::Result;
+
+use crate::instance::lock_instance;
+use crate::prelude::*;
+use crate::ui::{get_user_confirmation, read_single_char_from_terminal, Color, Table};
+
+use super::datastructures::{ReviewAction, ReviewsPerBackend};
+use super::strategy::Strategy;
+use super::{
+    ask_group, group_of, in_sync_summary, options_summary, review_table, show_changes_by_group,
+    Change, UNMANAGED_GROUP,
+};
+
+/// Whether a package is declared in a group but not installed, or installed but not
+/// declared in any group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Missing,
+    Unmanaged,
+}
+
+/// What the user wants to do with a package. `Install` is only valid for missing
+/// packages, `Delete` and `AssignGroup` only for unmanaged ones.
+#[derive(Debug)]
+enum Selection {
+    Skip,
+    Install,
+    Delete,
+    AssignGroup(Group),
+}
+
+impl Display for Selection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Skip => write!(f, "skip"),
+            Self::Install => write!(f, "install"),
+            Self::Delete => write!(f, "delete"),
+            Self::AssignGroup(group) => write!(f, "assign to {}", group.name),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Item {
+    backend: AnyBackend,
+    package: Package,
+    /// The group the package is listed under with `--group-by group`.
+    group: String,
+    kind: Kind,
+    selection: Selection,
+}
+
+enum Command {
+    Select(usize),
+    Apply,
+    Quit,
+    Invalid,
+}
+
+/// Let the user pick what to do with every missing and unmanaged package from a
+/// list, and apply all selections at once afterwards. Nothing is changed unless the
+/// user applies the selection and confirms the summary.
+///
+/// # Errors
+///
+/// This function will return an error if the terminal cannot be accessed, or if
+/// any of the backends fails to apply the selection.
+pub fn review_interactive(
+    missing: ToDoPerBackend,
+    unmanaged: ToDoPerBackend,
+    groups: &Groups,
+    config: &Config,
+    output: ReviewOutput,
+    group_by: GroupBy,
+    force: bool,
+) -> Result<()> {
+    let in_sync = missing
+        .iter()
+        .filter(|(backend, packages)| {
+            packages.is_empty()
+                && unmanaged
+                    .iter()
+                    .all(|(other, unmanaged)| other != backend || unmanaged.is_empty())
+        })
+        .count();
+    let mut items = collect_items(missing, unmanaged, groups);
+    if group_by == GroupBy::Group {
+        items.sort_by(|a, b| a.group.cmp(&b.group));
+    }
+
+    if items.is_empty() {
+        println!("nothing to do");
+        return Ok(());
+    }
+
+    if let Some(summary) = in_sync_summary(in_sync) {
+        println!("{summary}\n");
+    }
+
+    loop {
+        if output == ReviewOutput::Table {
+            print_items_table(&items, groups, group_by);
+        } else {
+            print_items(&items, group_by);
+        }
+
+        match ask_command()? {
+            Command::Select(idx) => {
+                if let Some(item) = items.get_mut(idx) {
+                    change_selection(item, groups)?;
+                }
+            }
+            Command::Apply => break,
+            Command::Quit => return Ok(()),
+            Command::Invalid => (),
+        }
+
+        println!();
+    }
+
+    apply(items, groups, config, output, group_by, force)
+}
+
+fn collect_items(missing: ToDoPerBackend, unmanaged: ToDoPerBackend, groups: &Groups) -> Vec<Item> {
+    let mut result = vec![];
+
+    for (kind, todo) in [(Kind::Missing, missing), (Kind::Unmanaged, unmanaged)] {
+        for (backend, packages) in todo {
+            for package in packages {
+                let group = match kind {
+                    Kind::Missing => group_of(groups, backend.backend_info().section, &package),
+                    Kind::Unmanaged => UNMANAGED_GROUP.to_string(),
+                };
+                result.push(Item {
+                    backend: backend.clone(),
+                    package,
+                    group,
+                    kind,
+                    selection: Selection::Skip,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+/// Print the numbered `items`, with a header before the items of each group if
+/// they are grouped by group.
+fn print_items(items: &[Item], group_by: GroupBy) {
+    let number_digits = items.len().to_string().len();
+
+    for (i, item) in items.iter().enumerate() {
+        let first_of_group = i == 0 || items[i - 1].group != item.group;
+        if group_by == GroupBy::Group && first_of_group {
+            println!("[{}]", item.group);
+        }
+
+        let kind = match item.kind {
+            Kind::Missing => "missing",
+            Kind::Unmanaged => "unmanaged",
+        };
+
+        println!(
+            "{i:>number_digits$}: {kind:<9} {}: {} -> {}",
+            item.backend.backend_info().section,
+            item.package,
+            item.selection
+        );
+    }
+}
+
+fn print_items_table(items: &[Item], groups: &Groups, group_by: GroupBy) {
+    let mut header = vec!["#", "backend", "package", "status", "selection", "options"];
+    if group_by == GroupBy::Group {
+        header.insert(1, "group");
+    }
+    let mut table = Table::new(&header);
+
+    for (i, item) in items.iter().enumerate() {
+        let section = item.backend.backend_info().section;
+        let kind = match item.kind {
+            Kind::Missing => "missing",
+            Kind::Unmanaged => "unmanaged",
+        };
+        let color = match item.selection {
+            Selection::Skip => None,
+            Selection::Install | Selection::AssignGroup(_) => Some(Color::Green),
+            Selection::Delete => Some(Color::Red),
+        };
+
+        let mut row = vec![
+            (i.to_string(), None),
+            (section.to_string(), None),
+            (item.package.to_string(), None),
+            (kind.to_string(), None),
+            (item.selection.to_string(), color),
+            (options_summary(groups, section, &item.package), None),
+        ];
+        if group_by == GroupBy::Group {
+            row.insert(1, (item.group.clone(), None));
+        }
+        table.push(row);
+    }
+
+    table.print();
+}
+
+fn ask_command() -> Result<Command> {
+    print!("number to change, (a)pply, (q)uit? ");
+    stdout().lock().flush()?;
+
+    let mut buf = String::new();
+    stdin().read_line(&mut buf)?;
+    let reply = buf.trim().to_ascii_lowercase();
+
+    let command = match reply.as_str() {
+        "a" => Command::Apply,
+        "q" => Command::Quit,
+        _ => reply.parse().map_or(Command::Invalid, Command::Select),
+    };
+
+    Ok(command)
+}
+
+/// Missing packages toggle between install and skip. For unmanaged packages the user
+/// is asked for the desired action.
+fn change_selection(item: &mut Item, groups: &Groups) -> Result<()> {
+    if item.kind == Kind::Missing {
+        item.selection = match item.selection {
+            Selection::Install => Selection::Skip,
+            _ => Selection::Install,
+        };
+        return Ok(());
+    }
+
+    print!("{}: assign to (g)roup, (d)elete, (s)kip? ", item.package);
+    stdout().lock().flush()?;
+
+    match read_single_char_from_terminal()?.to_ascii_lowercase() {
+        'g' => {
+            if let Ok(Some(group)) = ask_group(groups) {
+                item.selection = Selection::AssignGroup(group.clone());
+            }
+        }
+        'd' => item.selection = Selection::Delete,
+        's' => item.selection = Selection::Skip,
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn apply(
+    items: Vec<Item>,
+    groups: &Groups,
+    config: &Config,
+    output: ReviewOutput,
+    group_by: GroupBy,
+    force: bool,
+) -> Result<()> {
+    let mut to_install: BTreeMap<AnyBackend, Packages> = BTreeMap::new();
+    let mut actions: BTreeMap<AnyBackend, Vec<ReviewAction>> = BTreeMap::new();
+
+    for item in items {
+        let action = match item.selection {
+            Selection::Skip => continue,
+            Selection::Install => {
+                to_install
+                    .entry(item.backend)
+                    .or_default()
+                    .insert(item.package);
+                continue;
+            }
+            Selection::Delete => ReviewAction::Delete(item.package),
+            Selection::AssignGroup(group) => ReviewAction::AssignGroup(item.package, group),
+        };
+
+        actions.entry(item.backend).or_default().push(action);
+    }
+
+    let mut install = ToDoPerBackend::new();
+    for item in to_install {
+        install.push(item);
+    }
+
+    let mut reviews = ReviewsPerBackend::new();
+    for item in actions {
+        reviews.push(item);
+    }
+
+    if install.nothing_to_do_for_all_backends() && reviews.nothing_to_do() {
+        println!("nothing to do");
+        return Ok(());
+    }
+
+    let strategies = reviews.into_strategies();
+
+    if group_by == GroupBy::Group {
+        show_changes_grouped(&install, &strategies, groups, output);
+    } else {
+        show_changes(&install, &strategies, groups, output);
+    }
+
+    config.check_max_removals(strategies.iter().map(Strategy::removal_count).sum(), force)?;
+
+    if !get_user_confirmation()? {
+        return Ok(());
+    }
+
+    lock_instance()?;
+
+    install.install_missing_packages(false, config)?;
+
+    for strategy in strategies {
+        strategy.execute(config)?;
+    }
+
+    Ok(())
+}
+
+/// Show the packages to install and the actions of the `strategies`.
+fn show_changes(
+    install: &ToDoPerBackend,
+    strategies: &[Strategy],
+    groups: &Groups,
+    output: ReviewOutput,
+) {
+    if output == ReviewOutput::Terse {
+        show_changes_terse(install, strategies);
+    } else if output == ReviewOutput::Table {
+        let mut table = review_table();
+        for (backend, packages) in install.iter() {
+            let section = backend.backend_info().section;
+            for package in packages {
+                table.push(vec![
+                    (section.to_string(), None),
+                    (package.to_string(), None),
+                    ("install".to_string(), Some(Color::Green)),
+                    (options_summary(groups, section, package), None),
+                ]);
+            }
+        }
+        for strategy in strategies {
+            strategy.add_rows(&mut table, groups);
+        }
+        table.print();
+        println!();
+    } else {
+        if !install.nothing_to_do_for_all_backends() {
+            println!("Would install the following packages:\n");
+            install.show_changes('+', Color::Green);
+            println!();
+        }
+
+        for strategy in strategies {
+            strategy.show();
+            println!();
+        }
+    }
+}
+
+/// Show one line per package to install and per action of the `strategies`.
+fn show_changes_terse(install: &ToDoPerBackend, strategies: &[Strategy]) {
+    for (backend, packages) in install.iter() {
+        let section = backend.backend_info().section;
+        for package in packages {
+            println!("[{section}] {package}: install");
+        }
+    }
+    for strategy in strategies {
+        strategy.show_terse();
+    }
+    println!();
+}
+
+/// Show the packages to install and the actions of the `strategies` under the group
+/// of each package.
+fn show_changes_grouped(
+    install: &ToDoPerBackend,
+    strategies: &[Strategy],
+    groups: &Groups,
+    output: ReviewOutput,
+) {
+    let mut changes = vec![];
+    for (backend, packages) in install.iter() {
+        let section = backend.backend_info().section;
+        changes.extend(packages.iter().map(|package| Change {
+            group: group_of(groups, section, package),
+            section,
+            package,
+            status: "install".to_string(),
+            color: Color::Green,
+        }));
+    }
+    changes.extend(strategies.iter().flat_map(Strategy::changes));
+
+    show_changes_by_group(changes, groups, output);
+    println!();
+}
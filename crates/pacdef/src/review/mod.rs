@@ -1,17 +1,43 @@
 mod datastructures;
+mod interactive;
 mod strategy;
 
 use std::io::{stdin, stdout, Write};
 
 use anyhow::Result;
 
+use crate::grouping::group::declaring_groups;
+use crate::instance::lock_instance;
 use crate::prelude::*;
-use crate::ui::{get_user_confirmation, read_single_char_from_terminal};
+use crate::ui::{colored, get_user_confirmation, read_single_char_from_terminal, Color, Table};
 
 use self::datastructures::{ContinueWithReview, ReviewAction, ReviewIntention, ReviewsPerBackend};
 use self::strategy::Strategy;
 
-pub fn review(todo_per_backend: ToDoPerBackend, groups: &Groups) -> Result<()> {
+pub use self::interactive::review_interactive;
+
+/// The pseudo-group that packages are listed under with `--group-by group` if no
+/// group declares them.
+const UNMANAGED_GROUP: &str = "unmanaged";
+
+/// A change to a single package, as shown in a review.
+struct Change<'a> {
+    /// The group the package is listed under with `--group-by group`.
+    group: String,
+    section: &'a str,
+    package: &'a Package,
+    status: String,
+    color: Color,
+}
+
+pub fn review(
+    todo_per_backend: ToDoPerBackend,
+    groups: &Groups,
+    config: &Config,
+    output: ReviewOutput,
+    group_by: GroupBy,
+    force: bool,
+) -> Result<()> {
     let mut reviews = ReviewsPerBackend::new();
 
     if todo_per_backend.nothing_to_do_for_all_backends() {
@@ -19,6 +45,11 @@ pub fn review(todo_per_backend: ToDoPerBackend, groups: &Groups) -> Result<()> {
         return Ok(());
     }
 
+    let in_sync = todo_per_backend
+        .iter()
+        .filter(|(_, packages)| packages.is_empty())
+        .count();
+
     'outer: for (backend, packages) in todo_per_backend {
         let mut actions = vec![];
         for package in packages {
@@ -43,28 +74,167 @@ pub fn review(todo_per_backend: ToDoPerBackend, groups: &Groups) -> Result<()> {
     let strategies: Vec<Strategy> = reviews.into_strategies();
 
     println!();
-    let mut iter = strategies.iter().peekable();
-
-    while let Some(strategy) = iter.next() {
-        strategy.show();
+    if group_by == GroupBy::Group {
+        let changes = strategies.iter().flat_map(Strategy::changes).collect();
+        show_changes_by_group(changes, groups, output);
+    } else {
+        show_strategies(&strategies, groups, output);
+    }
 
-        if iter.peek().is_some() {
-            println!();
-        }
+    if let Some(summary) = in_sync_summary(in_sync) {
+        println!("\n{summary}");
     }
 
+    config.check_max_removals(strategies.iter().map(Strategy::removal_count).sum(), force)?;
+
     println!();
     if !get_user_confirmation()? {
         return Ok(());
     }
 
+    lock_instance()?;
+
     for strategy in strategies {
-        strategy.execute()?;
+        strategy.execute(config)?;
     }
 
     Ok(())
 }
 
+/// Show what will be done with the packages of the `strategies`.
+fn show_strategies(strategies: &[Strategy], groups: &Groups, output: ReviewOutput) {
+    match output {
+        ReviewOutput::Table => {
+            let mut table = review_table();
+            for strategy in strategies {
+                strategy.add_rows(&mut table, groups);
+            }
+            table.print();
+        }
+        ReviewOutput::Terse => {
+            for strategy in strategies {
+                strategy.show_terse();
+            }
+        }
+        ReviewOutput::Plain => {
+            let mut iter = strategies.iter().peekable();
+
+            while let Some(strategy) = iter.next() {
+                strategy.show();
+
+                if iter.peek().is_some() {
+                    println!();
+                }
+            }
+        }
+    }
+}
+
+/// Show the `changes` under the group of each package, in the order of the groups.
+fn show_changes_by_group(mut changes: Vec<Change>, groups: &Groups, output: ReviewOutput) {
+    changes.sort_by(|a, b| a.group.cmp(&b.group));
+
+    match output {
+        ReviewOutput::Table => {
+            let mut table = Table::new(&["group", "backend", "package", "status", "options"]);
+            for change in changes {
+                table.push(vec![
+                    (change.group, None),
+                    (change.section.to_string(), None),
+                    (change.package.to_string(), None),
+                    (change.status, Some(change.color)),
+                    (
+                        options_summary(groups, change.section, change.package),
+                        None,
+                    ),
+                ]);
+            }
+            table.print();
+        }
+        ReviewOutput::Terse => {
+            for change in changes {
+                println!(
+                    "[{}] {} ({}): {}",
+                    change.group, change.package, change.section, change.status
+                );
+            }
+        }
+        ReviewOutput::Plain => {
+            let mut previous: Option<String> = None;
+
+            for change in changes {
+                if previous.as_ref() != Some(&change.group) {
+                    if previous.is_some() {
+                        println!();
+                    }
+                    println!("[{}]", change.group);
+                }
+
+                println!(
+                    "  {} ({}): {}",
+                    change.package,
+                    change.section,
+                    colored(&change.status, change.color)
+                );
+                previous = Some(change.group);
+            }
+        }
+    }
+}
+
+/// The group to list a package that is declared for the backend with the given
+/// `section` under: all groups that declare it, or else the unmanaged pseudo-group.
+fn group_of(groups: &Groups, section: &str, package: &Package) -> String {
+    let declaring = declaring_groups(groups, section, package);
+
+    if declaring.is_empty() {
+        UNMANAGED_GROUP.to_string()
+    } else {
+        declaring.join(", ")
+    }
+}
+
+/// A note on how many backends were left out of the review because they have no
+/// changes, if any.
+fn in_sync_summary(count: usize) -> Option<String> {
+    match count {
+        0 => None,
+        1 => Some("1 backend in sync".to_string()),
+        _ => Some(format!("{count} backends in sync")),
+    }
+}
+
+/// An empty table for the changes of a review.
+fn review_table() -> Table {
+    Table::new(&["backend", "package", "status", "options"])
+}
+
+/// The options of `package` from the sidecar files of the groups, as `key=value`
+/// pairs. Empty if no group declares options for the package.
+fn options_summary(groups: &Groups, section: &str, package: &Package) -> String {
+    let Some(options) = groups.iter().find_map(|group| {
+        group
+            .options
+            .get(section)
+            .and_then(|options| options.get(&package.name))
+    }) else {
+        return String::new();
+    };
+
+    let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(options) else {
+        return String::new();
+    };
+
+    fields
+        .iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(value) => format!("{key}={value}"),
+            value => format!("{key}={value}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 fn get_action_for_package(
     package: Package,
     groups: &Groups,
@@ -1,6 +1,11 @@
 use anyhow::Result;
 
+use crate::cache::invalidate;
+use crate::cmd::with_timeout_of;
 use crate::prelude::*;
+use crate::ui::{colored, Color, Table};
+
+use super::{options_summary, Change, UNMANAGED_GROUP};
 
 #[derive(Debug)]
 pub struct Strategy {
@@ -25,9 +30,19 @@ impl Strategy {
         }
     }
 
-    pub fn execute(self) -> Result<()> {
+    pub fn execute(self, config: &Config) -> Result<()> {
+        let backend = self.backend.clone();
+        with_timeout_of(&backend, config, || self.execute_inner(config))
+    }
+
+    fn execute_inner(self, config: &Config) -> Result<()> {
+        if !self.delete.is_empty() || !self.as_dependency.is_empty() {
+            invalidate(&self.backend);
+        }
+
         if !self.delete.is_empty() {
-            self.backend.remove_packages(&self.delete, false)?;
+            let noconfirm = config.no_confirm_for(&self.backend, false);
+            self.backend.remove_packages(&self.delete, noconfirm)?;
         }
 
         if !self.as_dependency.is_empty() {
@@ -41,6 +56,28 @@ impl Strategy {
         Ok(())
     }
 
+    /// The number of packages that are removed, including the dependencies that the
+    /// backend removes along with them.
+    pub fn removal_count(&self) -> usize {
+        if self.delete.is_empty() {
+            return 0;
+        }
+
+        let cascade = match self.backend.get_removal_cascade(&self.delete) {
+            Ok(cascade) => cascade.len(),
+            Err(error) => {
+                let backend = &self.backend;
+                log::warn!(
+                    backend:% = backend;
+                    "could not determine dependencies removed by {backend}: {error}"
+                );
+                0
+            }
+        };
+
+        self.delete.len() + cascade
+    }
+
     pub fn show(&self) {
         if self.nothing_to_do() {
             return;
@@ -51,7 +88,7 @@ impl Strategy {
         if !self.delete.is_empty() {
             println!("delete:");
             for p in &self.delete {
-                println!("  {p}");
+                println!("  {}", colored(&p.to_string(), Color::Red));
             }
         }
 
@@ -70,6 +107,59 @@ impl Strategy {
         }
     }
 
+    /// Print one line per package, like `[arch] vim: remove`, e.g. for logs.
+    pub fn show_terse(&self) {
+        let section = self.backend.backend_info().section;
+
+        for change in self.changes() {
+            println!("[{section}] {}: {}", change.package, change.status);
+        }
+    }
+
+    /// Add one row per package to a table with the columns backend, package, status
+    /// and options.
+    pub fn add_rows(&self, table: &mut Table, groups: &Groups) {
+        let section = self.backend.backend_info().section;
+
+        for change in self.changes() {
+            table.push(vec![
+                (section.to_string(), None),
+                (change.package.to_string(), None),
+                (change.status, Some(change.color)),
+                (options_summary(groups, section, change.package), None),
+            ]);
+        }
+    }
+
+    /// Every package with a description of what is done with it, and the color to
+    /// highlight that in. Packages assigned to a group are listed under that group,
+    /// all others under the unmanaged pseudo-group.
+    pub(super) fn changes(&self) -> impl Iterator<Item = Change<'_>> {
+        let section = self.backend.backend_info().section;
+        let change = move |package, status: &str, color, group: &str| Change {
+            group: group.to_string(),
+            section,
+            package,
+            status: status.to_string(),
+            color,
+        };
+
+        let delete = self
+            .delete
+            .iter()
+            .map(move |p| change(p, "remove", Color::Red, UNMANAGED_GROUP));
+        let as_dependency = self
+            .as_dependency
+            .iter()
+            .map(move |p| change(p, "as dependency", Color::Yellow, UNMANAGED_GROUP));
+        let assign_group = self
+            .assign_group
+            .iter()
+            .map(move |(p, g)| change(p, &format!("assign to {}", g.name), Color::Green, &g.name));
+
+        delete.chain(as_dependency).chain(assign_group)
+    }
+
     pub fn nothing_to_do(&self) -> bool {
         self.delete.is_empty() && self.as_dependency.is_empty() && self.assign_group.is_empty()
     }
@@ -0,0 +1,48 @@
+/*!
+Follow a single package through the computation of the managed, missing and
+unmanaged packages, to find out why it is or is not among them.
+
+Each step that concerns the package set with [`set_traced`] is logged by [`trace`].
+For every other package, nothing is logged.
+*/
+
+use std::sync::Mutex;
+
+use crate::prelude::*;
+
+/// The name of the package that is traced, if any.
+static TRACED: Mutex<Option<String>> = Mutex::new(None);
+
+/// Trace the package called `name` from now on, or no package if `None`.
+pub fn set_traced(name: Option<String>) {
+    *TRACED
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = name;
+}
+
+/// Check whether `package` is traced, either by its name or as written in the
+/// group file, e.g. `<owner>/<repo>`.
+pub fn is_traced(package: &Package) -> bool {
+    TRACED
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .as_ref()
+        .is_some_and(|name| *name == package.name || *name == package.to_string())
+}
+
+/// Get the traced packages among `packages`.
+pub fn traced(packages: &Packages) -> impl Iterator<Item = &Package> {
+    packages.iter().filter(|package| is_traced(package))
+}
+
+/// Log the `step` of the computation for `package` in the backend with the given
+/// `section` name, if the package is traced.
+pub fn trace(section: &str, package: &Package, step: impl FnOnce() -> String) {
+    if is_traced(package) {
+        log::info!(
+            backend = section, package:% = package;
+            "trace {package} [{section}]: {}",
+            step()
+        );
+    }
+}
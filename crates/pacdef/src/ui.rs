@@ -1,8 +1,191 @@
+use std::env;
 use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
 
 use anyhow::{Context, Result};
 use termios::*;
 
+use crate::cli::ColorChoice;
+
+/// The colors used to highlight changes in summaries.
+#[derive(Debug, Clone, Copy)]
+pub enum Color {
+    /// Packages to install.
+    Green,
+    /// Packages to remove, and errors.
+    Red,
+    /// Dependencies removed along with packages, and warnings.
+    Yellow,
+}
+
+/// When to color the output, as chosen by [`set_color`]. Defaults to `auto`.
+static COLOR_CHOICE: AtomicU8 = AtomicU8::new(AUTO);
+
+const AUTO: u8 = 0;
+const ALWAYS: u8 = 1;
+const NEVER: u8 = 2;
+
+/// Set when to color the output. With [`ColorChoice::Auto`], output is colored if it
+/// goes to a terminal and the `NO_COLOR` environment variable is not set.
+pub fn set_color(choice: ColorChoice) {
+    let value = match choice {
+        ColorChoice::Auto => AUTO,
+        ColorChoice::Always => ALWAYS,
+        ColorChoice::Never => NEVER,
+    };
+    COLOR_CHOICE.store(value, Ordering::Relaxed);
+}
+
+/// Whether output to the file descriptor `fd` should be colored.
+fn use_color(fd: libc::c_int) -> bool {
+    match COLOR_CHOICE.load(Ordering::Relaxed) {
+        ALWAYS => true,
+        NEVER => false,
+        _ => {
+            let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            !no_color && unsafe { libc::isatty(fd) } == 1
+        }
+    }
+}
+
+/// Wrap `text` in the ANSI escape codes for `color` if output to stdout is colored.
+pub fn colored(text: &str, color: Color) -> String {
+    // 1 is the file descriptor for stdout
+    paint(text, color, use_color(1))
+}
+
+/// Wrap `text` in the ANSI escape codes for `color` if output to stderr is colored.
+pub fn colored_stderr(text: &str, color: Color) -> String {
+    // 2 is the file descriptor for stderr
+    paint(text, color, use_color(2))
+}
+
+fn paint(text: &str, color: Color, enabled: bool) -> String {
+    if !enabled {
+        return text.to_string();
+    }
+
+    let code = match color {
+        Color::Green => 32,
+        Color::Red => 31,
+        Color::Yellow => 33,
+    };
+
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+/// The width of the terminal stdout is attached to, or `None` if stdout is not a
+/// terminal.
+fn terminal_width() -> Option<usize> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    // 1 is the file descriptor for stdout
+    let result = unsafe { libc::ioctl(1, libc::TIOCGWINSZ, &mut size) };
+    (result == 0 && size.ws_col > 0).then(|| size.ws_col.into())
+}
+
+/// A table whose columns are aligned when it is printed. Every cell may have a color,
+/// which is applied after aligning, so that the escape codes do not count towards
+/// the width of the column.
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<(String, Option<Color>)>>,
+}
+
+impl Table {
+    /// Create an empty table with the column names in `header`.
+    pub fn new(header: &[&str]) -> Self {
+        Self {
+            header: header.iter().map(ToString::to_string).collect(),
+            rows: vec![],
+        }
+    }
+
+    /// Add a row with one cell per column.
+    pub fn push(&mut self, row: Vec<(String, Option<Color>)>) {
+        self.rows.push(row);
+    }
+
+    /// Print the table to stdout. The last column is cut off at the width of the
+    /// terminal.
+    pub fn print(&self) {
+        for line in self.render(terminal_width()) {
+            println!("{line}");
+        }
+    }
+
+    fn render(&self, max_width: Option<usize>) -> Vec<String> {
+        let header = self
+            .header
+            .iter()
+            .map(|name| (name.clone(), None))
+            .collect();
+        let rows: Vec<&Vec<_>> = std::iter::once(&header).chain(&self.rows).collect();
+
+        let mut widths = vec![0; self.header.len()];
+        for row in &rows {
+            for (width, (text, _)) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(text.chars().count());
+            }
+        }
+
+        rows.into_iter()
+            .map(|row| {
+                let mut line = String::new();
+                let mut used = 0;
+
+                for (i, (text, color)) in row.iter().enumerate() {
+                    let is_last = i + 1 == row.len();
+                    let text = if is_last {
+                        truncate(text, max_width.map(|max| max.saturating_sub(used)))
+                    } else {
+                        format!("{text:<width$}  ", width = widths[i])
+                    };
+                    used += text.chars().count();
+
+                    match color {
+                        Some(color) => line.push_str(&colored(&text, *color)),
+                        None => line.push_str(&text),
+                    }
+                }
+
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+}
+
+/// Cut `text` off after `max_width` characters, ending in an ellipsis.
+fn truncate(text: &str, max_width: Option<usize>) -> String {
+    match max_width {
+        Some(max) if text.chars().count() > max => {
+            let mut result: String = text.chars().take(max.saturating_sub(1)).collect();
+            result.push('…');
+            result
+        }
+        _ => text.to_string(),
+    }
+}
+
+/// Ask the user to confirm a destructive action. Contrary to
+/// [`get_user_confirmation`], an empty reply means no.
+pub fn get_user_confirmation_default_no() -> Result<bool> {
+    print!("Continue? [y/N] ");
+    std::io::stdout().flush().context("flushing stdout")?;
+
+    let mut reply = String::new();
+    std::io::stdin()
+        .read_line(&mut reply)
+        .context("reading stdin")?;
+
+    Ok(reply.trim().eq_ignore_ascii_case("y") || reply.trim().eq_ignore_ascii_case("yes"))
+}
+
 pub fn get_user_confirmation() -> Result<bool> {
     print!("Continue? [Y/n] ");
     std::io::stdout().flush().context("flushing stdout")?;
@@ -47,3 +230,33 @@ pub fn read_single_char_from_terminal() -> Result<char> {
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_aligned() {
+        let mut table = Table::new(&["backend", "package", "status"]);
+        table.push(vec![
+            ("arch".into(), None),
+            ("neovim".into(), None),
+            ("install".into(), None),
+        ]);
+        table.push(vec![
+            ("rust".into(), None),
+            ("cargo-edit".into(), None),
+            ("remove".into(), None),
+        ]);
+
+        assert_eq!(
+            table.render(Some(24)),
+            [
+                "backend  package     st…",
+                "arch     neovim      in…",
+                "rust     cargo-edit  re…",
+            ]
+        );
+        assert_eq!(table.render(None)[1], "arch     neovim      install");
+    }
+}
@@ -0,0 +1,134 @@
+/*!
+Check whether a newer version of pacdef was released.
+
+The check is opt-in through `update_check` in the config, and runs at most once per
+day. The time of the last check is kept in the state dir. The check must never get
+in the way of the actual work, so every failure is only reported as debug info.
+*/
+
+use std::fs::{create_dir_all, read_to_string, write};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::cmd::run_command_for_stdout;
+use crate::env::should_print_debug_info;
+use crate::history::now;
+use crate::prelude::*;
+
+/// The crates.io API endpoint for pacdef, used if `update_check_url` is not set.
+const DEFAULT_URL: &str = "https://crates.io/api/v1/crates/pacdef";
+
+/// The minimum time between two checks, in seconds.
+const CHECK_INTERVAL: i64 = 24 * 60 * 60;
+
+/// The maximum time a check may take, in seconds.
+const TIMEOUT: &str = "3";
+
+const LAST_CHECK_FILE_NAME: &str = "last_update_check";
+
+/// Print a notice if a newer pacdef was released, if enabled in the `config` and
+/// the last check was more than a day ago.
+pub fn check_for_update(config: &Config) {
+    if !config.update_check {
+        return;
+    }
+
+    match find_newer_version(config) {
+        Ok(Some(latest)) => println!(
+            "pacdef {latest} is available, you have {}",
+            env!("CARGO_PKG_VERSION")
+        ),
+        Ok(None) => (),
+        Err(error) => {
+            if should_print_debug_info() {
+                println!("could not check for a newer pacdef: {error:#}");
+            }
+        }
+    }
+}
+
+/// Get the latest released version if it is newer than this one. Returns `None`
+/// without a request if the last check was less than a day ago.
+fn find_newer_version(config: &Config) -> Result<Option<String>> {
+    let state_dir = get_pacdef_state_dir()?;
+    let last_check_file = state_dir.join(LAST_CHECK_FILE_NAME);
+
+    let now = now()?;
+    let last_check = read_to_string(&last_check_file)
+        .ok()
+        .and_then(|content| content.trim().parse::<i64>().ok());
+
+    if last_check.is_some_and(|last_check| now - last_check < CHECK_INTERVAL) {
+        return Ok(None);
+    }
+
+    // record the check before it runs, so that being offline does not cause a request
+    // on every sync
+    create_dir_all(&state_dir).context("creating state dir")?;
+    write(&last_check_file, now.to_string()).context("saving time of the check")?;
+
+    let url = config.update_check_url.as_deref().unwrap_or(DEFAULT_URL);
+
+    let mut cmd = Command::new("curl");
+    cmd.args(["--silent", "--fail", "--location", "--max-time", TIMEOUT]);
+    cmd.args([
+        "--user-agent",
+        concat!("pacdef/", env!("CARGO_PKG_VERSION")),
+    ]);
+    cmd.arg(url);
+
+    let response = run_command_for_stdout(cmd).context("fetching latest version")?;
+    let latest = parse_latest_version(&response).context("reading latest version")?;
+
+    Ok(is_newer(&latest, env!("CARGO_PKG_VERSION")).then_some(latest))
+}
+
+/// Read the version from a crates.io API response, or else take the whole response
+/// as the version.
+fn parse_latest_version(response: &str) -> Option<String> {
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(response) {
+        return json["crate"]["max_stable_version"]
+            .as_str()
+            .map(ToString::to_string);
+    }
+
+    let version = response.trim();
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+/// Compare two versions by their numeric components. A pre-release suffix is
+/// ignored.
+fn is_newer(version: &str, current: &str) -> bool {
+    fn components(version: &str) -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map_while(|part| part.parse().ok())
+            .collect()
+    }
+
+    components(version) > components(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_version() {
+        let response = r#"{"crate":{"name":"pacdef","max_stable_version":"1.6.1"}}"#;
+        let latest = parse_latest_version(response).expect("response has a version");
+        assert_eq!(latest, "1.6.1");
+        assert_eq!(parse_latest_version("v2.0.0\n").as_deref(), Some("v2.0.0"));
+
+        assert!(is_newer(&latest, "1.6.0"));
+        assert!(is_newer("1.10.0", "1.9.3"));
+        assert!(is_newer("v2.0.0", "1.6.0"));
+        assert!(!is_newer("1.6.0", "1.6.0"));
+        assert!(!is_newer("1.6.0-rc1", "1.6.0"));
+    }
+}